@@ -0,0 +1,243 @@
+#!/usr/bin/env python3
+"""Generates the synthetic game data used by the golden image tests (src/golden.rs) into res/golden/data.
+
+The original game data isn't redistributable, so the fixture is made up from procedurally generated textures,
+maps and scripts in the freeware data layout. Everything else (fonts, locales, shaders) comes from the built-in
+data. The output is deterministic, rerun the script after changing it and rebless the reference images with
+DOUKUTSU_GOLDEN_BLESS=1.
+"""
+
+import json
+import os
+import shutil
+import struct
+import zlib
+
+OUT_DIR = os.path.join(os.path.dirname(os.path.abspath(__file__)), "data")
+
+NEW_GAME_STAGE = 13
+
+# Texture sizes of the freeware data, the engine scales textures by their size relative to these.
+TEXTURES = {
+    "ArmsImage": (256, 16),
+    "Arms": (320, 200),
+    "Bullet": (320, 176),
+    "Caret": (320, 240),
+    "Face": (288, 240),
+    "Fade": (256, 32),
+    "ItemImage": (256, 128),
+    "Loading": (64, 8),
+    "MyChar": (200, 64),
+    "StageImage": (256, 16),
+    "TextBox": (244, 144),
+    "Title": (320, 48),
+    "Npc/Npc0": (32, 32),
+    "Npc/NpcGuest": (320, 184),
+    "Npc/NpcSym": (320, 240),
+    "Stage/PrtCave": (256, 80),
+    "Stage/MpFg": (128, 128),
+    "Stage/MpMg": (128, 128),
+    "Stage/MpBg": (128, 128),
+}
+
+BACKGROUNDS = {
+    "bk0": (64, 64),
+    "bkBlue": (64, 64),
+    "bkMoon": (320, 240),
+    "bkMoon480fix": (480, 272),
+    "bkWater": (32, 48),
+}
+
+
+def png(width, height, pixels):
+    """Encodes 8-bit RGB pixel rows as a PNG file, black pixels are treated as transparent by the engine."""
+
+    def chunk(kind, data):
+        return struct.pack(">I", len(data)) + kind + data + struct.pack(">I", zlib.crc32(kind + data))
+
+    raw = b"".join(b"\0" + bytes(row) for row in pixels)
+    header = struct.pack(">IIBBBBB", width, height, 8, 2, 0, 0, 0)
+    return b"\x89PNG\r\n\x1a\n" + chunk(b"IHDR", header) + chunk(b"IDAT", zlib.compress(raw, 9)) + chunk(b"IEND", b"")
+
+
+def seed(name):
+    return zlib.crc32(name.encode()) & 0xFFFFFF
+
+
+def color(base, index):
+    r, g, b = (base >> 16) & 0xFF, (base >> 8) & 0xFF, base & 0xFF
+    return ((r + index * 47) % 192 + 48, (g + index * 89) % 192 + 48, (b + index * 131) % 192 + 48)
+
+
+def sprite_sheet(name, width, height, cell):
+    """A grid of outlined cells with a diagonal each, so misplaced or flipped sprites are visible in the output."""
+    base = seed(name)
+    rows = []
+    for y in range(height):
+        row = []
+        for x in range(width):
+            cx, cy = x % cell, y % cell
+            index = (y // cell) * (width // cell + 1) + x // cell
+            if cx == 0 or cy == 0 or cx == cell - 1 or cy == cell - 1:
+                row.extend(color(base, index))
+            elif cx == cy or (cx < cell // 2 and cy > cell // 2 + cx // 2):
+                row.extend(color(base, index + 7))
+            else:
+                row.extend((0, 0, 0))
+        rows.append(row)
+    return rows
+
+
+def background(name, width, height):
+    """An opaque gradient with a stripe pattern, so the scrolling of the background is visible."""
+    base = seed(name)
+    rows = []
+    for y in range(height):
+        row = []
+        for x in range(width):
+            r, g, b = color(base, (y * 4) // height)
+            if (x + y) % 16 < 2:
+                r, g, b = r // 2 + 8, g // 2 + 8, b // 2 + 8
+            row.extend((r, g, b))
+        rows.append(row)
+    return rows
+
+
+def pxm(width, height, tile):
+    data = b"PXM\x10" + struct.pack("<HH", width, height)
+    return data + bytes(tile(x, y) for y in range(height) for x in range(width))
+
+
+def cave_tile(width, height):
+    """Solid walls around the map with a floor and a few platforms, the tileset rows are empty, solid,
+    foreground, background and water tiles."""
+
+    def tile(x, y):
+        if x < 2 or y < 2 or x >= width - 2 or y >= height - 2 or y == 10 and 4 <= x < 24:
+            return 16 + (x + y) % 4
+        if y == 6 and 14 <= x < 20 or x == 30 and 12 <= y < 20:
+            return 16 + (x * 3 + y) % 16
+        if y >= height - 6 and x % 7 < 2:
+            return 32 + x % 16
+        if (x * 7 + y * 3) % 11 == 0:
+            return 48 + (x + y) % 16
+        if y >= height - 4:
+            return 64 + x % 4
+        return 0
+
+    return tile
+
+
+def cave_pxa():
+    return bytes([0x00] * 16 + [0x41] * 16 + [0x40] * 16 + [0x00] * 16 + [0x02] * 16 + [0x00] * 176)
+
+
+def pxe(npcs):
+    data = b"PXE\x00" + struct.pack("<I", len(npcs))
+    for x, y, flag, event, npc_type, flags in npcs:
+        data += struct.pack("<hhHHHH", x, y, flag, event, npc_type, flags)
+    return data
+
+
+def pxpack(name, width, height):
+    def string(text):
+        raw = text.encode("shift_jis")
+        return bytes([len(raw)]) + raw
+
+    def layer(w, h, tile):
+        data = b"pxMAP01\0" + struct.pack("<HH", w, h)
+        if w * h == 0:
+            return data
+        return data + b"\0" + bytes(tile(x, y) for y in range(h) for x in range(w))
+
+    data = b"PXPACK121127a**\0" + string(name)
+    data += b"".join(string("") for _ in range(5))
+    data += struct.pack("<HHB", 0, 0, 0) + bytes((24, 16, 40))
+    for tileset, scroll in (("MpFg", 0), ("MpMg", 1), ("MpBg", 2)):
+        data += string(tileset) + bytes((0, scroll))
+
+    data += layer(width, height, lambda x, y: 1 if x in (0, width - 1) or y in (0, height - 1) else 0)
+    data += layer(width // 2, height // 2, lambda x, y: (x + y) % 16 + 16)
+    data += layer(width // 4, height // 4, lambda x, y: (x * 5 + y) % 32 + 32)
+    return data
+
+
+def tsc(text):
+    """Encrypts the script with the key in the middle byte, as the freeware data expects."""
+    data = bytearray(text.replace("\n", "\r\n").encode("ascii"))
+    half = len(data) // 2
+    key = data[half] or 7
+    for idx in range(len(data)):
+        if idx != half:
+            data[idx] = (data[idx] + key) & 0xFF
+    return bytes(data)
+
+
+def npc_table(count):
+    """A table of NPCs without sounds, with a 16x16 hitbox and display rect."""
+    data = b"".join(struct.pack("<H", 0) for _ in range(count))
+    data += b"".join(struct.pack("<H", 1) for _ in range(count))
+    data += bytes(count) * 4
+    data += b"".join(struct.pack("<I", 0) for _ in range(count)) * 2
+    data += bytes((4, 4, 4, 4)) * count
+    data += bytes((8, 8, 8, 8)) * count
+    return data
+
+
+def stage(name, map_name, tileset, bk, bk_type, npc1="0", npc2="0"):
+    return {
+        "name": name,
+        "map": map_name,
+        "tileset": tileset,
+        "background": bk,
+        "background_type": bk_type,
+        "npc1": npc1,
+        "npc2": npc2,
+    }
+
+
+def main():
+    files = {}
+
+    for name, (width, height) in TEXTURES.items():
+        cell = 8 if name.startswith("Stage/Mp") or height < 16 else 16
+        files[name + ".png"] = png(width, height, sprite_sheet(name, width, height, cell))
+
+    for name, (width, height) in BACKGROUNDS.items():
+        files[name + ".png"] = png(width, height, background(name, width, height))
+
+    stages = [stage("Null", "0", "0", "bk0", 0) for _ in range(NEW_GAME_STAGE)]
+    stages.append(stage("Start Point", "Start", "Cave", "bkBlue", 1, "Guest"))
+    stages.append(stage("Reservoir", "Water", "Cave", "bkWater", 3))
+    stages.append(stage("Pack Test", "Pack", "Cave", "bk0", 0))
+    files["stage.json"] = json.dumps(stages, indent=2).encode() + b"\n"
+
+    files["Stage/Start.pxm"] = pxm(40, 30, cave_tile(40, 30))
+    files["Stage/Start.pxe"] = pxe([])
+    files["Stage/Water.pxm"] = pxm(40, 30, cave_tile(40, 30))
+    files["Stage/Water.pxe"] = pxe([])
+    files["Stage/Pack.pxpack"] = pxpack("Pack Test", 80, 60)
+    files["Stage/Pack.pxe"] = pxe([])
+    files["Stage/PrtCave.pxa"] = cave_pxa()
+    files["Stage/MpFg.pxa"] = bytes([0x41, 0x41] + [0] * 254)
+
+    for map_name in ("Start", "Water", "Pack"):
+        files["Stage/%s.tsc" % map_name] = tsc("#0090\n<MNA<FAI0000<END\n#0200\n<END\n")
+
+    files["Head.tsc"] = tsc("#0000\n<END\n")
+    files["ArmsItem.tsc"] = tsc("#0000\n<END\n#5000\n<END\n#6000\n<END\n")
+    files["StageSelect.tsc"] = tsc("#1000\n<END\n")
+    files["npc.tbl"] = npc_table(361)
+
+    if os.path.isdir(OUT_DIR):
+        shutil.rmtree(OUT_DIR)
+
+    for path, data in sorted(files.items()):
+        full_path = os.path.join(OUT_DIR, path)
+        os.makedirs(os.path.dirname(full_path), exist_ok=True)
+        with open(full_path, "wb") as file:
+            file.write(data)
+
+
+if __name__ == "__main__":
+    main()
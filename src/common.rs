@@ -133,7 +133,7 @@ bitfield! {
 }
 
 bitfield! {
-    #[derive(Clone, Copy)]
+    #[derive(Clone, Copy, Serialize, Deserialize)]
     #[repr(C)]
     pub struct BulletFlag(u8);
     impl Debug;
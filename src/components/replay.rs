@@ -1,26 +1,373 @@
-use std::io::{Cursor, Read};
+use std::io::{Cursor, Read, Write};
 
 use byteorder::{LE, ReadBytesExt, WriteBytesExt};
+use num_traits::FromPrimitive;
 
 use crate::entity::GameEntity;
 use crate::framework::context::Context;
-use crate::framework::error::GameResult;
+use crate::framework::error::{GameError, GameResult};
 use crate::framework::filesystem;
 use crate::framework::keyboard::ScanCode;
 use crate::framework::vfs::OpenOptions;
 use crate::game::frame::Frame;
-use crate::game::shared_game_state::{ReplayKind, ReplayState, SharedGameState};
-use crate::input::replay_player_controller::{KeyState, ReplayController};
 use crate::game::player::Player;
+use crate::game::profile::GameProfile;
+use crate::game::scripting::tsc::text_script::TextScriptExecutionState;
+use crate::game::shared_game_state::{
+    GameDifficulty, PlayerCount, ReplayKind, ReplayState, SharedGameState, TimingMode,
+};
 use crate::graphics::font::Font;
+use crate::input::player_controller::PlayerController;
+use crate::input::replay_player_controller::{KeyState, ReplayController};
+use crate::scene::game_scene::GameScene;
+
+/// Latest version of the replay format.
+/// - 0: RNG seed followed by the raw key stream.
+/// - 1: metadata header, stage keyframes and the key stream.
+/// - 2: adds state checksums used for desync detection.
+/// - 3: adds the key stream of the second player.
+/// - 4: adds the list of loaded mods.
+pub const REPLAY_VERSION: u16 = 4;
+
+/// Directory in the user dir where session replays recorded from the pause menu are stored.
+pub const SESSION_REPLAY_DIR: &str = "/replays";
+
+/// Amount of ticks between two state checksums.
+pub const CHECKSUM_INTERVAL: u32 = 50;
+
+/// Minimum amount of ticks between two keyframes captured in the middle of a stage.
+pub const KEYFRAME_INTERVAL: u32 = CHECKSUM_INTERVAL * 60;
+
+/// Upper bound for the size of a keyframe's profile snapshot, anything larger is treated as a corrupted file.
+const MAX_KEYFRAME_PROFILE_SIZE: usize = 0x10000;
+
+/// Metadata stored at the start of a replay file, used to check if it can be played back.
+#[derive(Clone, Debug)]
+pub struct ReplayHeader {
+    pub version: u16,
+    pub engine_version: String,
+    pub mod_path: Option<String>,
+    /// Data directories of the challenge, enabled mods and their dependencies the replay was recorded with.
+    pub mod_paths: Vec<String>,
+    pub stage_id: u32,
+    pub difficulty: GameDifficulty,
+    pub timing_mode: TimingMode,
+    pub player_count: PlayerCount,
+    pub rng_seed: u64,
+}
+
+impl ReplayHeader {
+    pub fn new() -> ReplayHeader {
+        ReplayHeader {
+            version: REPLAY_VERSION,
+            engine_version: env!("CARGO_PKG_VERSION").to_owned(),
+            mod_path: None,
+            mod_paths: Vec::new(),
+            stage_id: 0,
+            difficulty: GameDifficulty::Normal,
+            timing_mode: TimingMode::_50Hz,
+            player_count: PlayerCount::One,
+            rng_seed: 0,
+        }
+    }
+
+    /// Checks whether this replay can be played back with currently loaded game data.
+    pub fn check_compatibility(&self, state: &SharedGameState) -> GameResult {
+        if self.version > REPLAY_VERSION {
+            return Err(GameError::InvalidValue(format!(
+                "Replay version {} is newer than the supported version {}.",
+                self.version, REPLAY_VERSION
+            )));
+        }
+
+        if self.version == 0 {
+            // legacy replays carry no metadata
+            return Ok(());
+        }
+
+        if self.mod_path != state.mod_path {
+            return Err(GameError::InvalidValue(format!(
+                "Replay was recorded with mod {:?}, but {:?} is loaded.",
+                self.mod_path, state.mod_path
+            )));
+        }
+
+        if let Some(path) = self.mod_paths.iter().find(|path| {
+            self.mod_path.as_ref() != Some(*path)
+                && !state.mod_list.mods.iter().any(|mod_info| mod_info.valid && &mod_info.path == *path)
+        }) {
+            return Err(GameError::InvalidValue(format!(
+                "Replay was recorded with mod {}, which isn't available.",
+                path
+            )));
+        }
+
+        if self.stage_id as usize >= state.stages.len() {
+            return Err(GameError::InvalidValue(format!("Replay starts on a missing stage {}.", self.stage_id)));
+        }
+
+        if self.engine_version != env!("CARGO_PKG_VERSION") {
+            log::warn!(
+                "Replay was recorded with engine version {}, playback might desync.",
+                self.engine_version
+            );
+        }
+
+        if self.timing_mode != state.settings.timing_mode {
+            log::warn!("Replay was recorded with a different timing mode.");
+        }
+
+        Ok(())
+    }
+
+    fn write_to<W: Write>(&self, mut data: W) -> GameResult {
+        data.write_u16::<LE>(self.version)?;
+        write_string(&mut data, &self.engine_version)?;
+        write_string(&mut data, self.mod_path.as_deref().unwrap_or(""))?;
+        data.write_u32::<LE>(self.stage_id)?;
+        data.write_u8(self.difficulty as u8)?;
+        data.write_u8(match self.timing_mode {
+            TimingMode::_50Hz => 0,
+            TimingMode::_60Hz => 1,
+            TimingMode::FrameSynchronized => 2,
+        })?;
+        data.write_u8(self.player_count as u8)?;
+        data.write_u64::<LE>(self.rng_seed)?;
+        data.write_u16::<LE>(self.mod_paths.len() as u16)?;
+        for path in &self.mod_paths {
+            write_string(&mut data, path)?;
+        }
+
+        Ok(())
+    }
+
+    fn read_from<R: Read>(mut data: R) -> GameResult<ReplayHeader> {
+        let mut header = ReplayHeader::new();
+        header.version = data.read_u16::<LE>()?;
+
+        if header.version == 0 {
+            header.engine_version = String::new();
+            header.rng_seed = data.read_u64::<LE>()?;
+
+            return Ok(header);
+        }
+
+        if header.version > REPLAY_VERSION {
+            return Ok(header);
+        }
+
+        header.engine_version = read_string(&mut data)?;
+        let mod_path = read_string(&mut data)?;
+        header.mod_path = if mod_path.is_empty() { None } else { Some(mod_path) };
+        header.stage_id = data.read_u32::<LE>()?;
+        header.difficulty = GameDifficulty::from_primitive(data.read_u8()?);
+        header.timing_mode = match data.read_u8()? {
+            1 => TimingMode::_60Hz,
+            2 => TimingMode::FrameSynchronized,
+            _ => TimingMode::_50Hz,
+        };
+        header.player_count = FromPrimitive::from_u8(data.read_u8()?).unwrap_or(PlayerCount::One);
+        header.rng_seed = data.read_u64::<LE>()?;
+
+        if header.version >= 4 {
+            let count = data.read_u16::<LE>()?;
+            for _ in 0..count {
+                header.mod_paths.push(read_string(&mut data)?);
+            }
+        }
+
+        Ok(header)
+    }
+}
+
+/// A checkpoint the replay can be seeked to, captured every time a stage is entered while recording
+/// and every `KEYFRAME_INTERVAL` ticks while no event is running.
+#[derive(Clone, Debug)]
+pub struct ReplayKeyframe {
+    /// Index into the key stream at which the stage was entered.
+    pub tick: u32,
+    /// State of the game RNG before the stage has been initialized.
+    pub rng_state: u64,
+    /// Event that was running when the stage was entered, 0 if none.
+    pub event_num: u16,
+    /// Profile.dat-formatted snapshot of the game state.
+    pub profile: Vec<u8>,
+}
+
+impl ReplayKeyframe {
+    pub fn capture(state: &mut SharedGameState, game_scene: &mut GameScene, tick: u32) -> GameResult<ReplayKeyframe> {
+        let rng_state = state.game_rng.dump_state();
+        let event_num = match state.textscript_vm.state {
+            TextScriptExecutionState::Running(event_num, _) => event_num,
+            _ => 0,
+        };
+
+        let mut profile = Vec::new();
+        GameProfile::dump(state, game_scene, None).write_save(&mut profile)?;
+
+        Ok(ReplayKeyframe { tick, rng_state, event_num, profile })
+    }
+
+    fn write_to<W: Write>(&self, mut data: W) -> GameResult {
+        data.write_u32::<LE>(self.tick)?;
+        data.write_u64::<LE>(self.rng_state)?;
+        data.write_u16::<LE>(self.event_num)?;
+        data.write_u32::<LE>(self.profile.len() as u32)?;
+        data.write_all(&self.profile)?;
+
+        Ok(())
+    }
+
+    fn read_from<R: Read>(mut data: R) -> GameResult<ReplayKeyframe> {
+        let tick = data.read_u32::<LE>()?;
+        let rng_state = data.read_u64::<LE>()?;
+        let event_num = data.read_u16::<LE>()?;
+        let len = data.read_u32::<LE>()? as usize;
+
+        if len > MAX_KEYFRAME_PROFILE_SIZE {
+            return Err(GameError::InvalidValue(format!("Replay keyframe profile is too large ({} bytes).", len)));
+        }
+
+        let mut profile = Vec::with_capacity(len);
+        data.take(len as u64).read_to_end(&mut profile)?;
+        if profile.len() != len {
+            return Err(GameError::InvalidValue("Replay keyframe profile is truncated.".to_owned()));
+        }
+
+        Ok(ReplayKeyframe { tick, rng_state, event_num, profile })
+    }
+}
+
+/// Cheap fingerprint of the simulation state, recorded every `CHECKSUM_INTERVAL` ticks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReplayChecksum {
+    pub tick: u32,
+    /// Hash of both players' positions.
+    pub position: u32,
+    /// Player 1 HP in the lower half, player 2 HP in the upper half.
+    pub life: u32,
+    /// Game RNG state folded to 32 bits.
+    pub rng: u32,
+    pub npc_count: u16,
+}
+
+impl ReplayChecksum {
+    pub fn capture(state: &SharedGameState, game_scene: &GameScene, tick: u32) -> ReplayChecksum {
+        // FNV-1a
+        let mut position = 0x811c9dc5u32;
+        for value in [game_scene.player1.x, game_scene.player1.y, game_scene.player2.x, game_scene.player2.y] {
+            for byte in value.to_le_bytes() {
+                position = (position ^ byte as u32).wrapping_mul(0x01000193);
+            }
+        }
+
+        let life = game_scene.player1.life as u32 | (game_scene.player2.life as u32) << 16;
+        let rng_state = state.game_rng.dump_state();
+        let rng = (rng_state ^ (rng_state >> 32)) as u32;
+        let npc_count = game_scene.npc_list.iter_alive().count() as u16;
+
+        ReplayChecksum { tick, position, life, rng, npc_count }
+    }
+
+    /// Returns names of the state parts that differ between two checksums.
+    pub fn diff(&self, other: &ReplayChecksum) -> Vec<&'static str> {
+        let mut parts = Vec::new();
+
+        if self.position != other.position {
+            parts.push("player position");
+        }
+        if self.life != other.life {
+            parts.push("player HP");
+        }
+        if self.rng != other.rng {
+            parts.push("game RNG");
+        }
+        if self.npc_count != other.npc_count {
+            parts.push("NPC count");
+        }
+
+        parts
+    }
+
+    fn write_to<W: Write>(&self, mut data: W) -> GameResult {
+        data.write_u32::<LE>(self.tick)?;
+        data.write_u32::<LE>(self.position)?;
+        data.write_u32::<LE>(self.life)?;
+        data.write_u32::<LE>(self.rng)?;
+        data.write_u16::<LE>(self.npc_count)?;
+
+        Ok(())
+    }
+
+    fn read_from<R: Read>(mut data: R) -> GameResult<ReplayChecksum> {
+        Ok(ReplayChecksum {
+            tick: data.read_u32::<LE>()?,
+            position: data.read_u32::<LE>()?,
+            life: data.read_u32::<LE>()?,
+            rng: data.read_u32::<LE>()?,
+            npc_count: data.read_u16::<LE>()?,
+        })
+    }
+}
+
+/// First mismatch found between recorded and played back state.
+#[derive(Clone, Debug)]
+pub struct ReplayDesync {
+    pub tick: u32,
+    pub parts: Vec<&'static str>,
+}
+
+fn write_string<W: Write>(data: &mut W, value: &str) -> GameResult {
+    if value.len() > u16::MAX as usize {
+        return Err(GameError::InvalidValue(format!(
+            "String is too long to be stored in a replay ({} bytes).",
+            value.len()
+        )));
+    }
+
+    data.write_u16::<LE>(value.len() as u16)?;
+    data.write_all(value.as_bytes())?;
+
+    Ok(())
+}
+
+fn read_string<R: Read>(data: &mut R) -> GameResult<String> {
+    let len = data.read_u16::<LE>()? as usize;
+    let mut buf = vec![0u8; len];
+    data.read_exact(&mut buf)?;
+
+    Ok(String::from_utf8(buf)?)
+}
+
+/// Packs the current state of a player controller into the `KeyState` bitfield.
+fn encode_inputs(controller: &dyn PlayerController) -> u16 {
+    controller.move_left() as u16
+        + ((controller.move_right() as u16) << 1)
+        + ((controller.move_up() as u16) << 2)
+        + ((controller.move_down() as u16) << 3)
+        + ((controller.trigger_map() as u16) << 4)
+        + ((controller.trigger_inventory() as u16) << 5)
+        + (((controller.jump() || controller.trigger_menu_ok()) as u16) << 6)
+        + (((controller.shoot() || controller.trigger_menu_back()) as u16) << 7)
+        + ((controller.next_weapon() as u16) << 8)
+        + ((controller.prev_weapon() as u16) << 9)
+        + ((controller.trigger_menu_ok() as u16) << 11)
+        + ((controller.skip() as u16) << 12)
+        + ((controller.strafe() as u16) << 13)
+}
 
 #[derive(Clone)]
 pub struct Replay {
-    replay_version: u16,
-    keylist: Vec<u16>,
-    last_input: KeyState,
-    rng_seed: u64,
-    pub controller: ReplayController,
+    pub header: ReplayHeader,
+    pub keyframes: Vec<ReplayKeyframe>,
+    pub checksums: Vec<ReplayChecksum>,
+    pub desync: Option<ReplayDesync>,
+    /// Whether this is a time attack replay or a session recorded from the pause menu.
+    pub kind: ReplayKind,
+    /// Key streams of player 1 and player 2, the latter is empty if player 2 never pressed anything.
+    keylists: [Vec<u16>; 2],
+    last_inputs: [KeyState; 2],
+    pub controllers: [ReplayController; 2],
     tick: usize,
     resume_tick: usize,
     is_active: bool,
@@ -29,24 +376,86 @@ pub struct Replay {
 impl Replay {
     pub fn new() -> Replay {
         Replay {
-            replay_version: 0,
-            keylist: Vec::new(),
-            last_input: KeyState(0),
-            rng_seed: 0,
-            controller: ReplayController::new(),
+            header: ReplayHeader::new(),
+            keyframes: Vec::new(),
+            checksums: Vec::new(),
+            desync: None,
+            kind: ReplayKind::Last,
+            keylists: [Vec::new(), Vec::new()],
+            last_inputs: [KeyState(0); 2],
+            controllers: [ReplayController::new(); 2],
             tick: 0,
             resume_tick: 0,
             is_active: false,
         }
     }
 
-    pub fn initialize_recording(&mut self, state: &mut SharedGameState) {
+    pub fn initialize_recording(&mut self, state: &mut SharedGameState, stage_id: usize) {
         if !self.is_active {
-            self.rng_seed = state.game_rng.dump_state();
+            self.header = ReplayHeader::new();
+            self.header.mod_path = state.mod_path.clone();
+            self.header.mod_paths = state.mod_paths();
+            self.header.stage_id = stage_id as u32;
+            self.header.difficulty = state.difficulty;
+            self.header.timing_mode = state.settings.timing_mode;
+            self.header.player_count = state.player_count;
+            self.header.rng_seed = state.game_rng.dump_state();
             self.is_active = true;
         }
     }
 
+    /// Amount of inputs recorded so far.
+    pub fn recorded_ticks(&self) -> u32 {
+        self.keylists[0].len() as u32
+    }
+
+    /// Current position in the key stream, the amount of recorded inputs while recording.
+    pub fn current_tick(&self, state: &SharedGameState) -> u32 {
+        match state.replay_state {
+            ReplayState::Recording => self.keylists[0].len() as u32,
+            _ => self.tick as u32,
+        }
+    }
+
+    /// Whether a mid-stage keyframe should be captured at current tick while recording.
+    pub fn keyframe_due(&self, state: &SharedGameState) -> bool {
+        if state.replay_state != ReplayState::Recording
+            || !matches!(state.textscript_vm.state, TextScriptExecutionState::Ended)
+        {
+            return false;
+        }
+
+        let tick = self.recorded_ticks();
+        self.keyframes.last().map_or(true, |last| tick >= last.tick.saturating_add(KEYFRAME_INTERVAL))
+    }
+
+    /// Stores the checksum while recording, or compares it against the recorded one during playback.
+    pub fn process_checksum(&mut self, state: &SharedGameState, checksum: ReplayChecksum) {
+        if checksum.tick == 0 || checksum.tick % CHECKSUM_INTERVAL != 0 {
+            return;
+        }
+
+        match state.replay_state {
+            ReplayState::Recording => {
+                if self.checksums.last().map_or(true, |last| last.tick < checksum.tick) {
+                    self.checksums.push(checksum);
+                }
+            }
+            ReplayState::Playback(_) if self.desync.is_none() => {
+                let Ok(idx) = self.checksums.binary_search_by_key(&checksum.tick, |c| c.tick) else {
+                    return;
+                };
+
+                let parts = self.checksums[idx].diff(&checksum);
+                if !parts.is_empty() {
+                    log::error!("Replay desynced at tick {}, mismatched state: {}.", checksum.tick, parts.join(", "));
+                    self.desync = Some(ReplayDesync { tick: checksum.tick, parts });
+                }
+            }
+            _ => {}
+        }
+    }
+
     pub fn stop_recording(
         &mut self,
         state: &mut SharedGameState,
@@ -64,6 +473,22 @@ impl Replay {
         Ok(())
     }
 
+    /// Stops recording a session and stores it in `SESSION_REPLAY_DIR`, returns the path of the written file.
+    pub fn stop_session_recording(&mut self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult<String> {
+        state.replay_state = ReplayState::None;
+        self.is_active = false;
+
+        filesystem::user_create_dir(ctx, SESSION_REPLAY_DIR)?;
+
+        let path = format!("{}/{}.rep", SESSION_REPLAY_DIR, chrono::Local::now().format("%Y-%m-%d_%H-%M-%S"));
+        let file = filesystem::open_options(ctx, &path, OpenOptions::new().write(true).create(true).truncate(true))?;
+        self.write_to(file)?;
+
+        log::info!("Saved session replay to {}.", path);
+
+        Ok(path)
+    }
+
     pub fn initialize_playback(
         &mut self,
         state: &mut SharedGameState,
@@ -71,92 +496,209 @@ impl Replay {
         replay_kind: ReplayKind,
     ) -> GameResult {
         if !self.is_active {
-            state.replay_state = ReplayState::Playback(replay_kind);
             self.read_replay(state, ctx, replay_kind)?;
-            state.game_rng.load_state(self.rng_seed);
-            self.is_active = true;
+            self.start_playback(state, replay_kind)?;
         }
         Ok(())
     }
 
+    /// Starts playing back already loaded replay data from the beginning.
+    pub fn start_playback(&mut self, state: &mut SharedGameState, replay_kind: ReplayKind) -> GameResult {
+        self.header.check_compatibility(state)?;
+        state.replay_state = ReplayState::Playback(replay_kind);
+        state.game_rng.load_state(self.header.rng_seed);
+        self.tick = 0;
+        self.resume_tick = 0;
+        self.last_inputs = [KeyState(0); 2];
+        self.desync = None;
+        self.is_active = true;
+
+        Ok(())
+    }
+
+    /// Loads replay data coming from outside of the user directory, used by the headless runner.
+    pub fn load_from<R: Read>(&mut self, data: R) -> GameResult {
+        self.read_from(data)
+    }
+
+    /// Reads only the metadata of a stored replay, without the key stream.
+    pub fn read_header(
+        state: &SharedGameState,
+        ctx: &mut Context,
+        replay_kind: ReplayKind,
+    ) -> GameResult<ReplayHeader> {
+        let file = filesystem::user_open(ctx, [state.get_rec_filename(), replay_kind.get_suffix()].join(""))?;
+        ReplayHeader::read_from(file)
+    }
+
+    /// Creates a stage scene positioned at given keyframe, with this replay set up to continue playback from there.
+    pub fn seek(&self, state: &mut SharedGameState, ctx: &mut Context, keyframe_idx: usize) -> GameResult<GameScene> {
+        let keyframe = self.keyframes.get(keyframe_idx).ok_or_else(|| {
+            GameError::InvalidValue(format!(
+                "Replay has no keyframe {} ({} total).",
+                keyframe_idx,
+                self.keyframes.len()
+            ))
+        })?;
+        let profile = GameProfile::load_from_save(Cursor::new(&keyframe.profile))?;
+
+        state.reset();
+        state.player_count = self.header.player_count;
+
+        let mut game_scene = GameScene::new(state, ctx, profile.current_map as usize)?;
+        profile.apply(state, &mut game_scene, ctx);
+
+        if keyframe.event_num != 0 {
+            state.textscript_vm.start_script(keyframe.event_num);
+        }
+        state.game_rng.load_state(keyframe.rng_state);
+        state.replay_state = ReplayState::Playback(self.kind);
+
+        let tick = keyframe.tick as usize;
+        game_scene.replay = self.clone();
+        game_scene.replay.tick = tick;
+        game_scene.replay.resume_tick = tick;
+        for (last_input, keylist) in game_scene.replay.last_inputs.iter_mut().zip(self.keylists.iter()) {
+            *last_input = KeyState(if tick > 0 { *keylist.get(tick - 1).unwrap_or(&0) } else { 0 });
+        }
+        game_scene.replay.desync = None;
+        game_scene.replay.is_active = true;
+
+        Ok(game_scene)
+    }
+
     fn write_replay(&mut self, state: &mut SharedGameState, ctx: &mut Context, replay_kind: ReplayKind) -> GameResult {
-        if let Ok(mut file) = filesystem::open_options(
+        if let Ok(file) = filesystem::open_options(
             ctx,
             [state.get_rec_filename(), replay_kind.get_suffix()].join(""),
             OpenOptions::new().write(true).create(true),
         ) {
-            file.write_u16::<LE>(0)?; // Space for versioning replay files
-            file.write_u64::<LE>(self.rng_seed)?;
-            for input in &self.keylist {
+            self.write_to(file)?;
+        }
+        Ok(())
+    }
+
+    fn write_to<W: Write>(&self, mut file: W) -> GameResult {
+        let mut header = self.header.clone();
+        header.version = REPLAY_VERSION;
+        header.write_to(&mut file)?;
+
+        file.write_u32::<LE>(self.keyframes.len() as u32)?;
+        for keyframe in &self.keyframes {
+            keyframe.write_to(&mut file)?;
+        }
+
+        file.write_u32::<LE>(self.checksums.len() as u32)?;
+        for checksum in &self.checksums {
+            checksum.write_to(&mut file)?;
+        }
+
+        for (idx, keylist) in self.keylists.iter().enumerate() {
+            // player 2 stream is only stored if they actually played
+            let count = if idx == 0 || keylist.iter().any(|&input| input != 0) { keylist.len() } else { 0 };
+
+            file.write_u32::<LE>(count as u32)?;
+            for input in &keylist[..count] {
                 file.write_u16::<LE>(*input)?;
             }
         }
+
         Ok(())
     }
 
     fn read_replay(&mut self, state: &mut SharedGameState, ctx: &mut Context, replay_kind: ReplayKind) -> GameResult {
-        if let Ok(mut file) = filesystem::user_open(ctx, [state.get_rec_filename(), replay_kind.get_suffix()].join(""))
-        {
-            self.replay_version = file.read_u16::<LE>()?;
-            self.rng_seed = file.read_u64::<LE>()?;
+        if let Ok(file) = filesystem::user_open(ctx, [state.get_rec_filename(), replay_kind.get_suffix()].join("")) {
+            self.read_from(file)?;
+        }
+        Ok(())
+    }
+
+    fn read_from<R: Read>(&mut self, mut file: R) -> GameResult {
+        self.header = ReplayHeader::read_from(&mut file)?;
+        self.keyframes.clear();
+        self.checksums.clear();
+        self.keylists = [Vec::new(), Vec::new()];
+        self.desync = None;
+
+        if self.header.version > REPLAY_VERSION {
+            return Ok(());
+        }
 
+        if self.header.version == 0 {
             let mut data = Vec::new();
             file.read_to_end(&mut data)?;
 
-            let count = data.len() / 2;
-            let mut inputs = Vec::new();
-            let mut f = Cursor::new(data);
+            self.keylists[0] = data.chunks_exact(2).map(|input| u16::from_le_bytes([input[0], input[1]])).collect();
 
-            for _ in 0..count {
-                inputs.push(f.read_u16::<LE>()?);
+            return Ok(());
+        }
+
+        let keyframe_count = file.read_u32::<LE>()?;
+        for _ in 0..keyframe_count {
+            self.keyframes.push(ReplayKeyframe::read_from(&mut file)?);
+        }
+
+        if self.header.version >= 2 {
+            let checksum_count = file.read_u32::<LE>()?;
+            for _ in 0..checksum_count {
+                self.checksums.push(ReplayChecksum::read_from(&mut file)?);
             }
+        }
 
-            self.keylist = inputs;
+        let streams = if self.header.version >= 3 { 2 } else { 1 };
+        for keylist in self.keylists.iter_mut().take(streams) {
+            let count = file.read_u32::<LE>()?;
+            for _ in 0..count {
+                keylist.push(file.read_u16::<LE>()?);
+            }
         }
+
         Ok(())
     }
 }
 
-impl GameEntity<(&mut Context, &mut Player)> for Replay {
-    fn tick(&mut self, state: &mut SharedGameState, (ctx, player): (&mut Context, &mut Player)) -> GameResult {
+impl GameEntity<(&mut Context, &mut Player, &mut Player)> for Replay {
+    fn tick(
+        &mut self,
+        state: &mut SharedGameState,
+        (ctx, player1, player2): (&mut Context, &mut Player, &mut Player),
+    ) -> GameResult {
         match state.replay_state {
             ReplayState::Recording => {
-                // This mimics the KeyState bitfield
-                let inputs = player.controller.move_left() as u16
-                    + ((player.controller.move_right() as u16) << 1)
-                    + ((player.controller.move_up() as u16) << 2)
-                    + ((player.controller.move_down() as u16) << 3)
-                    + ((player.controller.trigger_map() as u16) << 4)
-                    + ((player.controller.trigger_inventory() as u16) << 5)
-                    + (((player.controller.jump() || player.controller.trigger_menu_ok()) as u16) << 6)
-                    + (((player.controller.shoot() || player.controller.trigger_menu_back()) as u16) << 7)
-                    + ((player.controller.next_weapon() as u16) << 8)
-                    + ((player.controller.prev_weapon() as u16) << 9)
-                    + ((player.controller.trigger_menu_ok() as u16) << 11)
-                    + ((player.controller.skip() as u16) << 12)
-                    + ((player.controller.strafe() as u16) << 13);
-
-                self.keylist.push(inputs);
+                self.keylists[0].push(encode_inputs(player1.controller.as_ref()));
+                self.keylists[1].push(encode_inputs(player2.controller.as_ref()));
             }
             ReplayState::Playback(_) => {
                 let pause = ctx.keyboard_context.is_key_pressed(ScanCode::Escape) && (self.tick - self.resume_tick > 3);
 
-                let next_input = if pause { 1 << 10 } else { *self.keylist.get(self.tick).unwrap_or(&0) };
+                for (idx, player) in [&mut *player1, &mut *player2].into_iter().enumerate() {
+                    let next_input = if pause && idx == 0 {
+                        1 << 10
+                    } else if pause {
+                        self.last_inputs[idx].0
+                    } else {
+                        *self.keylists[idx].get(self.tick).unwrap_or(&0)
+                    };
+
+                    self.controllers[idx].state = KeyState(next_input);
+                    self.controllers[idx].old_state = self.last_inputs[idx];
+                    player.controller = Box::new(self.controllers[idx]);
 
-                self.controller.state = KeyState(next_input);
-                self.controller.old_state = self.last_input;
-                player.controller = Box::new(self.controller);
+                    if !pause {
+                        self.last_inputs[idx] = KeyState(next_input);
+                    }
+                }
 
                 if !pause {
-                    self.last_input = KeyState(next_input);
                     self.tick += 1;
                 } else {
                     self.resume_tick = self.tick;
                 };
 
-                if self.tick >= self.keylist.len() {
+                if self.tick >= self.keylists[0].len() {
                     state.replay_state = ReplayState::None;
-                    player.controller = state.settings.create_player1_controller();
+                    player1.controller = state.settings.create_player1_controller();
+                    player2.controller = state.settings.create_player2_controller();
                 }
             }
             ReplayState::None => {}
@@ -172,9 +714,12 @@ impl GameEntity<(&mut Context, &mut Player)> for Replay {
         match state.replay_state {
             ReplayState::None => {}
             ReplayState::Playback(_) => {
+                let text = if self.desync.is_some() { "DESYNC" } else { "PLAY" };
+                let x = x.min(state.canvas_size.0 - 8.0 - state.font.builder().compute_width(text));
+
                 state.font.builder()
                     .position(x, y)
-                    .draw("PLAY", ctx, &state.constants, &mut state.texture_set)?;
+                    .draw(text, ctx, &state.constants, &mut state.texture_set)?;
             }
             ReplayState::Recording => {
                 state.font.builder()
@@ -186,3 +731,112 @@ impl GameEntity<(&mut Context, &mut Player)> for Replay {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_roundtrip() {
+        let mut replay = Replay::new();
+        replay.header.mod_path = Some("/mods/test".to_owned());
+        replay.header.mod_paths = vec!["/mods/test".to_owned(), "/mods/dependency".to_owned()];
+        replay.header.stage_id = 13;
+        replay.header.difficulty = GameDifficulty::Hard;
+        replay.header.timing_mode = TimingMode::_60Hz;
+        replay.header.player_count = PlayerCount::Two;
+        replay.header.rng_seed = 0xdeadbeef;
+        replay.keyframes.push(ReplayKeyframe { tick: 0, rng_state: 1, event_num: 90, profile: vec![1, 2, 3] });
+        replay.keyframes.push(ReplayKeyframe { tick: 3, rng_state: 2, event_num: 0, profile: vec![] });
+        replay.checksums.push(ReplayChecksum { tick: 50, position: 7, life: 3 | 3 << 16, rng: 9, npc_count: 12 });
+        replay.keylists = [vec![0, 1, 2, 0x40, 0x80], vec![0, 0, 0x40, 0, 0]];
+
+        let mut data = Vec::new();
+        replay.write_to(&mut data).unwrap();
+
+        let mut loaded = Replay::new();
+        loaded.read_from(Cursor::new(data)).unwrap();
+
+        assert_eq!(loaded.header.version, REPLAY_VERSION);
+        assert_eq!(loaded.header.engine_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(loaded.header.mod_path.as_deref(), Some("/mods/test"));
+        assert_eq!(loaded.header.mod_paths, replay.header.mod_paths);
+        assert_eq!(loaded.header.stage_id, 13);
+        assert_eq!(loaded.header.difficulty, GameDifficulty::Hard);
+        assert!(loaded.header.timing_mode == TimingMode::_60Hz);
+        assert!(loaded.header.player_count == PlayerCount::Two);
+        assert_eq!(loaded.header.rng_seed, 0xdeadbeef);
+        assert_eq!(loaded.keyframes.len(), 2);
+        assert_eq!(loaded.keyframes[0].event_num, 90);
+        assert_eq!(loaded.keyframes[0].profile, vec![1, 2, 3]);
+        assert_eq!(loaded.keyframes[1].tick, 3);
+        assert_eq!(loaded.checksums, replay.checksums);
+        assert_eq!(loaded.keylists[0], vec![0, 1, 2, 0x40, 0x80]);
+        assert_eq!(loaded.keylists[1], vec![0, 0, 0x40, 0, 0]);
+    }
+
+    #[test]
+    fn test_replay_idle_player2() {
+        let mut replay = Replay::new();
+        replay.keylists = [vec![1, 2, 3], vec![0, 0, 0]];
+
+        let mut data = Vec::new();
+        replay.write_to(&mut data).unwrap();
+
+        let mut loaded = Replay::new();
+        loaded.read_from(Cursor::new(data)).unwrap();
+
+        assert_eq!(loaded.keylists[0], vec![1, 2, 3]);
+        assert!(loaded.keylists[1].is_empty());
+    }
+
+    #[test]
+    fn test_checksum_diff() {
+        let recorded = ReplayChecksum { tick: 100, position: 1, life: 3, rng: 5, npc_count: 8 };
+
+        assert!(recorded.diff(&recorded).is_empty());
+        assert_eq!(recorded.diff(&ReplayChecksum { rng: 6, ..recorded }), vec!["game RNG"]);
+        assert_eq!(
+            recorded.diff(&ReplayChecksum { position: 2, npc_count: 9, ..recorded }),
+            vec!["player position", "NPC count"]
+        );
+    }
+
+    #[test]
+    fn test_replay_legacy() {
+        let mut data = Vec::new();
+        data.write_u16::<LE>(0).unwrap();
+        data.write_u64::<LE>(1234).unwrap();
+        for input in [4u16, 5, 6] {
+            data.write_u16::<LE>(input).unwrap();
+        }
+
+        let mut loaded = Replay::new();
+        loaded.read_from(Cursor::new(data)).unwrap();
+
+        assert_eq!(loaded.header.version, 0);
+        assert_eq!(loaded.header.rng_seed, 1234);
+        assert!(loaded.keyframes.is_empty());
+        assert_eq!(loaded.keylists[0], vec![4, 5, 6]);
+        assert!(loaded.keylists[1].is_empty());
+    }
+
+    #[test]
+    fn test_replay_corrupted() {
+        let mut data = Vec::new();
+        data.write_u32::<LE>(0).unwrap();
+        data.write_u64::<LE>(0).unwrap();
+        data.write_u16::<LE>(0).unwrap();
+        data.write_u32::<LE>(u32::MAX).unwrap();
+        assert!(ReplayKeyframe::read_from(Cursor::new(&data)).is_err());
+
+        let len = data.len();
+        data[len - 4..].copy_from_slice(&16u32.to_le_bytes());
+        data.extend_from_slice(&[1, 2, 3]);
+        assert!(ReplayKeyframe::read_from(Cursor::new(&data)).is_err());
+
+        let mut header = ReplayHeader::new();
+        header.mod_path = Some("a".repeat(u16::MAX as usize + 1));
+        assert!(header.write_to(&mut Vec::new()).is_err());
+    }
+}
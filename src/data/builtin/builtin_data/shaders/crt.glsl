@@ -0,0 +1,34 @@
+#pragma upscale
+
+// Emulates a CRT screen with scanlines, an aperture grille mask and a slight horizontal blur and vignette.
+
+void main()
+{
+    vec2 texel = Frag_UV * SourceSize;
+    vec2 pixel = 1.0 / SourceSize;
+
+    // Samples the center of the source row, the scanlines take care of the vertical falloff.
+    vec2 uv = vec2(Frag_UV.x, (floor(texel.y) + 0.5) * pixel.y);
+    vec3 color = texture2D(Texture, uv).rgb * 0.6;
+    color += texture2D(Texture, uv - vec2(pixel.x, 0.0)).rgb * 0.2;
+    color += texture2D(Texture, uv + vec2(pixel.x, 0.0)).rgb * 0.2;
+
+    float scanline = sin(fract(texel.y) * 3.14159265);
+    color *= mix(0.5, 1.0, scanline);
+
+    vec3 mask = vec3(0.8);
+    float column = mod(floor(Frag_UV.x * OutputSize.x), 3.0);
+    if (column < 1.0) {
+        mask.r = 1.0;
+    } else if (column < 2.0) {
+        mask.g = 1.0;
+    } else {
+        mask.b = 1.0;
+    }
+    color *= mask;
+
+    vec2 center_dist = Frag_UV - 0.5;
+    color *= 1.0 - dot(center_dist, center_dist) * 0.5;
+
+    gl_FragColor = vec4(color * 1.25, 1.0);
+}
@@ -0,0 +1,23 @@
+// Reduces the image to the four shades of green of the original Game Boy screen.
+
+void main()
+{
+    vec3 color = texture2D(Texture, Frag_UV).rgb;
+    float luma = dot(color, vec3(0.299, 0.587, 0.114));
+
+    // Ordered dithering with a 2x2 Bayer matrix keeps the gradients readable with just four colors.
+    vec2 pos = mod(floor(Frag_UV * SourceSize), 2.0);
+    float threshold = (mod(pos.x * 2.0 + pos.y * 3.0, 4.0) + 0.5) / 4.0 - 0.5;
+    float level = clamp(floor(luma * 3.0 + 0.5 + threshold), 0.0, 3.0);
+
+    vec3 shade = vec3(0.059, 0.220, 0.059);
+    if (level >= 3.0) {
+        shade = vec3(0.608, 0.737, 0.059);
+    } else if (level >= 2.0) {
+        shade = vec3(0.545, 0.675, 0.059);
+    } else if (level >= 1.0) {
+        shade = vec3(0.188, 0.384, 0.188);
+    }
+
+    gl_FragColor = vec4(shade, 1.0);
+}
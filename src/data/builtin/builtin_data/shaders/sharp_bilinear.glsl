@@ -0,0 +1,16 @@
+#pragma upscale
+
+// Scales the image by the largest integer factor that fits using nearest neighbour filtering, then smooths out
+// only the remaining fraction with bilinear filtering, so the pixels stay sharp and even at any window size.
+
+void main()
+{
+    vec2 texel = Frag_UV * SourceSize;
+    vec2 scale = max(floor(OutputSize / SourceSize), vec2(1.0));
+
+    vec2 region = 0.5 - 0.5 / scale;
+    vec2 center_dist = fract(texel) - 0.5;
+    vec2 offset = (center_dist - clamp(center_dist, -region, region)) * scale + 0.5;
+
+    gl_FragColor = texture2D(Texture, (floor(texel) + offset) / SourceSize);
+}
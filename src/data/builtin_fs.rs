@@ -189,6 +189,20 @@ impl BuiltinFS {
                                     FSNode::File("jp.json", include_bytes!("builtin/builtin_data/locale/jp.json")),
                                 ],
                             ),
+                            FSNode::Directory(
+                                "shaders",
+                                vec![
+                                    FSNode::File("crt.glsl", include_bytes!("builtin/builtin_data/shaders/crt.glsl")),
+                                    FSNode::File(
+                                        "gameboy.glsl",
+                                        include_bytes!("builtin/builtin_data/shaders/gameboy.glsl"),
+                                    ),
+                                    FSNode::File(
+                                        "sharp_bilinear.glsl",
+                                        include_bytes!("builtin/builtin_data/shaders/sharp_bilinear.glsl"),
+                                    ),
+                                ],
+                            ),
                         ],
                     ),
                     FSNode::Directory(
@@ -0,0 +1,130 @@
+use imgui::Condition;
+
+use crate::components::tilemap::TileLayer;
+use crate::editor::history::EditorAction;
+use crate::editor::EditorInstance;
+
+/// Commonly used tile attributes, see `PhysicalEntity::tick_map_collisions` for how they behave.
+const ATTRIBUTE_PRESETS: [(u8, &str); 31] = [
+    (0x00, "Empty"),
+    (0x01, "Background"),
+    (0x02, "Water (background)"),
+    (0x03, "Solid to NPCs only"),
+    (0x04, "Water, solid to NPCs only"),
+    (0x05, "Solid (background)"),
+    (0x41, "Solid"),
+    (0x42, "Spikes"),
+    (0x43, "Breakable (star block)"),
+    (0x44, "Solid, unless NPC ignores 0x44"),
+    (0x46, "Solid to player only"),
+    (0x4a, "Platform"),
+    (0x50, "Slope: upper left, high"),
+    (0x51, "Slope: upper left, low"),
+    (0x52, "Slope: upper right, low"),
+    (0x53, "Slope: upper right, high"),
+    (0x54, "Slope: lower left, high"),
+    (0x55, "Slope: lower left, low"),
+    (0x56, "Slope: lower right, low"),
+    (0x57, "Slope: lower right, high"),
+    (0x60, "Water"),
+    (0x61, "Water, solid"),
+    (0x62, "Water, spikes"),
+    (0x70, "Water slope: upper left, high"),
+    (0x74, "Water slope: lower left, high"),
+    (0x80, "Wind: left"),
+    (0x81, "Wind: up"),
+    (0x82, "Wind: right"),
+    (0x83, "Wind: down"),
+    (0xa0, "Water current: left"),
+    (0xa2, "Water current: right"),
+];
+
+pub(super) fn attribute_color(attrib: u8) -> [f32; 4] {
+    match attrib {
+        0x42 | 0x62 => [1.0, 0.2, 0.2, 0.35],
+        0x02 | 0x60..=0x7f | 0xa0..=0xa3 => [0.2, 0.4, 1.0, 0.35],
+        0x50..=0x57 => [0.2, 1.0, 0.2, 0.35],
+        0x80..=0x83 => [1.0, 1.0, 0.2, 0.35],
+        0x03..=0x05 | 0x41 | 0x43 | 0x44 | 0x46 | 0x4a => [1.0, 1.0, 1.0, 0.25],
+        _ => [0.0, 0.0, 0.0, 0.0],
+    }
+}
+
+impl EditorInstance {
+    pub(super) fn attributes_window(&mut self, ui: &imgui::Ui) {
+        ui.window("Tile Attributes")
+            .position([ui.io().display_size[0] - 270.0, 260.0], Condition::FirstUseEver)
+            .position_pivot([1.0, 0.0])
+            .size([280.0, 240.0], Condition::FirstUseEver)
+            .build(|| {
+                ui.checkbox("Show on map", &mut self.show_attributes);
+                ui.checkbox("Assign in palette", &mut self.attribute_mode);
+
+                let mut attribute = self.current_attribute as i32;
+                if ui.input_int("Attribute", &mut attribute).chars_hexadecimal(true).build() {
+                    self.current_attribute = attribute.clamp(0, u8::MAX as i32) as u8;
+                }
+
+                ui.separator();
+
+                for (attrib, name) in ATTRIBUTE_PRESETS {
+                    let label = format!("{:02x}  {}", attrib, name);
+                    if ui.selectable_config(label).selected(self.current_attribute == attrib).build() {
+                        self.current_attribute = attrib;
+                    }
+                }
+            });
+    }
+
+    /// Changes the attribute of a tileset cell and records it in the undo history.
+    pub(super) fn set_attribute(&mut self, tile: usize, attrib: u8) {
+        let Some(old) = self.stage.map.attrib.get_mut(tile) else {
+            return;
+        };
+
+        if *old != attrib {
+            self.history.push(EditorAction::Attribute { tile, old: *old, new: attrib });
+            *old = attrib;
+        }
+    }
+
+    /// Draws the attribute of each visible foreground tile over the map.
+    pub(super) fn attribute_overlay(&self, ui: &imgui::Ui) {
+        let tile_size = self.stage.map.tile_size.as_int();
+        let halft = tile_size / 2;
+        let frame_x = self.frame.x / 0x200;
+        let frame_y = self.frame.y / 0x200;
+        let [display_w, display_h] = ui.io().display_size;
+        let tile_screen = tile_size as f32 * self.zoom;
+
+        let first_x = ((frame_x + halft).div_euclid(tile_size)).max(0);
+        let first_y = ((frame_y + halft).div_euclid(tile_size)).max(0);
+        let last_x = (first_x + (display_w / tile_screen) as i32 + 2).min(self.stage.map.width as i32);
+        let last_y = (first_y + (display_h / tile_screen) as i32 + 2).min(self.stage.map.height as i32);
+
+        let draw_list = ui.get_background_draw_list();
+
+        for y in first_y..last_y {
+            for x in first_x..last_x {
+                let Some(index) = self.stage.tile_index(x as usize, y as usize, TileLayer::Foreground) else {
+                    continue;
+                };
+
+                let tile = self.stage.map.tiles[index] as usize;
+                let attrib = self.stage.map.attrib.get(tile).copied().unwrap_or(0);
+                if attrib == 0 {
+                    continue;
+                }
+
+                let pos1 = [
+                    (x * tile_size - halft - frame_x) as f32 * self.zoom,
+                    (y * tile_size - halft - frame_y) as f32 * self.zoom,
+                ];
+                let pos2 = [pos1[0] + tile_screen, pos1[1] + tile_screen];
+
+                draw_list.add_rect(pos1, pos2, attribute_color(attrib)).filled(true).build();
+                draw_list.add_text([pos1[0] + 1.0, pos1[1] + 1.0], [1.0, 1.0, 1.0, 1.0], format!("{:02x}", attrib));
+            }
+        }
+    }
+}
@@ -0,0 +1,315 @@
+use imgui::{Condition, Key, MouseButton};
+
+use crate::common::{Color, Rect};
+use crate::components::flash::Flash;
+use crate::editor::EditorInstance;
+use crate::entity::GameEntity;
+use crate::framework::context::Context;
+use crate::framework::error::GameResult;
+use crate::framework::graphics;
+use crate::game::map::NPCData;
+use crate::game::npc::boss::BossNPC;
+use crate::game::npc::list::NPCList;
+use crate::game::npc::NPC;
+use crate::game::player::Player;
+use crate::game::shared_game_state::{GameDifficulty, SharedGameState};
+use crate::game::weapon::bullet::BulletManager;
+
+/// Names of the NPC flag bits, in the same order as `NPCFlag`.
+const NPC_FLAG_NAMES: [&str; 16] = [
+    "Solid (soft)",
+    "Ignore tile 0x44",
+    "Invulnerable",
+    "Ignore solidity",
+    "Bouncy",
+    "Shootable",
+    "Solid (hard)",
+    "Rear and top don't hurt",
+    "Event when touched",
+    "Event when killed",
+    "Unknown (0x400)",
+    "Appear when flag set",
+    "Spawn facing right",
+    "Interactable",
+    "Hide when flag set",
+    "Show damage",
+];
+
+/// Parts of the game state written by NPC AI, saved before ticking entity previews.
+struct PreviewStateSnapshot {
+    game_rng: u64,
+    effect_rng: u64,
+    carets: usize,
+    quake_counter: u16,
+    super_quake_counter: u16,
+    quake_rumble_counter: u32,
+    super_quake_rumble_counter: u32,
+    npc_super_pos: (i32, i32),
+    npc_curly_target: (i32, i32),
+    npc_curly_counter: u16,
+    water_level: i32,
+    difficulty: GameDifficulty,
+}
+
+impl PreviewStateSnapshot {
+    fn take(state: &SharedGameState) -> PreviewStateSnapshot {
+        PreviewStateSnapshot {
+            game_rng: state.game_rng.dump_state(),
+            effect_rng: state.effect_rng.dump_state(),
+            carets: state.carets.len(),
+            quake_counter: state.quake_counter,
+            super_quake_counter: state.super_quake_counter,
+            quake_rumble_counter: state.quake_rumble_counter,
+            super_quake_rumble_counter: state.super_quake_rumble_counter,
+            npc_super_pos: state.npc_super_pos,
+            npc_curly_target: state.npc_curly_target,
+            npc_curly_counter: state.npc_curly_counter,
+            water_level: state.water_level,
+            difficulty: state.difficulty,
+        }
+    }
+
+    fn restore(self, state: &mut SharedGameState) {
+        state.game_rng.load_state(self.game_rng);
+        state.effect_rng.load_state(self.effect_rng);
+        state.carets.truncate(self.carets);
+        state.quake_counter = self.quake_counter;
+        state.super_quake_counter = self.super_quake_counter;
+        state.quake_rumble_counter = self.quake_rumble_counter;
+        state.super_quake_rumble_counter = self.super_quake_rumble_counter;
+        state.npc_super_pos = self.npc_super_pos;
+        state.npc_curly_target = self.npc_curly_target;
+        state.npc_curly_counter = self.npc_curly_counter;
+        state.water_level = self.water_level;
+        state.difficulty = self.difficulty;
+    }
+}
+
+impl EditorInstance {
+    /// Recreates the NPCs used to draw the entities with their sprites.
+    ///
+    /// The NPC AI runs against a copy of the stage, and the game state it can touch is restored afterwards,
+    /// so previews can't modify the edited map, flags or anything else outside of the editor.
+    pub(super) fn refresh_entity_previews(&mut self, state: &mut SharedGameState, ctx: &mut Context) {
+        let mut player1 = Player::new(state, ctx);
+        let mut player2 = Player::new(state, ctx);
+        let npc_list = NPCList::new();
+        let mut bullet_manager = BulletManager::new();
+        let mut flash = Flash::new();
+        let mut boss = BossNPC::new();
+        let mut stage = self.stage.clone();
+        let tile_size = self.stage.map.tile_size;
+
+        let snapshot = PreviewStateSnapshot::take(state);
+        let was_muted = state.sound_manager.set_muted(true);
+
+        let mut previews = Vec::with_capacity(self.npc_data.len());
+        for data in &self.npc_data {
+            let mut npc = NPC::create_from_data(data, &state.npc_table, tile_size);
+            npc.cond.set_alive(true);
+
+            let flag = state.get_flag(npc.flag_num as usize);
+
+            // Most NPCs only pick their animation frame on the first tick.
+            let _ = npc.tick(
+                state,
+                ([&mut player1, &mut player2], &npc_list, &mut stage, &mut bullet_manager, &mut flash, &mut boss),
+            );
+
+            state.set_flag(npc.flag_num as usize, flag);
+            previews.push(npc);
+        }
+
+        state.sound_manager.set_muted(was_muted);
+        snapshot.restore(state);
+
+        self.entity_previews = previews;
+        for idx in 0..self.entity_previews.len() {
+            self.sync_entity_position(idx);
+        }
+
+        self.entity_previews_dirty = false;
+    }
+
+    fn sync_entity_position(&mut self, idx: usize) {
+        let (Some(data), Some(npc)) = (self.npc_data.get(idx), self.entity_previews.get_mut(idx)) else {
+            return;
+        };
+
+        let ti = self.stage.map.tile_size.as_int() * 0x200;
+        npc.x = data.x as i32 * ti;
+        npc.y = data.y as i32 * ti;
+        npc.prev_x = npc.x;
+        npc.prev_y = npc.y;
+    }
+
+    fn entity_at(&self, tile_x: i32, tile_y: i32) -> Option<usize> {
+        self.npc_data.iter().rposition(|data| data.x as i32 == tile_x && data.y as i32 == tile_y)
+    }
+
+    pub(super) fn process_entities(&mut self, ui: &imgui::Ui) {
+        if !ui.io().want_capture_keyboard && ui.is_key_pressed(Key::Delete) {
+            if let Some(idx) = self.selected_entity.take() {
+                self.delete_entity(idx);
+            }
+        }
+
+        if ui.io().want_capture_mouse {
+            return;
+        }
+
+        let (tile_x, tile_y) = self.tile_at_mouse();
+        let in_bounds =
+            tile_x >= 0 && tile_y >= 0 && tile_x < self.stage.map.width as i32 && tile_y < self.stage.map.height as i32;
+
+        if ui.is_mouse_clicked(MouseButton::Left) && in_bounds {
+            self.selected_entity = match self.entity_at(tile_x, tile_y) {
+                Some(idx) => Some(idx),
+                None => {
+                    self.npc_data.push(NPCData {
+                        id: 170 + self.npc_data.len() as u16,
+                        x: tile_x as i16,
+                        y: tile_y as i16,
+                        flag_num: 0,
+                        event_num: 0,
+                        npc_type: self.entity_type,
+                        flags: 0,
+                        layer: 0,
+                    });
+                    self.entity_previews_dirty = true;
+
+                    Some(self.npc_data.len() - 1)
+                }
+            };
+            self.dragging_entity = true;
+        }
+
+        if !ui.is_mouse_down(MouseButton::Left) {
+            self.dragging_entity = false;
+        }
+
+        if let (true, true, Some(idx)) = (self.dragging_entity, in_bounds, self.selected_entity) {
+            if let Some(data) = self.npc_data.get_mut(idx) {
+                data.x = tile_x as i16;
+                data.y = tile_y as i16;
+                self.sync_entity_position(idx);
+            }
+        }
+    }
+
+    fn delete_entity(&mut self, idx: usize) {
+        if idx < self.npc_data.len() {
+            self.npc_data.remove(idx);
+            self.entity_previews_dirty = true;
+        }
+    }
+
+    pub(super) fn entity_inspector_window(&mut self, ui: &imgui::Ui) {
+        ui.window("Entity Inspector")
+            .position([80.0, 80.0], Condition::FirstUseEver)
+            .size([280.0, 480.0], Condition::FirstUseEver)
+            .scrollable(true)
+            .always_vertical_scrollbar(true)
+            .build(|| {
+                input_u16(ui, "New entity type", &mut self.entity_type);
+                ui.separator();
+
+                let mut changed = false;
+                let mut delete = false;
+
+                if let Some(data) = self.selected_entity.and_then(|idx| self.npc_data.get_mut(idx)) {
+                    let mut position = [data.x as i32, data.y as i32];
+                    if ui.input_int2("Position", &mut position).build() {
+                        data.x = position[0].clamp(0, i16::MAX as i32) as i16;
+                        data.y = position[1].clamp(0, i16::MAX as i32) as i16;
+                    }
+
+                    changed |= input_u16(ui, "Type", &mut data.npc_type);
+                    input_u16(ui, "Flag", &mut data.flag_num);
+                    input_u16(ui, "Event", &mut data.event_num);
+
+                    let mut layer = data.layer as i32;
+                    if ui.input_int("Layer", &mut layer).build() {
+                        data.layer = layer.clamp(0, u8::MAX as i32) as u8;
+                    }
+
+                    ui.text(format!("Flags: {:#06x}", data.flags));
+                    for (bit, name) in NPC_FLAG_NAMES.iter().enumerate() {
+                        changed |= ui.checkbox_flags(name, &mut data.flags, 1 << bit);
+                    }
+
+                    delete = ui.button("Delete");
+                } else {
+                    ui.text_wrapped("Click an entity to select it, or an empty tile to place a new one.");
+                }
+
+                ui.separator();
+
+                let labels: Vec<String> = self
+                    .npc_data
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, data)| format!("#{} type={} ({}, {})", idx, data.npc_type, data.x, data.y))
+                    .collect();
+                let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
+                let mut selected = self.selected_entity.map_or(-1, |idx| idx as i32);
+
+                ui.push_item_width(-1.0);
+                if ui.list_box("##entities", &mut selected, &labels, 10) {
+                    self.selected_entity = usize::try_from(selected).ok();
+                }
+
+                if delete {
+                    if let Some(idx) = self.selected_entity.take() {
+                        self.delete_entity(idx);
+                    }
+                } else if changed {
+                    self.entity_previews_dirty = true;
+                } else if let Some(idx) = self.selected_entity {
+                    self.sync_entity_position(idx);
+                }
+            });
+    }
+
+    pub(super) fn draw_entities(
+        &self,
+        state: &mut SharedGameState,
+        ctx: &mut Context,
+        show_selection: bool,
+    ) -> GameResult {
+        state.npc_table.stage_textures = self.stage_textures.clone();
+
+        let tile_size = self.stage.map.tile_size.as_int();
+        let (frame_x, frame_y) = self.frame.xy_interpolated(state.frame_time);
+
+        for (idx, npc) in self.entity_previews.iter().enumerate() {
+            let has_sprite = npc.cond.alive() && !npc.cond.hidden() && npc.anim_rect.width() > 0;
+            if has_sprite {
+                npc.draw(state, ctx, &self.frame)?;
+            }
+
+            let selected = show_selection && self.selected_entity == Some(idx);
+            if !has_sprite || selected {
+                let x = ((npc.x / 0x200 - tile_size / 2) as f32 - frame_x) * state.scale;
+                let y = ((npc.y / 0x200 - tile_size / 2) as f32 - frame_y) * state.scale;
+                let size = tile_size as f32 * state.scale;
+                let rect = Rect::new_size(x as isize, y as isize, size as isize, size as isize);
+                let color = if selected { Color::from_rgb(255, 0, 0) } else { Color::from_rgba(255, 255, 0, 192) };
+
+                graphics::draw_outline_rect(ctx, rect, state.scale as usize, color)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn input_u16(ui: &imgui::Ui, label: &str, value: &mut u16) -> bool {
+    let mut input = *value as i32;
+    if ui.input_int(label, &mut input).build() {
+        *value = input.clamp(0, u16::MAX as i32) as u16;
+        return true;
+    }
+
+    false
+}
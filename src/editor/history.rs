@@ -0,0 +1,157 @@
+use crate::editor::EditorInstance;
+use crate::game::map::NPCData;
+
+/// Maximum number of actions kept in the undo history.
+const HISTORY_LIMIT: usize = 256;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TileChange {
+    /// Index in `Map::tiles`, which also encodes the layer.
+    pub index: usize,
+    pub old: u16,
+    pub new: u16,
+}
+
+/// Map size, tiles and entities, which all change together when the map is resized.
+#[derive(Debug, Clone)]
+pub struct MapSnapshot {
+    pub width: u16,
+    pub height: u16,
+    pub tiles: Vec<u16>,
+    pub npc_data: Vec<NPCData>,
+}
+
+#[derive(Debug, Clone)]
+pub enum EditorAction {
+    Tiles(Vec<TileChange>),
+    /// Entity list before and after the edit.
+    Entities(Vec<NPCData>, Vec<NPCData>),
+    /// Tileset attribute change of a single tile.
+    Attribute { tile: usize, old: u8, new: u8 },
+    /// Map state before and after a resize.
+    Resize(Box<MapSnapshot>, Box<MapSnapshot>),
+}
+
+/// Undo and redo stacks of an editor instance.
+#[derive(Default)]
+pub struct EditHistory {
+    undo_stack: Vec<EditorAction>,
+    redo_stack: Vec<EditorAction>,
+    /// Tile changes of the brush stroke in progress, which are undone together.
+    stroke: Vec<TileChange>,
+}
+
+impl EditHistory {
+    pub fn new() -> EditHistory {
+        EditHistory::default()
+    }
+
+    pub fn push(&mut self, action: EditorAction) {
+        self.end_stroke();
+        self.push_action(action);
+    }
+
+    fn push_action(&mut self, action: EditorAction) {
+        self.redo_stack.clear();
+        self.undo_stack.push(action);
+
+        if self.undo_stack.len() > HISTORY_LIMIT {
+            self.undo_stack.remove(0);
+        }
+    }
+
+    /// Adds a tile change to the current stroke, merging it with earlier changes of the same tile.
+    pub fn record_tile(&mut self, index: usize, old: u16, new: u16) {
+        match self.stroke.iter_mut().find(|change| change.index == index) {
+            Some(change) => change.new = new,
+            None => self.stroke.push(TileChange { index, old, new }),
+        }
+    }
+
+    pub fn end_stroke(&mut self) {
+        if !self.stroke.is_empty() {
+            let stroke = std::mem::take(&mut self.stroke);
+            self.push_action(EditorAction::Tiles(stroke));
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty() || !self.stroke.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+}
+
+impl EditorInstance {
+    pub fn undo(&mut self) {
+        self.history.end_stroke();
+
+        if let Some(action) = self.history.undo_stack.pop() {
+            self.apply_action(&action, false);
+            self.history.redo_stack.push(action);
+        }
+    }
+
+    pub fn redo(&mut self) {
+        self.history.end_stroke();
+
+        if let Some(action) = self.history.redo_stack.pop() {
+            self.apply_action(&action, true);
+            self.history.undo_stack.push(action);
+        }
+    }
+
+    fn apply_action(&mut self, action: &EditorAction, redo: bool) {
+        match action {
+            EditorAction::Tiles(changes) => {
+                // Changes of one stroke never touch the same tile twice, so the order doesn't matter.
+                for change in changes {
+                    if let Some(tile) = self.stage.map.tiles.get_mut(change.index) {
+                        *tile = if redo { change.new } else { change.old };
+                    }
+                }
+            }
+            EditorAction::Entities(before, after) => {
+                self.npc_data = if redo { after.clone() } else { before.clone() };
+                self.entity_previews_dirty = true;
+                self.entity_snapshot = None;
+                self.dragging_entity = false;
+
+                if self.selected_entity.map_or(false, |idx| idx >= self.npc_data.len()) {
+                    self.selected_entity = None;
+                }
+            }
+            EditorAction::Attribute { tile, old, new } => {
+                if let Some(attrib) = self.stage.map.attrib.get_mut(*tile) {
+                    *attrib = if redo { *new } else { *old };
+                }
+            }
+            EditorAction::Resize(before, after) => {
+                self.restore_snapshot(if redo { after } else { before });
+            }
+        }
+    }
+
+    pub(super) fn snapshot(&self) -> MapSnapshot {
+        MapSnapshot {
+            width: self.stage.map.width,
+            height: self.stage.map.height,
+            tiles: self.stage.map.tiles.clone(),
+            npc_data: self.npc_data.clone(),
+        }
+    }
+
+    fn restore_snapshot(&mut self, snapshot: &MapSnapshot) {
+        self.stage.map.width = snapshot.width;
+        self.stage.map.height = snapshot.height;
+        self.stage.map.tiles = snapshot.tiles.clone();
+        self.stage.tile_edits.clear();
+        self.npc_data = snapshot.npc_data.clone();
+        self.entity_previews_dirty = true;
+        self.entity_snapshot = None;
+        self.dragging_entity = false;
+        self.selected_entity = None;
+    }
+}
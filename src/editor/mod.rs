@@ -1,31 +1,73 @@
 use std::cell::RefCell;
 use std::ops::Deref;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
-use imgui::{Image, MouseButton, Window};
+use imgui::{Condition, Image, MouseButton, Window};
 
 use crate::common::{Color, Rect};
 use crate::components::background::Background;
 use crate::components::tilemap::{TileLayer, Tilemap};
 use crate::framework::context::Context;
-use crate::framework::error::GameResult;
-use crate::framework::graphics;
-use crate::game::shared_game_state::SharedGameState;
+use crate::framework::error::{GameError, GameResult};
+use crate::framework::{filesystem, graphics};
 use crate::game::frame::Frame;
+use crate::game::map::NPCData;
+use crate::game::npc::NPC;
+use crate::game::shared_game_state::SharedGameState;
 use crate::game::stage::{Stage, StageTexturePaths};
 use crate::graphics::texture_set::I_MAG;
 
+use self::attributes::attribute_color;
+use self::history::{EditHistory, EditorAction};
+use self::resize::ResizeDialog;
+
+mod attributes;
+mod entities;
+mod history;
+mod resize;
+pub mod stage_table;
+
+/// Tile layers which can be edited, in drawing order.
+const EDITABLE_LAYERS: [(TileLayer, &str); 4] = [
+    (TileLayer::Background, "Background"),
+    (TileLayer::Middleground, "Middleground"),
+    (TileLayer::Foreground, "Foreground"),
+    (TileLayer::FarForeground, "Far foreground"),
+];
+
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub enum CurrentTool {
     Move,
     Brush,
     Fill,
     Rectangle,
+    Entity,
 }
 
 pub struct EditorInstance {
     pub stage: Stage,
     pub stage_id: usize,
+    pub npc_data: Vec<NPCData>,
+    pub entity_previews: Vec<NPC>,
+    pub entity_previews_dirty: bool,
+    pub selected_entity: Option<usize>,
+    pub dragging_entity: bool,
+    /// Type of the entities placed with the entity tool.
+    pub entity_type: u16,
+    /// Entity list from before the drag in progress, so the whole drag is undone at once.
+    pub entity_snapshot: Option<Vec<NPCData>>,
+    pub history: EditHistory,
+    /// Layer painted on by the tile tools.
+    pub current_layer: TileLayer,
+    /// Visibility of each layer, in the order of `EDITABLE_LAYERS`.
+    pub visible_layers: [bool; 4],
+    pub resize_dialog: ResizeDialog,
+    /// Overlay tile attributes over the map.
+    pub show_attributes: bool,
+    /// Clicking the palette assigns `current_attribute` to tiles instead of selecting them.
+    pub attribute_mode: bool,
+    pub current_attribute: u8,
     pub frame: Frame,
     pub background: Background,
     pub stage_textures: Rc<RefCell<StageTexturePaths>>,
@@ -37,7 +79,7 @@ pub struct EditorInstance {
 }
 
 impl EditorInstance {
-    pub fn new(stage_id: usize, stage: Stage) -> EditorInstance {
+    pub fn new(stage_id: usize, stage: Stage, npc_data: Vec<NPCData>) -> EditorInstance {
         let stage_textures = {
             let mut textures = StageTexturePaths::new();
             textures.update(&stage);
@@ -50,6 +92,20 @@ impl EditorInstance {
         EditorInstance {
             stage,
             stage_id,
+            npc_data,
+            entity_previews: Vec::new(),
+            entity_previews_dirty: true,
+            selected_entity: None,
+            dragging_entity: false,
+            entity_type: 0,
+            entity_snapshot: None,
+            history: EditHistory::new(),
+            current_layer: TileLayer::Foreground,
+            visible_layers: [true; 4],
+            resize_dialog: ResizeDialog::new(),
+            show_attributes: false,
+            attribute_mode: false,
+            current_attribute: 0x41,
             frame,
             background: Background::new(),
             stage_textures,
@@ -67,6 +123,20 @@ impl EditorInstance {
         self.mouse_pos = (ui.io().mouse_pos[0], ui.io().mouse_pos[1]);
         self.want_capture_mouse = ui.io().want_capture_mouse;
 
+        if self.entity_previews_dirty {
+            self.refresh_entity_previews(state, ctx);
+        }
+
+        if !ui.is_mouse_down(MouseButton::Left) {
+            self.history.end_stroke();
+        }
+
+        self.resize_window(ui);
+
+        if self.show_attributes {
+            self.attribute_overlay(ui);
+        }
+
         let mut drag = false;
 
         match tool {
@@ -79,6 +149,8 @@ impl EditorInstance {
             }
             CurrentTool::Brush => {
                 self.palette_window(state, ctx, ui);
+                self.layers_window(ui);
+                self.attributes_window(ui);
 
                 if ui.io().want_capture_mouse {
                     return;
@@ -87,30 +159,42 @@ impl EditorInstance {
                 drag |= ui.is_mouse_down(MouseButton::Right);
 
                 if !drag && ui.is_mouse_down(MouseButton::Left) {
-                    let tile_size = self.stage.map.tile_size.as_int();
-                    let halft = tile_size / 2;
-                    let stage_mouse_x = (self.frame.x / 0x200) + halft + (self.mouse_pos.0 / self.zoom) as i32;
-                    let stage_mouse_y = (self.frame.y / 0x200) + halft + (self.mouse_pos.1 / self.zoom) as i32;
-                    let tile_x = stage_mouse_x / tile_size;
-                    let tile_y = stage_mouse_y / tile_size;
+                    let (tile_x, tile_y) = self.tile_at_mouse();
 
                     if tile_x >= 0
                         && tile_y >= 0
                         && tile_x < self.stage.map.width as i32
                         && tile_y < self.stage.map.height as i32
                     {
-                        self.stage.change_tile(tile_x as usize, tile_y as usize, self.current_tile);
+                        self.paint_tile(tile_x as usize, tile_y as usize, self.current_tile as u16);
                     }
                 }
             }
             CurrentTool::Fill => {
                 self.palette_window(state, ctx, ui);
+                self.layers_window(ui);
+                self.attributes_window(ui);
                 drag |= ui.is_mouse_down(MouseButton::Right);
             }
             CurrentTool::Rectangle => {
                 self.palette_window(state, ctx, ui);
+                self.layers_window(ui);
+                self.attributes_window(ui);
                 drag |= ui.is_mouse_down(MouseButton::Right);
             }
+            CurrentTool::Entity => {
+                let before = self.entity_snapshot.take().unwrap_or_else(|| self.npc_data.clone());
+
+                self.entity_inspector_window(ui);
+                self.process_entities(ui);
+                drag |= !ui.io().want_capture_mouse && ui.is_mouse_down(MouseButton::Right);
+
+                if self.dragging_entity {
+                    self.entity_snapshot = Some(before);
+                } else if before != self.npc_data {
+                    self.history.push(EditorAction::Entities(before, self.npc_data.clone()));
+                }
+            }
         }
 
         if drag {
@@ -121,6 +205,101 @@ impl EditorInstance {
         }
     }
 
+    /// Writes the map, its entities and the tileset attributes back to the data directories they were loaded from.
+    pub fn save(&self, state: &SharedGameState, ctx: &Context) -> GameResult {
+        if self.stage.data.pxpack_data.is_some() {
+            return Err(GameError::InvalidValue("Saving PxPack stages is not supported.".to_owned()));
+        }
+
+        let map_path = ["Stage/", &self.stage.data.map, ".pxm"].join("");
+        let npc_path = ["Stage/", &self.stage.data.map, ".pxe"].join("");
+        let attrib_path = ["Stage/", &self.stage.data.tileset.name, ".pxa"].join("");
+
+        let mut map_data = Vec::new();
+        self.stage.map.write_pxm(&mut map_data)?;
+
+        let mut npc_data = Vec::new();
+        NPCData::write_to(&self.npc_data, &mut npc_data)?;
+
+        let mut attrib_data = Vec::new();
+        self.stage.map.write_pxa(&mut attrib_data)?;
+
+        let map_file = find_data_file(state, ctx, &map_path)?;
+        // a map without entities doesn't have to come with an entity file, it's created next to the map
+        let npc_file = match find_data_file(state, ctx, &npc_path) {
+            Ok(npc_file) => npc_file,
+            Err(_) => map_file.with_extension("pxe"),
+        };
+        let attrib_file = find_data_file(state, ctx, &attrib_path)?;
+
+        for (file, data) in [(&map_file, map_data), (&npc_file, npc_data), (&attrib_file, attrib_data)] {
+            write_data_file(file, &data)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if the map has the extra layers of the PXM format.
+    fn has_layers(&self) -> bool {
+        let tile_count = self.stage.map.width as usize * self.stage.map.height as usize;
+        self.stage.data.pxpack_data.is_none() && self.stage.map.tiles.len() > tile_count
+    }
+
+    /// Changes a tile on the selected layer and records it in the undo history.
+    fn paint_tile(&mut self, x: usize, y: usize, tile: u16) {
+        let Some(index) = self.stage.tile_index(x, y, self.current_layer) else {
+            return;
+        };
+
+        let old = self.stage.map.tiles[index];
+        if self.stage.change_tile_layer(x, y, tile, self.current_layer) {
+            self.history.record_tile(index, old, tile);
+        }
+    }
+
+    fn layers_window(&mut self, ui: &imgui::Ui) {
+        let has_layers = self.has_layers();
+        if !has_layers {
+            self.current_layer = TileLayer::Foreground;
+        }
+
+        ui.window("Layers")
+            .position([ui.io().display_size[0], 80.0], Condition::FirstUseEver)
+            .position_pivot([1.0, 0.0])
+            .always_auto_resize(true)
+            .build(|| {
+                for (idx, (layer, name)) in EDITABLE_LAYERS.iter().enumerate() {
+                    let _id = ui.push_id_usize(idx);
+
+                    ui.checkbox("##visible", &mut self.visible_layers[idx]);
+                    ui.same_line();
+
+                    let _disabled = ui.begin_disabled(!has_layers && *layer != TileLayer::Foreground);
+                    ui.radio_button(name, &mut self.current_layer, *layer);
+                }
+
+                if !has_layers {
+                    ui.text_disabled("This map has no extra layers.");
+                }
+            });
+    }
+
+    fn is_layer_visible(&self, layer: TileLayer) -> bool {
+        let layer = if layer == TileLayer::Snack { TileLayer::Foreground } else { layer };
+
+        EDITABLE_LAYERS.iter().zip(self.visible_layers.iter()).any(|((l, _), &visible)| *l == layer && visible)
+    }
+
+    /// Returns the coordinates of the tile under the mouse cursor, which can be out of the map bounds.
+    fn tile_at_mouse(&self) -> (i32, i32) {
+        let tile_size = self.stage.map.tile_size.as_int();
+        let halft = tile_size / 2;
+        let stage_mouse_x = (self.frame.x / 0x200) + halft + (self.mouse_pos.0 / self.zoom) as i32;
+        let stage_mouse_y = (self.frame.y / 0x200) + halft + (self.mouse_pos.1 / self.zoom) as i32;
+
+        (stage_mouse_x.div_euclid(tile_size), stage_mouse_y.div_euclid(tile_size))
+    }
+
     fn tile_cursor(&self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
         if self.want_capture_mouse {
             return Ok(());
@@ -128,10 +307,7 @@ impl EditorInstance {
 
         let tile_size = self.stage.map.tile_size.as_int();
         let halft = tile_size / 2;
-        let stage_mouse_x = (self.frame.x / 0x200) + halft + (self.mouse_pos.0 / self.zoom) as i32;
-        let stage_mouse_y = (self.frame.y / 0x200) + halft + (self.mouse_pos.1 / self.zoom) as i32;
-        let tile_x = stage_mouse_x / tile_size;
-        let tile_y = stage_mouse_y / tile_size;
+        let (tile_x, tile_y) = self.tile_at_mouse();
         let frame_x = self.frame.x as f32 / 512.0;
         let frame_y = self.frame.y as f32 / 512.0;
 
@@ -192,6 +368,22 @@ impl EditorInstance {
                 }
 
                 let draw_list = ui.get_window_draw_list();
+
+                if self.show_attributes || self.attribute_mode {
+                    for (tile, &attrib) in self.stage.map.attrib.iter().enumerate().take(256) {
+                        let pos1 = [
+                            pos[0].floor() + tile_size * (tile % 16) as f32,
+                            pos[1].floor() + tile_size * (tile / 16) as f32,
+                        ];
+                        let pos2 = [pos1[0] + tile_size, pos1[1] + tile_size];
+
+                        draw_list.add_rect(pos1, pos2, attribute_color(attrib)).filled(true).build();
+                        if attrib != 0 {
+                            draw_list.add_text(pos1, [1.0, 1.0, 1.0, 1.0], format!("{:02x}", attrib));
+                        }
+                    }
+                }
+
                 let cur_pos1 = [
                     pos[0].floor() + tile_size * (self.current_tile % 16) as f32,
                     pos[1].floor() + tile_size * (self.current_tile / 16) as f32,
@@ -205,7 +397,13 @@ impl EditorInstance {
                     let y = (mouse_pos[1] - pos[1]) / tile_size;
 
                     if x >= 0.0 && x < 16.0 && y >= 0.0 && y < 16.0 {
-                        self.current_tile = (y as u8 * 16 + x as u8) as u8;
+                        let tile = y as u8 * 16 + x as u8;
+
+                        if self.attribute_mode {
+                            self.set_attribute(tile as usize, self.current_attribute);
+                        } else {
+                            self.current_tile = tile;
+                        }
                     }
                 }
             });
@@ -218,15 +416,22 @@ impl EditorInstance {
         let paths = self.stage_textures.deref().borrow();
         self.background.draw(state, ctx, &self.frame, &*paths, &self.stage)?;
 
-        self.tilemap.draw(state, ctx, &self.frame, TileLayer::Background, &*paths, &self.stage)?;
-        self.tilemap.draw(state, ctx, &self.frame, TileLayer::Middleground, &*paths, &self.stage)?;
-        self.tilemap.draw(state, ctx, &self.frame, TileLayer::Foreground, &*paths, &self.stage)?;
-        self.tilemap.draw(state, ctx, &self.frame, TileLayer::Snack, &*paths, &self.stage)?;
+        for layer in [TileLayer::Background, TileLayer::Middleground, TileLayer::Foreground, TileLayer::Snack] {
+            if self.is_layer_visible(layer) {
+                self.tilemap.draw(state, ctx, &self.frame, layer, &*paths, &self.stage)?;
+            }
+        }
+
+        self.draw_entities(state, ctx, tool == CurrentTool::Entity)?;
+
+        if self.is_layer_visible(TileLayer::FarForeground) {
+            self.tilemap.draw(state, ctx, &self.frame, TileLayer::FarForeground, &*paths, &self.stage)?;
+        }
 
         self.draw_black_bars(state, ctx)?;
 
         match tool {
-            CurrentTool::Move => (),
+            CurrentTool::Move | CurrentTool::Entity => (),
             CurrentTool::Brush | CurrentTool::Fill | CurrentTool::Rectangle => {
                 self.tile_cursor(state, ctx)?;
             }
@@ -279,6 +484,42 @@ impl EditorInstance {
     }
 }
 
+/// Returns the location on disk of a data file, in the mounted directory the game currently loads it from.
+/// Files coming from built-in data or archives can't be written to, so they're rejected.
+fn find_data_file(state: &SharedGameState, ctx: &Context, path: &str) -> GameResult<PathBuf> {
+    let vfs_path = state
+        .constants
+        .base_paths
+        .iter()
+        .map(|root| [root.as_str(), path].join(""))
+        .find(|vfs_path| filesystem::exists(ctx, vfs_path))
+        .ok_or_else(|| GameError::FilesystemError(format!("{} isn't present in any data directory.", path)))?;
+
+    let relative_path = vfs_path.trim_start_matches('/');
+    filesystem::physical_roots(ctx)
+        .into_iter()
+        .map(|root| root.join(relative_path))
+        .find(|file| file.is_file())
+        .ok_or_else(|| {
+            GameError::FilesystemError(format!(
+                "{} is stored in built-in data or an archive and can't be saved.",
+                vfs_path
+            ))
+        })
+}
+
+fn write_data_file(file: &Path, data: &[u8]) -> GameResult {
+    let target = if file.exists() { file } else { file.parent().unwrap_or(file) };
+    if std::fs::metadata(target)?.permissions().readonly() {
+        return Err(GameError::FilesystemError(format!("{} is read-only.", target.display())));
+    }
+
+    std::fs::write(file, data)?;
+    log::info!("Saved {}", file.display());
+
+    Ok(())
+}
+
 fn set_scale(state: &mut SharedGameState, scale: f32) {
     state.scale = scale;
 
@@ -0,0 +1,126 @@
+use imgui::Condition;
+
+use crate::editor::history::EditorAction;
+use crate::editor::EditorInstance;
+
+/// Which side of an axis stays in place when the map is resized.
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum ResizeAnchor {
+    Start,
+    Center,
+    End,
+}
+
+impl ResizeAnchor {
+    const ALL: [ResizeAnchor; 3] = [ResizeAnchor::Start, ResizeAnchor::Center, ResizeAnchor::End];
+
+    /// Returns how far the existing tiles move along the axis.
+    fn offset(self, old_size: u16, new_size: u16) -> i32 {
+        let delta = new_size as i32 - old_size as i32;
+
+        match self {
+            ResizeAnchor::Start => 0,
+            ResizeAnchor::Center => delta / 2,
+            ResizeAnchor::End => delta,
+        }
+    }
+}
+
+/// State of the resize dialog.
+pub struct ResizeDialog {
+    pub visible: bool,
+    pub size: [i32; 2],
+    pub anchor: (ResizeAnchor, ResizeAnchor),
+}
+
+impl ResizeDialog {
+    pub fn new() -> ResizeDialog {
+        ResizeDialog { visible: false, size: [0, 0], anchor: (ResizeAnchor::Start, ResizeAnchor::Start) }
+    }
+}
+
+impl EditorInstance {
+    pub fn show_resize_dialog(&mut self) {
+        self.resize_dialog.visible = true;
+        self.resize_dialog.size = [self.stage.map.width as i32, self.stage.map.height as i32];
+    }
+
+    pub(super) fn resize_window(&mut self, ui: &imgui::Ui) {
+        if !self.resize_dialog.visible {
+            return;
+        }
+
+        let mut visible = true;
+        let mut apply = false;
+
+        ui.window("Resize stage")
+            .opened(&mut visible)
+            .position([200.0, 120.0], Condition::FirstUseEver)
+            .always_auto_resize(true)
+            .build(|| {
+                let dialog = &mut self.resize_dialog;
+
+                ui.text(format!("Current size: {}x{}", self.stage.map.width, self.stage.map.height));
+                ui.input_int2("Size", &mut dialog.size).build();
+                // Entity positions are stored as signed 16-bit tile coordinates.
+                dialog.size[0] = dialog.size[0].clamp(1, i16::MAX as i32);
+                dialog.size[1] = dialog.size[1].clamp(1, i16::MAX as i32);
+
+                ui.text("Anchor");
+                for anchor_y in ResizeAnchor::ALL {
+                    for (idx, anchor_x) in ResizeAnchor::ALL.into_iter().enumerate() {
+                        if idx > 0 {
+                            ui.same_line();
+                        }
+
+                        let selected = dialog.anchor == (anchor_x, anchor_y);
+                        let id = anchor_y as usize * 3 + idx;
+                        let label = format!("{}##anchor_{}", if selected { "X" } else { " " }, id);
+                        if ui.button_with_size(label, [24.0, 24.0]) {
+                            dialog.anchor = (anchor_x, anchor_y);
+                        }
+                    }
+                }
+
+                ui.separator();
+                apply = ui.button("Resize");
+            });
+
+        if apply {
+            let [width, height] = self.resize_dialog.size;
+            self.resize_stage(width as u16, height as u16, self.resize_dialog.anchor);
+            visible = false;
+        }
+
+        self.resize_dialog.visible = visible;
+    }
+
+    /// Resizes the map and moves the entities along with the tiles. Entities which end up outside of the map
+    /// are removed.
+    pub fn resize_stage(&mut self, width: u16, height: u16, anchor: (ResizeAnchor, ResizeAnchor)) {
+        let map = &self.stage.map;
+        if (width, height) == (map.width, map.height) {
+            return;
+        }
+
+        let offset = (anchor.0.offset(map.width, width), anchor.1.offset(map.height, height));
+        let before = self.snapshot();
+
+        self.stage.map.resize(width, height, offset);
+        self.stage.tile_edits.clear();
+
+        self.npc_data.retain_mut(|data| {
+            let x = data.x as i32 + offset.0;
+            let y = data.y as i32 + offset.1;
+            data.x = x as i16;
+            data.y = y as i16;
+
+            x >= 0 && y >= 0 && x < width as i32 && y < height as i32
+        });
+        self.entity_previews_dirty = true;
+        self.entity_snapshot = None;
+        self.selected_entity = None;
+
+        self.history.push(EditorAction::Resize(Box::new(before), Box::new(self.snapshot())));
+    }
+}
@@ -0,0 +1,151 @@
+use std::io::Read;
+
+use imgui::Condition;
+use strum::IntoEnumIterator;
+
+use crate::common::Color;
+use crate::editor::{find_data_file, write_data_file, EditorInstance};
+use crate::framework::context::Context;
+use crate::framework::error::GameResult;
+use crate::framework::filesystem;
+use crate::game::shared_game_state::SharedGameState;
+use crate::game::stage::{Background, BackgroundType, NpcType, StageData, StageTableFormat, Tileset};
+
+/// Returns the table entry used for newly created stages.
+pub fn new_stage_data(map: &str) -> StageData {
+    StageData {
+        name: "New stage".to_owned(),
+        name_jp: "New stage".to_owned(),
+        map: map.to_owned(),
+        boss_no: 0,
+        tileset: Tileset::new("0"),
+        pxpack_data: None,
+        background: Background::new("bk0"),
+        background_type: BackgroundType::TiledStatic,
+        background_color: Color::from_rgb(0, 0, 32),
+        npc1: NpcType::new("0"),
+        npc2: NpcType::new("0"),
+    }
+}
+
+/// Writes the stage table in the format it was loaded from, or in specified one.
+pub fn save_stage_table(state: &SharedGameState, ctx: &Context, format: Option<StageTableFormat>) -> GameResult {
+    let format = format.or(state.stage_table_format).unwrap_or(StageTableFormat::Json);
+    let path = format.file_name();
+
+    let mut data = Vec::new();
+    StageData::write_stage_table(
+        &state.stages,
+        format,
+        state.constants.is_switch,
+        state.constants.stage_encoding,
+        &mut data,
+    )?;
+
+    // a table converted to another format is written next to the one it was loaded from
+    let file = match (find_data_file(state, ctx, path), state.stage_table_format) {
+        (Ok(file), _) => file,
+        (Err(_), Some(loaded_format)) if loaded_format != format => {
+            find_data_file(state, ctx, loaded_format.file_name())?.with_file_name(path)
+        }
+        (Err(err), _) => return Err(err),
+    };
+    write_data_file(&file, &data)
+}
+
+/// Window for editing an entry of the stage table.
+pub struct StageEntryWindow {
+    pub stage_id: Option<usize>,
+}
+
+impl StageEntryWindow {
+    pub fn new() -> StageEntryWindow {
+        StageEntryWindow { stage_id: None }
+    }
+
+    pub fn open(&mut self, stage_id: usize) {
+        self.stage_id = Some(stage_id);
+    }
+
+    /// Shows the window, returns the id of the stage if its entry has been changed.
+    pub fn action(&mut self, state: &mut SharedGameState, ui: &imgui::Ui) -> Option<usize> {
+        let stage_id = self.stage_id?;
+        let Some(stage) = state.stages.get_mut(stage_id) else {
+            self.stage_id = None;
+            return None;
+        };
+
+        let mut opened = true;
+        let mut changed = false;
+
+        ui.window(format!("Stage table entry #{}###stage_entry", stage_id))
+            .opened(&mut opened)
+            .position([320.0, 80.0], Condition::FirstUseEver)
+            .size([320.0, 0.0], Condition::FirstUseEver)
+            .build(|| {
+                changed |= ui.input_text("Name", &mut stage.name).build();
+                changed |= ui.input_text("Japanese name", &mut stage.name_jp).build();
+                changed |= ui.input_text("Map", &mut stage.map).build();
+                changed |= ui.input_text("Tileset", &mut stage.tileset.name).build();
+
+                let mut background = stage.background.name().to_owned();
+                if ui.input_text("Background", &mut background).build() {
+                    stage.background = Background::new(&background);
+                    changed = true;
+                }
+
+                let types: Vec<BackgroundType> = BackgroundType::iter().collect();
+                let names: Vec<String> = types.iter().map(|t| format!("{:?}", t)).collect();
+                let mut idx = types.iter().position(|&t| t == stage.background_type).unwrap_or(0);
+                if ui.combo_simple_string("Background type", &mut idx, &names) {
+                    stage.background_type = types[idx];
+                    changed = true;
+                }
+
+                let mut boss_no = stage.boss_no as i32;
+                if ui.input_int("Boss number", &mut boss_no).build() {
+                    stage.boss_no = boss_no.clamp(0, u8::MAX as i32) as u8;
+                    changed = true;
+                }
+
+                for (label, npc) in [("NPC sheet 1", &mut stage.npc1), ("NPC sheet 2", &mut stage.npc2)] {
+                    let mut name = npc.name().to_owned();
+                    if ui.input_text(label, &mut name).build() {
+                        *npc = NpcType::new(&name);
+                        changed = true;
+                    }
+                }
+            });
+
+        if !opened {
+            self.stage_id = None;
+        }
+
+        changed.then_some(stage_id)
+    }
+}
+
+impl EditorInstance {
+    /// Applies changes of the stage table entry to the opened stage.
+    pub fn set_stage_data(&mut self, data: &StageData, state: &SharedGameState, ctx: &mut Context) {
+        let tileset_changed = data.tileset != self.stage.data.tileset;
+        let pxpack_data = self.stage.data.pxpack_data.take();
+
+        self.stage.data = data.clone();
+        self.stage.data.pxpack_data = pxpack_data;
+        self.stage_textures.borrow_mut().update(&self.stage);
+
+        if tileset_changed && self.stage.data.pxpack_data.is_none() {
+            let path = ["Stage/", &data.tileset.name, ".pxa"].join("");
+            if let Ok(mut file) = filesystem::open_find(ctx, &state.constants.base_paths, path) {
+                let mut attrib = Vec::new();
+                if file.read_to_end(&mut attrib).is_ok() {
+                    attrib.resize(attrib.len().max(0x100), 0);
+                    self.stage.map.attrib = attrib;
+                }
+            }
+        }
+
+        self.entity_previews_dirty = true;
+    }
+}
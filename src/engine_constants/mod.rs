@@ -21,8 +21,9 @@ use crate::sound::pixtone::{Channel, Envelope, PixToneParameters, Waveform};
 use crate::sound::SoundManager;
 
 mod npcs;
+mod overrides;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
 pub struct PhysicsConsts {
     pub max_dash: i32,
     pub max_move: i32,
@@ -34,7 +35,7 @@ pub struct PhysicsConsts {
     pub jump: i32,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
 pub struct BoosterConsts {
     pub fuel: u32,
     pub b2_0_up: i32,
@@ -44,7 +45,7 @@ pub struct BoosterConsts {
     pub b2_0_right: i32,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
 pub struct PlayerConsts {
     pub life: u16,
     pub max_life: u16,
@@ -56,7 +57,7 @@ pub struct PlayerConsts {
     pub frames_bubble: [Rect<u16>; 2],
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
 pub struct GameConsts {
     pub intro_stage: u16,
     pub intro_event: u16,
@@ -67,7 +68,7 @@ pub struct GameConsts {
     pub tile_offset_x: i32,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct CaretConsts {
     pub offsets: [(i32, i32); 18],
     pub bubble_left_rects: Vec<Rect<u16>>,
@@ -97,7 +98,7 @@ pub struct TextureSizeTable {
     sizes: HashMap<String, (u16, u16)>,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
 pub struct BulletData {
     pub damage: u8,
     pub life: u8,
@@ -110,7 +111,7 @@ pub struct BulletData {
     pub display_bounds: Rect<u8>,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
 pub struct BulletRects {
     pub b001_snake_l1: [Rect<u16>; 8],
     pub b002_003_snake_l2_3: [Rect<u16>; 3],
@@ -147,14 +148,14 @@ pub struct BulletRects {
     pub b042_spur_trail_l3: [Rect<u16>; 6],
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct WeaponConsts {
     pub bullet_table: Vec<BulletData>,
     pub bullet_rects: BulletRects,
     pub level_table: [[u16; 3]; 14],
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
 pub struct WorldConsts {
     pub snack_rect: Rect<u16>,
     pub water_push_rect: Rect<u16>,
@@ -167,14 +168,14 @@ pub struct AnimatedFace {
     pub anim_frames: Vec<(u16, u16)>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct ExtraSoundtrack {
     pub id: String,
     pub path: String,
     pub available: bool,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
 pub struct TextScriptConsts {
     pub encoding: TextScriptEncoding,
     pub encrypted: bool,
@@ -207,7 +208,7 @@ pub struct TextScriptConsts {
     pub fade_ticks: i8,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct TitleConsts {
     pub intro_text: String,
     pub logo_rect: Rect<u16>,
@@ -228,7 +229,7 @@ pub struct TitleConsts {
     pub cursor_sue: [Rect<u16>; 4],
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct GamepadConsts {
     pub button_rects: HashMap<Button, [Rect<u16>; 4]>,
     pub axis_rects: HashMap<Axis, [Rect<u16>; 4]>,
@@ -245,11 +246,15 @@ impl GamepadConsts {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct EngineConstants {
+    #[serde(skip)]
     pub base_paths: Vec<String>,
+    #[serde(skip)]
     pub is_cs_plus: bool,
+    #[serde(skip)]
     pub is_switch: bool,
+    #[serde(skip)]
     pub is_demo: bool,
     pub supports_og_textures: bool,
     pub has_difficulty_menu: bool,
@@ -261,6 +266,7 @@ pub struct EngineConstants {
     pub world: WorldConsts,
     pub npc: NPCConsts,
     pub weapon: WeaponConsts,
+    #[serde(skip, default = "CaseInsensitiveHashMap::new")]
     pub tex_sizes: CaseInsensitiveHashMap<(u16, u16)>,
     pub textscript: TextScriptConsts,
     pub title: TitleConsts,
@@ -272,12 +278,18 @@ pub struct EngineConstants {
     pub organya_paths: Vec<String>,
     pub credit_illustration_paths: Vec<String>,
     pub player_skin_paths: Vec<String>,
+    #[serde(skip)]
     pub animated_face_table: Vec<AnimatedFace>,
+    #[serde(skip)]
     pub string_table: HashMap<String, String>,
     pub missile_flags: Vec<u16>,
+    #[serde(skip)]
     pub locales: Vec<Locale>,
     pub gamepad: GamepadConsts,
     pub stage_encoding: Option<TextScriptEncoding>,
+    /// Restores the values overwritten by the currently applied `constants.json` files.
+    #[serde(skip)]
+    json_undo: Option<serde_json::Value>,
 }
 
 impl EngineConstants {
@@ -1602,6 +1614,7 @@ impl EngineConstants {
                 ]),
             },
             stage_encoding: None,
+            json_undo: None,
         }
     }
 
@@ -1694,7 +1707,15 @@ impl EngineConstants {
         self.title.logo_splash_rect = Rect { left: 224, top: 0, right: 320, bottom: 48 };
     }
 
-    pub fn rebuild_path_list(&mut self, mod_path: Option<String>, season: Season, settings: &Settings) {
+    /// Rebuilds the list of data directories. `challenge_path` is the directory of the selected challenge, and
+    /// `mod_paths` are the directories of all loaded mods and their dependencies, from the highest priority one.
+    pub fn rebuild_path_list(
+        &mut self,
+        challenge_path: Option<&String>,
+        mod_paths: &[String],
+        season: Season,
+        settings: &Settings,
+    ) {
         self.base_paths.clear();
         self.base_paths.push("/builtin/builtin_data/".to_owned());
         self.base_paths.push("/".to_owned());
@@ -1721,18 +1742,17 @@ impl EngineConstants {
             }
         }
 
-        if let Some(mut mod_path) = mod_path {
+        for mod_path in mod_paths.iter().rev() {
             self.base_paths.insert(0, mod_path.clone());
             if settings.original_textures {
-                mod_path.push_str("ogph/");
-                self.base_paths.insert(0, mod_path);
+                self.base_paths.insert(0, [mod_path, "ogph/"].join(""));
             }
+        }
 
-            // Nicalis left a landmine of a file in the original graphics for the nemesis challenge
-            // It has 17 colors defined for a 4-bit color depth bitmap
-            if self.is_cs_plus && !self.is_switch {
-                self.base_paths.retain(|path| !path.contains("ogph/"));
-            }
+        // Nicalis left a landmine of a file in the original graphics for the nemesis challenge
+        // It has 17 colors defined for a 4-bit color depth bitmap
+        if challenge_path.is_some() && self.is_cs_plus && !self.is_switch {
+            self.base_paths.retain(|path| !path.contains("ogph/"));
         }
     }
 
@@ -1811,8 +1831,6 @@ impl EngineConstants {
         Ok(())
     }
 
-    pub fn apply_constant_json_files(&mut self) {}
-
     pub fn load_texture_size_hints(&mut self, ctx: &mut Context) -> GameResult {
         if let Ok(file) = filesystem::open_find(ctx, &self.base_paths, "texture_sizes.json") {
             match serde_json::from_reader::<_, TextureSizeTable>(file) {
@@ -0,0 +1,255 @@
+use serde_json::{Map, Value};
+
+use crate::engine_constants::EngineConstants;
+use crate::framework::context::Context;
+use crate::framework::error::{GameError, GameResult};
+use crate::framework::filesystem;
+
+impl EngineConstants {
+    /// Deep-merges the `constants.json` files of all data directories over the constants, starting from the
+    /// directory with lowest priority. Values overridden by previously applied files are reverted first.
+    ///
+    /// Objects are merged field by field, arrays can be either replaced or patched per element using an object with
+    /// indices as keys, eg. `{ "weapon": { "level_table": { "2": [20, 30, 40] } } }`.
+    pub fn apply_constant_json_files(&mut self, ctx: &mut Context) -> GameResult {
+        let mut patches = Vec::new();
+        for root in self.base_paths.iter().rev() {
+            let path = [root, "constants.json"].join("");
+            if let Ok(file) = filesystem::open(ctx, &path) {
+                let patch: Value = serde_json::from_reader(file)
+                    .map_err(|err| GameError::ParseError(format!("{}: {}", path, err)))?;
+                patches.push((path, patch));
+            }
+        }
+
+        if patches.is_empty() && self.json_undo.is_none() {
+            return Ok(());
+        }
+
+        self.apply_json_patches(&patches).map_err(GameError::ParseError)?;
+
+        for (path, _) in &patches {
+            log::info!("Applied engine constants from {}.", path);
+        }
+
+        Ok(())
+    }
+
+    /// Restores the values overridden by `constants.json` files, so the files of a previously loaded mod
+    /// don't leak into the next one.
+    pub fn revert_constant_json_files(&mut self) -> GameResult {
+        if self.json_undo.is_some() {
+            self.apply_json_patches(&[]).map_err(GameError::ParseError)?;
+        }
+
+        Ok(())
+    }
+
+    fn apply_json_patches(&mut self, patches: &[(String, Value)]) -> Result<(), String> {
+        let mut value = serde_json::to_value(&*self).map_err(|err| err.to_string())?;
+        if let Some(undo) = &self.json_undo {
+            merge_json(&mut value, undo, "")?;
+        }
+
+        let base = value.clone();
+        let mut undo = Value::Object(Map::new());
+
+        for (path, patch) in patches {
+            let before = value.clone();
+            merge_json(&mut value, patch, "").map_err(|err| format!("{}: {}", path, err))?;
+
+            if let Err(err) = serde_json::from_value::<EngineConstants>(value.clone()) {
+                let (field, err) = find_invalid_field(&before, patch, &mut Vec::new())
+                    .unwrap_or_else(|| ("<root>".to_owned(), err.to_string()));
+                return Err(format!("{}: {}: {}", path, field, err));
+            }
+
+            merge_patches(&mut undo, undo_patch(&base, patch));
+        }
+
+        let mut constants: EngineConstants = serde_json::from_value(value).map_err(|err| err.to_string())?;
+        constants.base_paths = std::mem::take(&mut self.base_paths);
+        constants.is_cs_plus = self.is_cs_plus;
+        constants.is_switch = self.is_switch;
+        constants.is_demo = self.is_demo;
+        std::mem::swap(&mut constants.tex_sizes, &mut self.tex_sizes);
+        constants.animated_face_table = std::mem::take(&mut self.animated_face_table);
+        constants.string_table = std::mem::take(&mut self.string_table);
+        constants.locales = std::mem::take(&mut self.locales);
+        constants.json_undo = (!patches.is_empty()).then_some(undo);
+
+        *self = constants;
+        Ok(())
+    }
+}
+
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+fn value_kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Merges `patch` over `base`, `path` is the location of `base` used in error messages.
+fn merge_json(base: &mut Value, patch: &Value, path: &str) -> Result<(), String> {
+    match (base, patch) {
+        (Value::Object(base), Value::Object(patch)) => {
+            for (key, value) in patch {
+                let child_path = join_path(path, key);
+                let Some(child) = base.get_mut(key) else {
+                    return Err(format!("{}: unknown field", child_path));
+                };
+
+                merge_json(child, value, &child_path)?;
+            }
+        }
+        (Value::Array(base), Value::Object(patch)) => {
+            let len = base.len();
+            for (key, value) in patch {
+                let child_path = join_path(path, key);
+                let Some(child) = key.parse::<usize>().ok().and_then(|idx| base.get_mut(idx)) else {
+                    return Err(format!("{}: expected an index lower than {}", child_path, len));
+                };
+
+                merge_json(child, value, &child_path)?;
+            }
+        }
+        (base, patch) => {
+            if !base.is_null() && !patch.is_null() && value_kind(base) != value_kind(patch) {
+                let path = if path.is_empty() { "<root>" } else { path };
+                return Err(format!("{}: expected {}, found {}", path, value_kind(base), value_kind(patch)));
+            }
+
+            *base = patch.clone();
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns a patch which restores the values of `base` overwritten by `patch`.
+fn undo_patch(base: &Value, patch: &Value) -> Value {
+    let Value::Object(fields) = patch else {
+        return base.clone();
+    };
+
+    let undo = fields.iter().filter_map(|(key, value)| {
+        let child = match base {
+            Value::Object(base) => base.get(key),
+            Value::Array(base) => key.parse::<usize>().ok().and_then(|idx| base.get(idx)),
+            _ => None,
+        }?;
+
+        Some((key.clone(), undo_patch(child, value)))
+    });
+
+    match base {
+        Value::Object(_) | Value::Array(_) => Value::Object(undo.collect()),
+        _ => base.clone(),
+    }
+}
+
+/// Combines two patches into one, values from `patch` take priority.
+fn merge_patches(base: &mut Value, patch: Value) {
+    match (base, patch) {
+        (Value::Object(base), Value::Object(patch)) => {
+            for (key, value) in patch {
+                match base.get_mut(&key) {
+                    Some(child) => merge_patches(child, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        // `base` already restores the whole value.
+        (base, Value::Object(_)) if !base.is_object() => {}
+        (base, patch) => *base = patch,
+    }
+}
+
+/// Wraps `value` in objects so it can be merged at `path`.
+fn nest(path: &[String], value: &Value) -> Value {
+    path.iter().rev().fold(value.clone(), |value, key| Value::Object(Map::from_iter([(key.clone(), value)])))
+}
+
+/// Narrows down the deserialization error of a patch by applying its fields one at a time. Returns the path to the
+/// innermost field which can't be deserialized, along with the error.
+fn find_invalid_field(base: &Value, patch: &Value, path: &mut Vec<String>) -> Option<(String, String)> {
+    let Value::Object(fields) = patch else {
+        return None;
+    };
+
+    for (key, value) in fields {
+        path.push(key.clone());
+
+        let mut merged = base.clone();
+        if merge_json(&mut merged, &nest(path, value), "").is_ok() {
+            if let Err(err) = serde_json::from_value::<EngineConstants>(merged) {
+                return find_invalid_field(base, value, path).or_else(|| Some((path.join("."), err.to_string())));
+            }
+        }
+
+        path.pop();
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_apply_json_patches() {
+        let mut constants = EngineConstants::defaults();
+        let jump = constants.player.water_physics.jump;
+
+        let patch = json!({
+            "player": { "air_physics": { "jump": 0x600 }, "max_life": 10 },
+            "weapon": { "level_table": { "2": [20, 30, 40] } },
+        });
+        constants.apply_json_patches(&[("constants.json".to_owned(), patch)]).unwrap();
+
+        assert_eq!(constants.player.air_physics.jump, 0x600);
+        assert_eq!(constants.player.water_physics.jump, jump);
+        assert_eq!(constants.player.max_life, 10);
+        assert_eq!(constants.weapon.level_table[2], [20, 30, 40]);
+
+        // reapplying without any files reverts the overrides
+        constants.apply_json_patches(&[]).unwrap();
+        assert_eq!(constants.player.max_life, EngineConstants::defaults().player.max_life);
+        assert_eq!(constants.weapon.level_table, EngineConstants::defaults().weapon.level_table);
+    }
+
+    #[test]
+    fn test_json_patch_errors() {
+        let mut constants = EngineConstants::defaults();
+
+        let patch = json!({ "player": { "air_physics": { "jumpp": 1 } } });
+        let err = constants.apply_json_patches(&[("constants.json".to_owned(), patch)]).unwrap_err();
+        assert!(err.starts_with("constants.json: player.air_physics.jumpp: "), "{}", err);
+
+        let patch = json!({ "booster": { "fuel": -1 } });
+        let err = constants.apply_json_patches(&[("constants.json".to_owned(), patch)]).unwrap_err();
+        assert!(err.starts_with("constants.json: booster.fuel: "), "{}", err);
+
+        let patch = json!({ "weapon": { "level_table": { "14": [0, 0, 0] } } });
+        let err = constants.apply_json_patches(&[("constants.json".to_owned(), patch)]).unwrap_err();
+        assert!(err.starts_with("constants.json: weapon.level_table.14: "), "{}", err);
+    }
+}
@@ -5,7 +5,7 @@ use imgui::DrawData;
 
 use crate::common::{Color, Rect};
 use crate::framework::context::Context;
-use crate::framework::error::GameResult;
+use crate::framework::error::{GameError, GameResult};
 use crate::framework::graphics::{BlendMode, VSyncMode};
 use crate::game::Game;
 
@@ -25,6 +25,18 @@ pub enum BackendShader {
     Texture,
 }
 
+/// A fragment shader applied to the final game canvas before it's presented.
+///
+/// `source` is the body of a GLSL shader without the `#version` line, the renderer prepends a header declaring
+/// the `Texture` sampler, the `SourceSize`, `OutputSize` and `Time` uniforms and the `Frag_UV` and `Frag_Color`
+/// varyings. Passes before the first one with `upscale` set run at the native resolution of the canvas.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PostProcessPass {
+    pub name: String,
+    pub source: String,
+    pub upscale: bool,
+}
+
 pub trait Backend {
     fn create_event_loop(&self, ctx: &Context) -> GameResult<Box<dyn BackendEventLoop>>;
 
@@ -87,6 +99,20 @@ pub trait BackendRenderer {
         shader: BackendShader,
     ) -> GameResult;
 
+    fn supports_post_process(&self) -> bool {
+        false
+    }
+
+    /// Sets the chain of post-processing passes, `scale` is the ratio between the screen and the native canvas size.
+    fn set_post_process(&mut self, _passes: &[PostProcessPass], _scale: f32) -> GameResult {
+        Err(GameError::RenderError("Post-processing is not supported by this renderer.".to_string()))
+    }
+
+    /// Reads back the contents of the screen as RGBA8 pixels, starting from the top row.
+    fn read_screen(&mut self) -> GameResult<(u16, u16, Vec<u8>)> {
+        Err(GameError::RenderError("Reading back the screen is not supported by this renderer.".to_string()))
+    }
+
     fn as_any(&self) -> &dyn Any;
 }
 
@@ -108,6 +108,21 @@ impl From<jni::errors::Error> for GameError {
     }
 }
 
+#[cfg(feature = "scripting-lua")]
+impl From<mlua::Error> for GameError {
+    fn from(e: mlua::Error) -> GameError {
+        let errstr = format!("Lua error: {}", e);
+        GameError::ParseError(errstr)
+    }
+}
+
+impl From<zip::result::ZipError> for GameError {
+    fn from(e: zip::result::ZipError) -> GameError {
+        let errstr = format!("Zip archive error: {}", e);
+        GameError::ResourceLoadError(errstr)
+    }
+}
+
 impl From<strum::ParseError> for GameError {
     fn from(s: strum::ParseError) -> GameError {
         let errstr = format!("Strum parse error: {}", s);
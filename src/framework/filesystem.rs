@@ -229,6 +229,22 @@ impl Filesystem {
     pub fn unmount_user_vfs(&mut self, root: &PathBuf) {
         self.user_vfs.remove(root);
     }
+
+    /// Mounts a zip archive from the resource filesystem, so its contents show up in `mount_point`.
+    /// The archive is mounted in front of the other filesystems, so it shadows the archive file itself.
+    pub fn mount_zip(&mut self, archive: &path::Path, mount_point: &str) -> GameResult {
+        let file = self.vfs.open(archive)?;
+        let zip_fs = vfs::ZipFS::new(file, mount_point)?;
+        trace!("Mounting new zip archive: {:?}", zip_fs);
+        self.vfs.push_front(Box::new(zip_fs));
+
+        Ok(())
+    }
+
+    /// Returns the directories of the mounted resource filesystems which are backed by a physical directory.
+    pub fn physical_roots(&self) -> Vec<PathBuf> {
+        self.vfs.roots().iter().filter_map(|vfs| vfs.to_path_buf()).collect()
+    }
 }
 
 /// Opens the given path and returns the resulting `File`
@@ -371,6 +387,16 @@ pub fn read_dir_find<P: AsRef<path::Path>>(
     Ok(Box::new(files.into_iter().flatten()))
 }
 
+/// Mounts a zip archive from the resource filesystem, so its contents show up in `mount_point`.
+pub fn mount_zip<P: AsRef<path::Path>>(ctx: &mut Context, archive: P, mount_point: &str) -> GameResult {
+    ctx.filesystem.mount_zip(archive.as_ref(), mount_point)
+}
+
+/// Returns the directories of the mounted resource filesystems which are backed by a physical directory.
+pub fn physical_roots(ctx: &Context) -> Vec<PathBuf> {
+    ctx.filesystem.physical_roots()
+}
+
 /// Adds the given (absolute) path to the list of directories
 /// it will search to look for resources.
 ///
@@ -1,5 +1,5 @@
 use crate::common::{Color, Rect};
-use crate::framework::backend::{BackendShader, BackendTexture, VertexData};
+use crate::framework::backend::{BackendShader, BackendTexture, PostProcessPass, VertexData};
 use crate::framework::context::Context;
 use crate::framework::error::{GameError, GameResult};
 
@@ -176,6 +176,15 @@ pub fn prepare_draw(ctx: &mut Context) -> GameResult {
     Err(GameError::RenderError("Rendering backend hasn't been initialized yet.".to_string()))
 }
 
+/// Reads back the last drawn frame, returns its width, height and RGBA8 pixels.
+pub fn read_screen(ctx: &mut Context) -> GameResult<(u16, u16, Vec<u8>)> {
+    if let Some(renderer) = &mut ctx.renderer {
+        return renderer.read_screen();
+    }
+
+    Err(GameError::RenderError("Rendering backend hasn't been initialized yet.".to_string()))
+}
+
 pub fn supports_vertex_draw(ctx: &Context) -> GameResult<bool> {
     if let Some(renderer) = ctx.renderer.as_ref() {
         return Ok(renderer.supports_vertex_draw());
@@ -184,6 +193,22 @@ pub fn supports_vertex_draw(ctx: &Context) -> GameResult<bool> {
     Err(GameError::RenderError("Rendering backend hasn't been initialized yet.".to_string()))
 }
 
+pub fn supports_post_process(ctx: &Context) -> GameResult<bool> {
+    if let Some(renderer) = ctx.renderer.as_ref() {
+        return Ok(renderer.supports_post_process());
+    }
+
+    Err(GameError::RenderError("Rendering backend hasn't been initialized yet.".to_string()))
+}
+
+pub fn set_post_process(ctx: &mut Context, passes: &[PostProcessPass], scale: f32) -> GameResult {
+    if let Some(renderer) = &mut ctx.renderer {
+        return renderer.set_post_process(passes, scale);
+    }
+
+    Err(GameError::RenderError("Rendering backend hasn't been initialized yet.".to_string()))
+}
+
 pub fn draw_triangle_list(
     ctx: &mut Context,
     vertices: &[VertexData],
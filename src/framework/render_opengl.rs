@@ -6,11 +6,14 @@ use std::mem;
 use std::mem::MaybeUninit;
 use std::ptr::null;
 use std::sync::Arc;
+use std::time::Instant;
 
 use imgui::{DrawCmd, DrawCmdParams, DrawData, DrawIdx, DrawVert, TextureId, Ui};
 
 use crate::common::{Color, Rect};
-use crate::framework::backend::{BackendRenderer, BackendShader, BackendTexture, SpriteBatchCommand, VertexData};
+use crate::framework::backend::{
+    BackendRenderer, BackendShader, BackendTexture, PostProcessPass, SpriteBatchCommand, VertexData,
+};
 use crate::framework::context::Context;
 use crate::framework::error::GameError;
 use crate::framework::error::GameError::RenderError;
@@ -305,10 +308,12 @@ const VERTEX_SHADER_BASIC: &str = include_str!("shaders/opengl/vertex_basic_110.
 const FRAGMENT_SHADER_TEXTURED: &str = include_str!("shaders/opengl/fragment_textured_110.glsl");
 const FRAGMENT_SHADER_COLOR: &str = include_str!("shaders/opengl/fragment_color_110.glsl");
 const FRAGMENT_SHADER_WATER: &str = include_str!("shaders/opengl/fragment_water_110.glsl");
+const POST_PROCESS_HEADER: &str = include_str!("shaders/opengl/post_process_header_110.glsl");
 
 const VERTEX_SHADER_BASIC_GLES: &str = include_str!("shaders/opengles/vertex_basic_100.glsl");
 const FRAGMENT_SHADER_TEXTURED_GLES: &str = include_str!("shaders/opengles/fragment_textured_100.glsl");
 const FRAGMENT_SHADER_COLOR_GLES: &str = include_str!("shaders/opengles/fragment_color_100.glsl");
+const POST_PROCESS_HEADER_GLES: &str = include_str!("shaders/opengles/post_process_header_100.glsl");
 
 #[derive(Copy, Clone)]
 struct RenderShader {
@@ -318,6 +323,8 @@ struct RenderShader {
     scale: GLint,
     time: GLint,
     frame_offset: GLint,
+    source_size: GLint,
+    output_size: GLint,
     position: GLuint,
     uv: GLuint,
     color: GLuint,
@@ -332,6 +339,8 @@ impl Default for RenderShader {
             scale: 0,
             time: 0,
             frame_offset: 0,
+            source_size: 0,
+            output_size: 0,
             position: 0,
             uv: 0,
             color: 0,
@@ -395,6 +404,8 @@ impl RenderShader {
             shader.scale = gl.gl.GetUniformLocation(shader.program_id, b"Scale\0".as_ptr() as _) as _;
             shader.time = gl.gl.GetUniformLocation(shader.program_id, b"Time\0".as_ptr() as _) as _;
             shader.frame_offset = gl.gl.GetUniformLocation(shader.program_id, b"FrameOffset\0".as_ptr() as _) as _;
+            shader.source_size = gl.gl.GetUniformLocation(shader.program_id, b"SourceSize\0".as_ptr() as _) as _;
+            shader.output_size = gl.gl.GetUniformLocation(shader.program_id, b"OutputSize\0".as_ptr() as _) as _;
             shader.position = gl.gl.GetAttribLocation(shader.program_id, b"Position\0".as_ptr() as _) as _;
             shader.uv = gl.gl.GetAttribLocation(shader.program_id, b"UV\0".as_ptr() as _) as _;
             shader.color = gl.gl.GetAttribLocation(shader.program_id, b"Color\0".as_ptr() as _) as _;
@@ -441,6 +452,103 @@ impl RenderShader {
     }
 }
 
+/// An intermediate render target of the post-processing chain.
+#[derive(Default)]
+struct PostTarget {
+    texture: GLuint,
+    framebuffer: GLuint,
+    size: (u32, u32),
+}
+
+impl PostTarget {
+    /// Creates the target on first use and reallocates it if the size has changed.
+    unsafe fn resize(&mut self, gl: &Gl, size: (u32, u32)) {
+        if self.texture == 0 {
+            self.texture = return_param(|x| gl.gl.GenTextures(1, x));
+            gl.gl.BindTexture(gl::TEXTURE_2D, self.texture);
+            gl.gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as _);
+            gl.gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as _);
+            gl.gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as _);
+            gl.gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as _);
+            gl.gl.BindTexture(gl::TEXTURE_2D, 0);
+
+            self.framebuffer = return_param(|x| gl.gl.GenFramebuffers(1, x));
+            self.size = (0, 0);
+        }
+
+        if self.size != size {
+            self.size = size;
+
+            gl.gl.BindTexture(gl::TEXTURE_2D, self.texture);
+            gl.gl.TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA as _,
+                size.0 as _,
+                size.1 as _,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                null() as _,
+            );
+            gl.gl.BindTexture(gl::TEXTURE_2D, 0);
+
+            gl.gl.BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+            gl.gl.FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, self.texture, 0);
+        }
+    }
+}
+
+unsafe fn set_texture_filter(gl: &Gl, texture: GLuint, filter: GLenum) {
+    gl.gl.BindTexture(gl::TEXTURE_2D, texture);
+    gl.gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter as _);
+    gl.gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter as _);
+    gl.gl.BindTexture(gl::TEXTURE_2D, 0);
+}
+
+/// Draws the texture over the whole viewport.
+unsafe fn draw_fullscreen(
+    gl: &Gl,
+    shader: &RenderShader,
+    vbo: GLuint,
+    texture: GLuint,
+    source_size: (u32, u32),
+    output_size: (u32, u32),
+    time: f32,
+) -> GameResult {
+    let matrix = [[2.0f32, 0.0, 0.0, 0.0], [0.0, -2.0, 0.0, 0.0], [0.0, 0.0, -1.0, 0.0], [-1.0, 1.0, 0.0, 1.0]];
+
+    shader.bind_attrib_pointer(gl, vbo)?;
+    gl.gl.UniformMatrix4fv(shader.proj_mtx, 1, gl::FALSE, matrix.as_ptr() as _);
+    gl.gl.Uniform1i(shader.texture, 0);
+    gl.gl.Uniform2f(shader.source_size, source_size.0 as f32, source_size.1 as f32);
+    gl.gl.Uniform2f(shader.output_size, output_size.0 as f32, output_size.1 as f32);
+    gl.gl.Uniform1f(shader.time, time);
+
+    let color = (255, 255, 255, 255);
+    let vertices = [
+        VertexData { position: (0.0, 1.0), uv: (0.0, 0.0), color },
+        VertexData { position: (0.0, 0.0), uv: (0.0, 1.0), color },
+        VertexData { position: (1.0, 0.0), uv: (1.0, 1.0), color },
+        VertexData { position: (0.0, 1.0), uv: (0.0, 0.0), color },
+        VertexData { position: (1.0, 0.0), uv: (1.0, 1.0), color },
+        VertexData { position: (1.0, 1.0), uv: (1.0, 0.0), color },
+    ];
+
+    gl.gl.BindTexture(gl::TEXTURE_2D, texture);
+    gl.gl.BufferData(
+        gl::ARRAY_BUFFER,
+        (vertices.len() * mem::size_of::<VertexData>()) as _,
+        vertices.as_ptr() as _,
+        gl::STREAM_DRAW,
+    );
+    gl.gl.DrawArrays(gl::TRIANGLES, 0, vertices.len() as _);
+    gl.gl.BindTexture(gl::TEXTURE_2D, 0);
+    gl.gl.BindBuffer(gl::ARRAY_BUFFER, 0);
+
+    Ok(())
+}
+
 struct RenderData {
     initialized: bool,
     tex_shader: RenderShader,
@@ -453,6 +561,13 @@ struct RenderData {
     surf_framebuffer: GLuint,
     surf_texture: GLuint,
     last_size: (u32, u32),
+    /// Passes the post-processing chain was last built from.
+    post_process_passes: Vec<PostProcessPass>,
+    /// Compiled post-processing shaders, along with whether the pass upscales the image.
+    post_process: Vec<(bool, RenderShader)>,
+    post_process_scale: f32,
+    post_targets: [PostTarget; 2],
+    start_time: Instant,
 }
 
 impl RenderData {
@@ -469,6 +584,11 @@ impl RenderData {
             surf_framebuffer: 0,
             surf_texture: 0,
             last_size: (320, 240),
+            post_process_passes: Vec::new(),
+            post_process: Vec::new(),
+            post_process_scale: 1.0,
+            post_targets: Default::default(),
+            start_time: Instant::now(),
         }
     }
 
@@ -617,6 +737,61 @@ impl OpenGLRenderer {
 
         Some((&mut self.refs, gl))
     }
+
+    /// Runs the post-processing chain on the canvas and returns the texture holding the result.
+    unsafe fn apply_post_process(&mut self, gl: &Gl) -> GameResult<GLuint> {
+        let render_data = &mut self.render_data;
+        let screen_size = render_data.last_size;
+        let scale = render_data.post_process_scale;
+        let native_size =
+            (((screen_size.0 as f32 / scale) as u32).max(1), ((screen_size.1 as f32 / scale) as u32).max(1));
+        let time = render_data.start_time.elapsed().as_secs_f32();
+
+        let blend = gl.gl.IsEnabled(gl::BLEND) == gl::TRUE;
+        gl.gl.Disable(gl::BLEND);
+
+        // The canvas is drawn at the screen resolution, so it's scaled back down to the original pixels first.
+        let target = &mut render_data.post_targets[0];
+        target.resize(gl, native_size);
+        gl.gl.BindFramebuffer(gl::FRAMEBUFFER, target.framebuffer);
+        gl.gl.Viewport(0, 0, native_size.0 as _, native_size.1 as _);
+        draw_fullscreen(
+            gl,
+            &render_data.tex_shader,
+            render_data.vbo,
+            render_data.surf_texture,
+            screen_size,
+            native_size,
+            time,
+        )?;
+
+        let mut source = (target.texture, native_size);
+        let mut upscaled = false;
+
+        for (idx, (upscale, shader)) in render_data.post_process.iter().enumerate() {
+            upscaled |= *upscale;
+            let size = if upscaled { screen_size } else { native_size };
+
+            let target = &mut render_data.post_targets[(idx + 1) % 2];
+            target.resize(gl, size);
+            gl.gl.BindFramebuffer(gl::FRAMEBUFFER, target.framebuffer);
+            gl.gl.Viewport(0, 0, size.0 as _, size.1 as _);
+
+            set_texture_filter(gl, source.0, gl::LINEAR);
+            draw_fullscreen(gl, shader, render_data.vbo, source.0, source.1, size, time)?;
+            set_texture_filter(gl, source.0, gl::NEAREST);
+
+            source = (target.texture, size);
+        }
+
+        gl.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        gl.gl.Viewport(0, 0, screen_size.0 as _, screen_size.1 as _);
+        if blend {
+            gl.gl.Enable(gl::BLEND);
+        }
+
+        Ok(source.0)
+    }
 }
 
 impl BackendRenderer for OpenGLRenderer {
@@ -647,6 +822,12 @@ impl BackendRenderer for OpenGLRenderer {
 
         unsafe {
             if let Some((_, gl)) = self.get_context() {
+                let texture = if self.render_data.post_process.is_empty() {
+                    self.render_data.surf_texture
+                } else {
+                    self.apply_post_process(gl)?
+                };
+
                 gl.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
                 gl.gl.ClearColor(0.0, 0.0, 0.0, 1.0);
                 gl.gl.Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
@@ -667,12 +848,7 @@ impl BackendRenderer for OpenGLRenderer {
                     VertexData { position: (1.0, 1.0), uv: (1.0, 0.0), color },
                 ];
 
-                self.draw_arrays_tex_id(
-                    gl::TRIANGLES,
-                    &vertices,
-                    self.render_data.surf_texture,
-                    BackendShader::Texture,
-                )?;
+                self.draw_arrays_tex_id(gl::TRIANGLES, &vertices, texture, BackendShader::Texture)?;
 
                 gl.gl.Finish();
             }
@@ -1199,6 +1375,53 @@ impl BackendRenderer for OpenGLRenderer {
         true
     }
 
+    fn supports_post_process(&self) -> bool {
+        true
+    }
+
+    fn set_post_process(&mut self, passes: &[PostProcessPass], scale: f32) -> GameResult {
+        self.render_data.post_process_scale = scale.max(1.0);
+        if self.render_data.post_process_passes == passes {
+            return Ok(());
+        }
+
+        let gles2 = self.refs.gles2_mode;
+        let Some((_, gl)) = self.get_context() else {
+            return Err(RenderError("No OpenGL context available!".to_string()));
+        };
+
+        let (vertex_shader, header) = if gles2 {
+            (VERTEX_SHADER_BASIC_GLES, POST_PROCESS_HEADER_GLES)
+        } else {
+            (VERTEX_SHADER_BASIC, POST_PROCESS_HEADER)
+        };
+
+        for (_, shader) in self.render_data.post_process.drain(..) {
+            unsafe {
+                gl.gl.DeleteProgram(shader.program_id);
+            }
+        }
+
+        let mut errors = Vec::new();
+        for pass in passes {
+            // The last character of the source is dropped by the compiler, as it's expected to be a newline.
+            let source = [header, &pass.source, "\n"].join("");
+
+            match RenderShader::compile(gl, vertex_shader, &source) {
+                Ok(shader) => self.render_data.post_process.push((pass.upscale, shader)),
+                Err(err) => errors.push(format!("{}: {}", pass.name, err)),
+            }
+        }
+
+        self.render_data.post_process_passes = passes.to_vec();
+
+        if !errors.is_empty() {
+            return Err(RenderError(format!("Failed to compile post-processing shaders: {}", errors.join("; "))));
+        }
+
+        Ok(())
+    }
+
     fn draw_triangle_list(
         &mut self,
         vertices: &[VertexData],
@@ -1208,6 +1431,37 @@ impl BackendRenderer for OpenGLRenderer {
         self.draw_arrays(gl::TRIANGLES, vertices, texture, shader)
     }
 
+    fn read_screen(&mut self) -> GameResult<(u16, u16, Vec<u8>)> {
+        if let Some((_, gl)) = self.get_context() {
+            let (width, height) = self.render_data.last_size;
+            let mut pixels = vec![0u8; width as usize * height as usize * 4];
+
+            unsafe {
+                let current_framebuffer = return_param(|x| gl.gl.GetIntegerv(gl::FRAMEBUFFER_BINDING, x)) as u32;
+
+                gl.gl.BindFramebuffer(gl::FRAMEBUFFER, self.render_data.surf_framebuffer);
+                gl.gl.ReadPixels(
+                    0,
+                    0,
+                    width as _,
+                    height as _,
+                    gl::RGBA,
+                    gl::UNSIGNED_BYTE,
+                    pixels.as_mut_ptr() as _,
+                );
+                gl.gl.BindFramebuffer(gl::FRAMEBUFFER, current_framebuffer);
+            }
+
+            // OpenGL returns the rows starting from the bottom one.
+            let stride = width as usize * 4;
+            let pixels = pixels.chunks_exact(stride).rev().flatten().copied().collect();
+
+            Ok((width as u16, height as u16, pixels))
+        } else {
+            Err(RenderError("No OpenGL context available!".to_string()))
+        }
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
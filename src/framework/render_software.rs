@@ -0,0 +1,653 @@
+//! Renderer rasterizing everything on the CPU into an RGBA framebuffer. It doesn't need a GPU, so it can be used to
+//! render headlessly and to read the rendered frames back.
+
+use std::any::Any;
+use std::cell::{RefCell, UnsafeCell};
+use std::rc::Rc;
+
+use imgui::{DrawData, TextureId, Ui};
+
+use crate::common::{Color, Rect};
+use crate::framework::backend::{BackendRenderer, BackendShader, BackendTexture, SpriteBatchCommand, VertexData};
+use crate::framework::error::GameError::RenderError;
+use crate::framework::error::GameResult;
+use crate::framework::graphics::BlendMode;
+
+/// An RGBA8 image used as the framebuffer and as texture storage.
+#[derive(Clone)]
+pub struct Surface {
+    width: u16,
+    height: u16,
+    /// Non-premultiplied pixels, row by row starting from the top.
+    pixels: Vec<u8>,
+}
+
+impl Surface {
+    pub fn new(width: u16, height: u16) -> Surface {
+        Surface { width, height, pixels: vec![0; width as usize * height as usize * 4] }
+    }
+
+    /// Creates a surface from RGBA8 pixel data, missing pixels are left transparent.
+    pub fn from_rgba(width: u16, height: u16, data: &[u8]) -> Surface {
+        let mut surface = Surface::new(width, height);
+        let len = surface.pixels.len().min(data.len());
+        surface.pixels[..len].copy_from_slice(&data[..len]);
+        surface
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        let offset = (y * self.width as usize + x) * 4;
+        let mut pixel = [0; 4];
+        pixel.copy_from_slice(&self.pixels[offset..offset + 4]);
+        pixel
+    }
+
+    /// Returns the pixel at the given coordinates, wrapping around the edges like GL_REPEAT.
+    fn sample(&self, x: isize, y: isize) -> [u8; 4] {
+        if self.width == 0 || self.height == 0 {
+            return [0; 4];
+        }
+
+        let x = x.rem_euclid(self.width as isize) as usize;
+        let y = y.rem_euclid(self.height as isize) as usize;
+        self.get_pixel(x, y)
+    }
+
+    fn blend_pixel(&mut self, x: isize, y: isize, color: [u8; 4], blend_mode: BlendMode) {
+        let offset = (y as usize * self.width as usize + x as usize) * 4;
+        blend(&mut self.pixels[offset..offset + 4], color, blend_mode);
+    }
+
+    fn area(&self) -> Rect {
+        Rect::new(0, 0, self.width as isize, self.height as isize)
+    }
+}
+
+fn rgba(color: Color) -> [u8; 4] {
+    let (r, g, b, a) = color.to_rgba();
+    [r, g, b, a]
+}
+
+#[inline(always)]
+fn mul(a: u8, b: u8) -> u8 {
+    ((a as u32 * b as u32 + 127) / 255) as u8
+}
+
+/// Combines a fragment with the destination pixel, mirroring the blend functions set up by the OpenGL renderer.
+fn blend(dst: &mut [u8], src: [u8; 4], blend_mode: BlendMode) {
+    match blend_mode {
+        BlendMode::None => dst.copy_from_slice(&src),
+        BlendMode::Alpha => {
+            let alpha = src[3];
+            for i in 0..4 {
+                dst[i] = mul(src[i], alpha).saturating_add(mul(dst[i], 255 - alpha));
+            }
+        }
+        BlendMode::Add => {
+            for i in 0..4 {
+                dst[i] = dst[i].saturating_add(src[i]);
+            }
+        }
+        BlendMode::Multiply => {
+            for i in 0..4 {
+                dst[i] = mul(dst[i], src[i]);
+            }
+        }
+    }
+}
+
+fn intersect(a: Rect, b: Rect) -> Rect {
+    Rect::new(a.left.max(b.left), a.top.max(b.top), a.right.min(b.right), a.bottom.min(b.bottom))
+}
+
+/// State shared between the renderer and its textures, as the textures draw themselves onto the current target.
+struct RenderState {
+    screen: Rc<RefCell<Surface>>,
+    target: Option<Rc<RefCell<Surface>>>,
+    blend_mode: BlendMode,
+    clip_rect: Option<Rect>,
+}
+
+impl RenderState {
+    fn target(&self) -> Rc<RefCell<Surface>> {
+        self.target.clone().unwrap_or_else(|| self.screen.clone())
+    }
+
+    /// Part of the target which can be drawn to.
+    fn bounds(&self, target: &Surface) -> Rect {
+        match self.clip_rect {
+            Some(clip_rect) => intersect(target.area(), clip_rect),
+            None => target.area(),
+        }
+    }
+}
+
+struct Sprite {
+    src: Rect<f32>,
+    dest: Rect<f32>,
+    color: [u8; 4],
+}
+
+/// Returns the range of pixels whose centers are covered by the span between `a` and `b`.
+fn pixel_span(a: f32, b: f32, min: isize, max: isize) -> (isize, isize) {
+    let start = ((a.min(b) - 0.5).ceil() as isize).max(min);
+    let end = ((a.max(b) - 0.5).ceil() as isize).min(max);
+    (start, end)
+}
+
+fn draw_sprite(target: &mut Surface, bounds: Rect, source: &Surface, sprite: &Sprite, blend_mode: BlendMode) {
+    let Sprite { src, dest, color } = sprite;
+    let (dest_width, dest_height) = (dest.right - dest.left, dest.bottom - dest.top);
+    if dest_width == 0.0 || dest_height == 0.0 {
+        return;
+    }
+
+    let (x_start, x_end) = pixel_span(dest.left, dest.right, bounds.left, bounds.right);
+    let (y_start, y_end) = pixel_span(dest.top, dest.bottom, bounds.top, bounds.bottom);
+    let (max_x, max_y) = (source.width as isize - 1, source.height as isize - 1);
+    if max_x < 0 || max_y < 0 {
+        return;
+    }
+
+    for y in y_start..y_end {
+        let v = src.top + (y as f32 + 0.5 - dest.top) / dest_height * (src.bottom - src.top);
+        let tex_y = (v.floor() as isize).clamp(0, max_y);
+
+        for x in x_start..x_end {
+            let u = src.left + (x as f32 + 0.5 - dest.left) / dest_width * (src.right - src.left);
+            let tex_x = (u.floor() as isize).clamp(0, max_x);
+
+            let texel = source.get_pixel(tex_x as usize, tex_y as usize);
+            target.blend_pixel(x, y, [0, 1, 2, 3].map(|i| mul(texel[i], color[i])), blend_mode);
+        }
+    }
+}
+
+fn fill_rect(target: &mut Surface, bounds: Rect, rect: Rect, color: [u8; 4], blend_mode: BlendMode) {
+    let rect = Rect::new(
+        rect.left.min(rect.right),
+        rect.top.min(rect.bottom),
+        rect.left.max(rect.right),
+        rect.top.max(rect.bottom),
+    );
+    let rect = intersect(bounds, rect);
+
+    for y in rect.top..rect.bottom {
+        for x in rect.left..rect.right {
+            target.blend_pixel(x, y, color, blend_mode);
+        }
+    }
+}
+
+enum Fragment<'a> {
+    Fill,
+    Texture(&'a Surface),
+    /// Copy of the screen, scale, time and frame offset, see `fragment_water_110.glsl`.
+    Water(&'a Surface, f32, f32, (f32, f32)),
+}
+
+impl Fragment<'_> {
+    fn shade(&self, x: isize, y: isize, uv: (f32, f32), color: [f32; 4]) -> [u8; 4] {
+        match self {
+            Fragment::Fill => color.map(|c| c.round() as u8),
+            Fragment::Texture(texture) => {
+                let tex_x = (uv.0 * texture.width as f32).floor() as isize;
+                let tex_y = (uv.1 * texture.height as f32).floor() as isize;
+                let texel = texture.sample(tex_x, tex_y);
+                [0, 1, 2, 3].map(|i| mul(texel[i], color[i].round() as u8))
+            }
+            Fragment::Water(screen, scale, t, frame_pos) => water(screen, x, y, *scale, *t, *frame_pos, color),
+        }
+    }
+}
+
+/// CPU version of the water shader, which distorts the screen and adds some chromatic aberration. It works in the
+/// bottom-up coordinates used by OpenGL to keep the waves in the same phase.
+fn water(screen: &Surface, x: isize, y: isize, scale: f32, t: f32, frame_pos: (f32, f32), color: [f32; 4]) -> [u8; 4] {
+    let (width, height) = (screen.width as f32, screen.height as f32);
+    let inv = (1.0 / width, 1.0 / height);
+
+    let uv = ((x as f32 + 0.5) * inv.0, (height - (y as f32 + 0.5)) * inv.1 + 1.0);
+    let wave = (
+        uv.0 + ((-frame_pos.1 * inv.1 + uv.0 * 16.0) + t / 20.0).sin() * scale * inv.0,
+        uv.1 - ((-frame_pos.0 * inv.0 + uv.1 * 16.0) + t / 5.0).cos() * scale * inv.1,
+    );
+    let off = 0.35 * scale * inv.1;
+    let off2 = 2.0 * off;
+
+    let sample = |dx: f32, dy: f32| -> [f32; 3] {
+        let tex_x = ((wave.0 + dx) * width).floor() as isize;
+        let tex_y = ((wave.1 + dy) * height).floor() as isize;
+        let texel = screen.sample(tex_x, screen.height as isize - 1 - tex_y);
+        [texel[0] as f32 / 255.0, texel[1] as f32 / 255.0, texel[2] as f32 / 255.0]
+    };
+
+    let mut rgb = sample(0.0, 0.0).map(|c| c * 0.25);
+    for (dy, weight) in [(off, 0.125), (-off, 0.125)] {
+        let s = sample(0.0, dy);
+        (0..3).for_each(|i| rgb[i] += s[i] * weight);
+    }
+
+    for (dy, weight) in [(-off, 0.0625), (0.0, 0.125), (off, 0.0625)] {
+        let s = sample(-off, dy);
+        rgb[0] += s[0] * weight;
+        rgb[1] += s[1] * weight;
+        rgb[2] += sample(-off2, dy)[2] * weight;
+
+        let s = sample(off, -dy);
+        rgb[0] += s[1] * weight;
+        rgb[1] += s[2] * weight;
+        rgb[2] += sample(off2, -dy)[0] * weight;
+    }
+
+    let alpha = color[3] / 255.0;
+    let rgb = [0, 1, 2].map(|i| ((rgb[i] * (1.0 - alpha) + color[i] / 255.0 * alpha) * 255.0).clamp(0.0, 255.0) as u8);
+    [rgb[0], rgb[1], rgb[2], 255]
+}
+
+fn edge(a: (f32, f32), b: (f32, f32), p: (f32, f32)) -> f32 {
+    (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0)
+}
+
+/// Decides which of the two triangles sharing an edge owns the pixels lying exactly on it, so they're not drawn twice.
+fn owns_edge(a: (f32, f32), b: (f32, f32)) -> bool {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    dy > 0.0 || (dy == 0.0 && dx < 0.0)
+}
+
+fn draw_triangle(
+    target: &mut Surface,
+    bounds: Rect,
+    vertices: &[VertexData],
+    fragment: &Fragment,
+    blend_mode: BlendMode,
+) {
+    let (v0, mut v1, mut v2) = (&vertices[0], &vertices[1], &vertices[2]);
+    let mut area = edge(v0.position, v1.position, v2.position);
+    if area == 0.0 || !area.is_finite() {
+        return;
+    }
+
+    if area < 0.0 {
+        std::mem::swap(&mut v1, &mut v2);
+        area = -area;
+    }
+
+    let min = |a: f32, b: f32, c: f32| a.min(b).min(c);
+    let max = |a: f32, b: f32, c: f32| a.max(b).max(c);
+    let (p0, p1, p2) = (v0.position, v1.position, v2.position);
+    let (x_start, x_end) = pixel_span(min(p0.0, p1.0, p2.0), max(p0.0, p1.0, p2.0), bounds.left, bounds.right);
+    let (y_start, y_end) = pixel_span(min(p0.1, p1.1, p2.1), max(p0.1, p1.1, p2.1), bounds.top, bounds.bottom);
+
+    let edges = [(p1, p2), (p2, p0), (p0, p1)];
+    let owned = edges.map(|(a, b)| owns_edge(a, b));
+    let colors = [v0, v1, v2].map(|v| [v.color.0, v.color.1, v.color.2, v.color.3].map(|c| c as f32));
+    let uvs = [v0.uv, v1.uv, v2.uv];
+
+    for y in y_start..y_end {
+        for x in x_start..x_end {
+            let p = (x as f32 + 0.5, y as f32 + 0.5);
+            let w = edges.map(|(a, b)| edge(a, b, p));
+            if (0..3).any(|i| w[i] < 0.0 || (w[i] == 0.0 && !owned[i])) {
+                continue;
+            }
+
+            let l = w.map(|w| w / area);
+            let color = [0, 1, 2, 3].map(|i| l[0] * colors[0][i] + l[1] * colors[1][i] + l[2] * colors[2][i]);
+            let uv = (
+                l[0] * uvs[0].0 + l[1] * uvs[1].0 + l[2] * uvs[2].0,
+                l[0] * uvs[0].1 + l[1] * uvs[1].1 + l[2] * uvs[2].1,
+            );
+
+            target.blend_pixel(x, y, fragment.shade(x, y, uv, color), blend_mode);
+        }
+    }
+}
+
+pub struct SoftwareTexture {
+    surface: Rc<RefCell<Surface>>,
+    sprites: Vec<Sprite>,
+    state: Rc<RefCell<RenderState>>,
+}
+
+impl SoftwareTexture {
+    /// Returns the pixels of the texture, including anything rendered to it.
+    pub fn surface(&self) -> Rc<RefCell<Surface>> {
+        self.surface.clone()
+    }
+}
+
+impl BackendTexture for SoftwareTexture {
+    fn dimensions(&self) -> (u16, u16) {
+        let surface = self.surface.borrow();
+        (surface.width, surface.height)
+    }
+
+    fn add(&mut self, command: SpriteBatchCommand) {
+        let (mut src, dest, flip_x, flip_y, color) = match command {
+            SpriteBatchCommand::DrawRect(src, dest) => (src, dest, false, false, [255; 4]),
+            SpriteBatchCommand::DrawRectFlip(src, dest, flip_x, flip_y) => (src, dest, flip_x, flip_y, [255; 4]),
+            SpriteBatchCommand::DrawRectTinted(src, dest, color) => (src, dest, false, false, rgba(color)),
+            SpriteBatchCommand::DrawRectFlipTinted(src, dest, flip_x, flip_y, color) => {
+                (src, dest, flip_x, flip_y, rgba(color))
+            }
+        };
+
+        if flip_x {
+            std::mem::swap(&mut src.left, &mut src.right);
+        }
+
+        if flip_y {
+            std::mem::swap(&mut src.top, &mut src.bottom);
+        }
+
+        self.sprites.push(Sprite { src, dest, color });
+    }
+
+    fn clear(&mut self) {
+        self.sprites.clear();
+    }
+
+    fn draw(&mut self) -> GameResult {
+        let state = self.state.borrow();
+        let target = state.target();
+
+        // Drawing a texture onto itself reads the pixels from before the draw.
+        let copy;
+        let borrowed;
+        let source = if Rc::ptr_eq(&target, &self.surface) {
+            copy = self.surface.borrow().clone();
+            &copy
+        } else {
+            borrowed = self.surface.borrow();
+            &*borrowed
+        };
+
+        let mut target = target.borrow_mut();
+        let bounds = state.bounds(&target);
+        for sprite in &self.sprites {
+            draw_sprite(&mut target, bounds, source, sprite, state.blend_mode);
+        }
+
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+pub struct SoftwareRenderer {
+    state: Rc<RefCell<RenderState>>,
+    imgui: UnsafeCell<Option<imgui::Context>>,
+}
+
+impl SoftwareRenderer {
+    pub fn new(width: u16, height: u16) -> SoftwareRenderer {
+        let state = RenderState {
+            screen: Rc::new(RefCell::new(Surface::new(width, height))),
+            target: None,
+            blend_mode: BlendMode::Alpha,
+            clip_rect: None,
+        };
+
+        SoftwareRenderer { state: Rc::new(RefCell::new(state)), imgui: UnsafeCell::new(None) }
+    }
+
+    /// Returns the framebuffer, which holds the last presented frame until the next `prepare_draw` call.
+    pub fn screen(&self) -> Rc<RefCell<Surface>> {
+        self.state.borrow().screen.clone()
+    }
+
+    fn get_texture(texture: &Box<dyn BackendTexture>) -> GameResult<&SoftwareTexture> {
+        texture
+            .as_any()
+            .downcast_ref::<SoftwareTexture>()
+            .ok_or_else(|| RenderError("This texture was not created by the software renderer.".to_string()))
+    }
+
+    fn new_texture(&self, surface: Surface) -> Box<dyn BackendTexture> {
+        Box::new(SoftwareTexture {
+            surface: Rc::new(RefCell::new(surface)),
+            sprites: Vec::new(),
+            state: self.state.clone(),
+        })
+    }
+}
+
+impl BackendRenderer for SoftwareRenderer {
+    fn renderer_name(&self) -> String {
+        "Software".to_owned()
+    }
+
+    fn clear(&mut self, color: Color) {
+        let state = self.state.borrow();
+        let target = state.target();
+        let mut target = target.borrow_mut();
+        let bounds = state.bounds(&target);
+        fill_rect(&mut target, bounds, bounds, rgba(color), BlendMode::None);
+    }
+
+    fn present(&mut self) -> GameResult {
+        Ok(())
+    }
+
+    fn prepare_draw(&mut self, width: f32, height: f32) -> GameResult {
+        let mut state = self.state.borrow_mut();
+        let (width, height) = (width as u16, height as u16);
+
+        {
+            let mut screen = state.screen.borrow_mut();
+            if (screen.width, screen.height) != (width, height) {
+                *screen = Surface::new(width, height);
+            } else {
+                screen.pixels.fill(0);
+            }
+        }
+
+        state.target = None;
+        state.blend_mode = BlendMode::Alpha;
+
+        Ok(())
+    }
+
+    fn create_texture_mutable(&mut self, width: u16, height: u16) -> GameResult<Box<dyn BackendTexture>> {
+        Ok(self.new_texture(Surface::new(width, height)))
+    }
+
+    fn create_texture(&mut self, width: u16, height: u16, data: &[u8]) -> GameResult<Box<dyn BackendTexture>> {
+        Ok(self.new_texture(Surface::from_rgba(width, height, data)))
+    }
+
+    fn set_blend_mode(&mut self, blend: BlendMode) -> GameResult {
+        self.state.borrow_mut().blend_mode = blend;
+        Ok(())
+    }
+
+    fn set_render_target(&mut self, texture: Option<&Box<dyn BackendTexture>>) -> GameResult {
+        let target = match texture {
+            Some(texture) => Some(SoftwareRenderer::get_texture(texture)?.surface.clone()),
+            None => None,
+        };
+
+        self.state.borrow_mut().target = target;
+        Ok(())
+    }
+
+    fn draw_rect(&mut self, rect: Rect<isize>, color: Color) -> GameResult {
+        let state = self.state.borrow();
+        let target = state.target();
+        let mut target = target.borrow_mut();
+        let bounds = state.bounds(&target);
+        fill_rect(&mut target, bounds, rect, rgba(color), state.blend_mode);
+
+        Ok(())
+    }
+
+    fn draw_outline_rect(&mut self, rect: Rect<isize>, line_width: usize, color: Color) -> GameResult {
+        let line_width = line_width as isize;
+        let Rect { left, top, right, bottom } = rect;
+
+        self.draw_rect(Rect::new(left, top, right, top + line_width), color)?;
+        self.draw_rect(Rect::new(left, bottom - line_width, right, bottom), color)?;
+        self.draw_rect(Rect::new(left, top + line_width, left + line_width, bottom - line_width), color)?;
+        self.draw_rect(Rect::new(right - line_width, top + line_width, right, bottom - line_width), color)
+    }
+
+    fn set_clip_rect(&mut self, rect: Option<Rect>) -> GameResult {
+        self.state.borrow_mut().clip_rect = rect;
+        Ok(())
+    }
+
+    fn imgui(&self) -> GameResult<&mut imgui::Context> {
+        let imgui = unsafe { &mut *self.imgui.get() };
+        let screen = self.screen();
+        let screen = screen.borrow();
+
+        Ok(imgui.get_or_insert_with(|| {
+            let mut imgui = imgui::Context::create();
+            imgui.io_mut().display_size = [screen.width as f32, screen.height as f32];
+            imgui.fonts().build_alpha8_texture();
+            imgui
+        }))
+    }
+
+    fn imgui_texture_id(&self, _texture: &Box<dyn BackendTexture>) -> GameResult<TextureId> {
+        Ok(TextureId::from(0))
+    }
+
+    fn prepare_imgui(&mut self, _ui: &Ui) -> GameResult {
+        Ok(())
+    }
+
+    fn render_imgui(&mut self, _draw_data: &DrawData) -> GameResult {
+        // The debug UI isn't rasterized, frames rendered by this backend are meant to only contain the game itself.
+        Ok(())
+    }
+
+    fn supports_vertex_draw(&self) -> bool {
+        true
+    }
+
+    fn draw_triangle_list(
+        &mut self,
+        vertices: &[VertexData],
+        texture: Option<&Box<dyn BackendTexture>>,
+        shader: BackendShader,
+    ) -> GameResult {
+        let state = self.state.borrow();
+
+        let texture = match texture {
+            Some(texture) => Some(SoftwareRenderer::get_texture(texture)?.surface.borrow().clone()),
+            None => None,
+        };
+        let screen;
+        let fragment = match (shader, &texture) {
+            (BackendShader::Texture, Some(texture)) => Fragment::Texture(texture),
+            (BackendShader::Texture, None) | (BackendShader::Fill, _) => Fragment::Fill,
+            (BackendShader::WaterFill(scale, t, frame_pos), _) => {
+                screen = state.screen.borrow().clone();
+                Fragment::Water(&screen, scale, t, frame_pos)
+            }
+        };
+
+        let target = state.target();
+        let mut target = target.borrow_mut();
+        let bounds = state.bounds(&target);
+        for triangle in vertices.chunks_exact(3) {
+            draw_triangle(&mut target, bounds, triangle, &fragment, state.blend_mode);
+        }
+
+        Ok(())
+    }
+
+    fn read_screen(&mut self) -> GameResult<(u16, u16, Vec<u8>)> {
+        let state = self.state.borrow();
+        let screen = state.screen.borrow();
+        Ok((screen.width, screen.height, screen.pixels.clone()))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_software_renderer() {
+        let mut renderer = SoftwareRenderer::new(8, 8);
+        renderer.prepare_draw(8.0, 8.0).unwrap();
+        renderer.clear(Color::from_rgba(0, 0, 255, 255));
+
+        // 2x1 texture with an opaque red and a half transparent white pixel
+        let mut texture = renderer.create_texture(2, 1, &[255, 0, 0, 255, 255, 255, 255, 128]).unwrap();
+        texture.add(SpriteBatchCommand::DrawRect(Rect::new(0.0, 0.0, 2.0, 1.0), Rect::new(0.0, 0.0, 4.0, 2.0)));
+        texture.add(SpriteBatchCommand::DrawRectFlip(
+            Rect::new(0.0, 0.0, 2.0, 1.0),
+            Rect::new(4.0, 0.0, 6.0, 1.0),
+            true,
+            false,
+        ));
+        texture.draw().unwrap();
+
+        renderer.set_clip_rect(Some(Rect::new(0, 4, 8, 8))).unwrap();
+        renderer.draw_rect(Rect::new(0, 2, 2, 6), Color::from_rgba(0, 255, 0, 255)).unwrap();
+
+        let screen = renderer.screen();
+        let screen = screen.borrow();
+        assert_eq!(screen.get_pixel(1, 1), [255, 0, 0, 255]);
+        assert_eq!(screen.get_pixel(2, 0), [128, 128, 255, 191]);
+        assert_eq!(screen.get_pixel(4, 0), [128, 128, 255, 191]);
+        assert_eq!(screen.get_pixel(5, 0), [255, 0, 0, 255]);
+        assert_eq!(screen.get_pixel(1, 3), [0, 0, 255, 255]);
+        assert_eq!(screen.get_pixel(1, 4), [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn test_triangles_render_target() {
+        let mut renderer = SoftwareRenderer::new(4, 4);
+        renderer.prepare_draw(4.0, 4.0).unwrap();
+
+        let target = renderer.create_texture_mutable(4, 4).unwrap();
+        renderer.set_render_target(Some(&target)).unwrap();
+
+        // a quad made of two triangles, with pixels on the shared diagonal blended only once
+        let color = (255, 255, 255, 128);
+        let vertex = |x, y| VertexData { position: (x, y), color, uv: (0.0, 0.0) };
+        let quad = [
+            vertex(0.0, 0.0),
+            vertex(4.0, 0.0),
+            vertex(4.0, 4.0),
+            vertex(0.0, 0.0),
+            vertex(4.0, 4.0),
+            vertex(0.0, 4.0),
+        ];
+        renderer.draw_triangle_list(&quad, None, BackendShader::Fill).unwrap();
+        renderer.set_render_target(None).unwrap();
+
+        let surface = SoftwareRenderer::get_texture(&target).unwrap().surface();
+        let surface = surface.borrow();
+        for y in 0..4 {
+            for x in 0..4 {
+                assert_eq!(surface.get_pixel(x, y), [128, 128, 128, 64]);
+            }
+        }
+
+        assert_eq!(renderer.screen().borrow().get_pixel(0, 0), [0, 0, 0, 0]);
+    }
+}
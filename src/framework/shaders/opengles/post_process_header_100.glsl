@@ -0,0 +1,12 @@
+#version 100
+
+precision mediump float;
+
+uniform sampler2D Texture;
+uniform vec2 SourceSize;
+uniform vec2 OutputSize;
+uniform float Time;
+varying vec2 Frag_UV;
+varying vec4 Frag_Color;
+
+#line 1
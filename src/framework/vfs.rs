@@ -9,12 +9,13 @@
 //! as a trait object, and its path abstraction is not the most
 //! convenient.
 
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::ffi::OsStr;
 use std::fmt::{self, Debug};
 use std::fs;
-use std::io::{Read, Seek, Write};
+use std::io::{self, Read, Seek, Write};
 use std::path::{self, Component, Path, PathBuf};
+use std::sync::Mutex;
 
 use crate::framework::error::{GameError, GameResult};
 
@@ -425,9 +426,6 @@ impl OverlayFS {
     }
 
     /// Adds a new VFS to the front of the list.
-    /// Currently unused, I suppose, but good to
-    /// have at least for tests.
-    #[allow(dead_code)]
     pub fn push_front(&mut self, fs: Box<dyn VFS>) {
         self.roots.push_front(fs);
     }
@@ -543,6 +541,216 @@ impl VFS for OverlayFS {
     }
 }
 
+/// A read-only VFS backed by a zip archive, with its contents mounted under a given path.
+///
+/// Paths are matched case-insensitively, like with `PhysicalFS::new_lowercase`. If all entries of the
+/// archive are inside of a single directory, that directory is used as the root.
+pub struct ZipFS {
+    mount_point: String,
+    archive: Mutex<zip::ZipArchive<Box<dyn VFile>>>,
+    /// Lowercase path of each file in the archive, relative to the root.
+    files: HashMap<String, ZipEntry>,
+    /// Lowercase path of each directory, including the ones without an entry in the archive, to its original name.
+    dirs: HashMap<String, String>,
+}
+
+struct ZipEntry {
+    index: usize,
+    name: String,
+    len: u64,
+}
+
+/// Returns the lowercase path relative to the root, joined with `/`.
+fn normalized_path(path: &Path) -> Option<String> {
+    let path = sanitize_path(path)?;
+    let components: Vec<&str> = path.iter().filter_map(|c| c.to_str()).collect();
+    Some(components.join("/").to_lowercase())
+}
+
+fn parent_dir(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(parent, _)| parent)
+}
+
+impl ZipFS {
+    /// Reads the index of a zip archive. `mount_point` is the directory its contents show up in,
+    /// eg. `/mods/example.zip/`.
+    pub fn new(reader: Box<dyn VFile>, mount_point: &str) -> GameResult<ZipFS> {
+        let mut archive = zip::ZipArchive::new(reader)?;
+        let mut entries = Vec::new();
+
+        for index in 0..archive.len() {
+            let file = archive.by_index(index)?;
+            if !file.is_dir() {
+                entries.push((index, file.name().trim_matches('/').to_owned(), file.size()));
+            }
+        }
+
+        let top_dir = entries.first().and_then(|(_, name, _)| name.split_once('/')).map(|(dir, _)| [dir, "/"].join(""));
+        let prefix = top_dir.filter(|dir| entries.iter().all(|(_, name, _)| name.starts_with(dir.as_str())));
+        let prefix_len = prefix.map_or(0, |prefix| prefix.len());
+
+        let mut files = HashMap::new();
+        let mut dirs = HashMap::new();
+        dirs.insert(String::new(), String::new());
+
+        for (index, name, len) in entries {
+            let name = name[prefix_len..].to_owned();
+            let mut dir = parent_dir(&name);
+            while !dir.is_empty() {
+                dirs.insert(dir.to_lowercase(), dir.to_owned());
+                dir = parent_dir(dir);
+            }
+
+            files.insert(name.to_lowercase(), ZipEntry { index, name, len });
+        }
+
+        let mount_point = normalized_path(Path::new(mount_point)).ok_or_else(|| {
+            GameError::FilesystemError(format!("Invalid mount point for zip archive: {}", mount_point))
+        })?;
+
+        Ok(ZipFS { mount_point, archive: Mutex::new(archive), files, dirs })
+    }
+
+    /// Returns the lowercase path inside of the archive, or None if the path is outside of the mount point.
+    fn archive_path(&self, path: &Path) -> Option<String> {
+        let path = normalized_path(path)?;
+        if self.mount_point.is_empty() {
+            return Some(path);
+        }
+
+        if path == self.mount_point {
+            return Some(String::new());
+        }
+
+        path.strip_prefix(&self.mount_point)?.strip_prefix('/').map(str::to_owned)
+    }
+
+    fn read_only_error(&self, path: &Path) -> GameError {
+        GameError::FilesystemError(format!("Cannot alter file {:?} in {:?}, zip archives are read-only", path, self))
+    }
+}
+
+impl Debug for ZipFS {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "<ZipFS mount point: /{}>", self.mount_point)
+    }
+}
+
+/// A file read from a zip archive into memory.
+#[derive(Debug)]
+struct ZipFileWrapper(io::Cursor<Vec<u8>>);
+
+impl Read for ZipFileWrapper {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Write for ZipFileWrapper {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "Cannot write to a file in a zip archive"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for ZipFileWrapper {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.0.seek(pos)
+    }
+}
+
+struct ZipMetadata {
+    is_dir: bool,
+    len: u64,
+}
+
+impl VMetadata for ZipMetadata {
+    fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+    fn is_file(&self) -> bool {
+        !self.is_dir
+    }
+    fn len(&self) -> u64 {
+        self.len
+    }
+}
+
+impl VFS for ZipFS {
+    fn open_options(&self, path: &Path, open_options: OpenOptions) -> GameResult<Box<dyn VFile>> {
+        if open_options.write || open_options.create || open_options.append || open_options.truncate {
+            return Err(self.read_only_error(path));
+        }
+
+        let entry = self
+            .archive_path(path)
+            .and_then(|name| self.files.get(&name))
+            .ok_or_else(|| GameError::FilesystemError(format!("File {:?} not found in {:?}", path, self)))?;
+
+        let mut archive =
+            self.archive.lock().map_err(|_| GameError::FilesystemError("Zip archive lock poisoned".to_owned()))?;
+        let mut file = archive.by_index(entry.index)?;
+        let mut data = Vec::with_capacity(entry.len as usize);
+        file.read_to_end(&mut data)?;
+
+        Ok(Box::new(ZipFileWrapper(io::Cursor::new(data))))
+    }
+
+    fn mkdir(&self, path: &Path) -> GameResult {
+        Err(self.read_only_error(path))
+    }
+
+    fn rm(&self, path: &Path) -> GameResult {
+        Err(self.read_only_error(path))
+    }
+
+    fn rmrf(&self, path: &Path) -> GameResult {
+        Err(self.read_only_error(path))
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.archive_path(path).map_or(false, |name| self.files.contains_key(&name) || self.dirs.contains_key(&name))
+    }
+
+    fn metadata(&self, path: &Path) -> GameResult<Box<dyn VMetadata>> {
+        let name = self.archive_path(path);
+        if let Some(entry) = name.as_ref().and_then(|name| self.files.get(name)) {
+            return Ok(Box::new(ZipMetadata { is_dir: false, len: entry.len }));
+        }
+
+        if name.map_or(false, |name| self.dirs.contains_key(&name)) {
+            return Ok(Box::new(ZipMetadata { is_dir: true, len: 0 }));
+        }
+
+        Err(GameError::FilesystemError(format!("File {:?} not found in {:?}", path, self)))
+    }
+
+    fn read_dir(&self, path: &Path) -> GameResult<Box<dyn Iterator<Item = GameResult<PathBuf>>>> {
+        let dir = self
+            .archive_path(path)
+            .filter(|name| self.dirs.contains_key(name))
+            .ok_or_else(|| GameError::FilesystemError(format!("Directory {:?} not found in {:?}", path, self)))?;
+
+        let files = self.files.iter().map(|(key, entry)| (key, &entry.name));
+        let entries: Vec<GameResult<PathBuf>> = files
+            .chain(self.dirs.iter())
+            .filter(|(key, _)| !key.is_empty() && parent_dir(key) == dir)
+            .filter_map(|(_, name)| name.rsplit('/').next())
+            .map(|file_name| Ok(path.join(file_name)))
+            .collect();
+
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    /// Zip archives have no location on disk.
+    fn to_path_buf(&self) -> Option<PathBuf> {
+        None
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::io::{self, BufRead};
@@ -686,5 +894,34 @@ mod tests {
         assert!(!fs.exists(testdir));
     }
 
+    #[test]
+    fn headless_test_zip() {
+        let mut writer = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = zip::write::FileOptions::default();
+        writer.start_file("Example/mod.txt", options).unwrap();
+        writer.write_all(b"Example mod").unwrap();
+        writer.start_file("Example/Stage/Cave.tsc", options).unwrap();
+        writer.write_all(b"#0100").unwrap();
+        let archive = writer.finish().unwrap();
+
+        let fs = ZipFS::new(Box::new(archive), "/mods/example.zip/").unwrap();
+
+        // the common top level directory is stripped and paths are case-insensitive
+        assert!(fs.exists(Path::new("/mods/example.zip/mod.txt")));
+        assert!(fs.exists(Path::new("/Mods/Example.zip/stage/CAVE.TSC")));
+        assert!(fs.metadata(Path::new("/mods/example.zip/Stage")).unwrap().is_dir());
+        assert!(!fs.exists(Path::new("/mod.txt")));
+
+        let mut s = String::new();
+        fs.open(Path::new("/mods/example.zip/Stage/Cave.tsc")).unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "#0100");
+
+        let entries: Vec<PathBuf> = fs.read_dir(Path::new("/mods/example.zip/")).unwrap().map(Result::unwrap).collect();
+        assert_eq!(entries.len(), 2);
+        assert!(entries.contains(&PathBuf::from("/mods/example.zip/Stage")));
+
+        assert!(fs.create(Path::new("/mods/example.zip/new.txt")).is_err());
+    }
+
     // BUGGO: TODO: Make sure all functions are tested for OverlayFS and ZipFS!!
 }
@@ -0,0 +1,278 @@
+use std::io::Write;
+use std::sync::mpsc::{channel, Sender};
+use std::thread::{self, JoinHandle};
+
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::codecs::png::PngEncoder;
+use image::{ColorType, Delay, Frame, ImageEncoder, RgbaImage};
+
+use crate::framework::context::Context;
+use crate::framework::error::{GameError, GameResult};
+use crate::framework::keyboard::ScanCode;
+use crate::framework::{filesystem, graphics, keyboard};
+use crate::game::shared_game_state::SharedGameState;
+
+/// Directory in the user dir where screenshots and recordings are stored.
+pub const CAPTURE_DIR: &str = "/screenshots";
+
+/// Destination of a captured image, either in the user dir or anywhere else when used by the headless runner.
+pub type CaptureFile = Box<dyn Write + Send>;
+
+/// Reads back the last drawn frame and scales it down to the native resolution of the game canvas.
+pub fn read_canvas(ctx: &mut Context, scale: f32) -> GameResult<RgbaImage> {
+    let (width, height, pixels) = graphics::read_screen(ctx)?;
+    let screen = RgbaImage::from_raw(width as u32, height as u32, pixels)
+        .ok_or_else(|| GameError::RenderError("Invalid screen size.".to_string()))?;
+
+    let scale = (scale as u32).max(1);
+    let (width, height) = ((screen.width() / scale).max(1), (screen.height() / scale).max(1));
+
+    Ok(RgbaImage::from_fn(width, height, |x, y| {
+        // The canvas is scaled by an integer factor, so the center of each block is one canvas pixel.
+        let sx = (x * scale + scale / 2).min(screen.width() - 1);
+        let sy = (y * scale + scale / 2).min(screen.height() - 1);
+        let mut pixel = *screen.get_pixel(sx, sy);
+        pixel.0[3] = 255;
+        pixel
+    }))
+}
+
+/// Returns how long a frame showing `ticks` game ticks lasts.
+pub fn tick_delay(state: &SharedGameState, ticks: u32) -> Delay {
+    let tps = match state.settings.timing_mode.get_tps() {
+        0 => 60,
+        tps => tps as u32,
+    };
+
+    Delay::from_numer_denom_ms(ticks * 1000, tps)
+}
+
+pub fn write_png<W: Write>(file: W, image: &RgbaImage) -> GameResult {
+    PngEncoder::new(file).write_image(image.as_raw(), image.width(), image.height(), ColorType::Rgba8)?;
+    Ok(())
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CaptureFormat {
+    /// A single animated GIF file.
+    Gif,
+    /// Every frame as a separate PNG file.
+    Frames,
+}
+
+/// Encodes captured frames on a background thread, so recording doesn't stall the game loop.
+pub struct Recorder {
+    sender: Option<Sender<(RgbaImage, Delay, Option<CaptureFile>)>>,
+    thread: Option<JoinHandle<GameResult>>,
+    frames: u32,
+}
+
+impl Recorder {
+    /// Starts encoding an animated GIF into `file`.
+    pub fn gif(file: CaptureFile) -> GameResult<Recorder> {
+        let mut encoder = GifEncoder::new_with_speed(file, 10);
+        encoder.set_repeat(Repeat::Infinite)?;
+
+        Recorder::spawn(Some(encoder))
+    }
+
+    /// Starts writing PNG frames, the file of each frame is passed to `push_frame`.
+    pub fn frames() -> GameResult<Recorder> {
+        Recorder::spawn(None)
+    }
+
+    fn spawn(mut encoder: Option<GifEncoder<CaptureFile>>) -> GameResult<Recorder> {
+        let (sender, receiver) = channel::<(RgbaImage, Delay, Option<CaptureFile>)>();
+
+        let thread = thread::Builder::new().name("capture".to_owned()).spawn(move || {
+            for (image, delay, file) in receiver {
+                if let Some(encoder) = &mut encoder {
+                    encoder.encode_frame(Frame::from_parts(image, 0, 0, delay))?;
+                } else if let Some(file) = file {
+                    write_png(file, &image)?;
+                }
+            }
+
+            Ok(())
+        })?;
+
+        Ok(Recorder { sender: Some(sender), thread: Some(thread), frames: 0 })
+    }
+
+    /// Amount of frames pushed so far.
+    pub fn frames_count(&self) -> u32 {
+        self.frames
+    }
+
+    /// Queues a frame to be written, `file` is only used when writing separate frames.
+    pub fn push_frame(&mut self, image: RgbaImage, delay: Delay, file: Option<CaptureFile>) -> GameResult {
+        let sent = self.sender.as_ref().map_or(false, |sender| sender.send((image, delay, file)).is_ok());
+        if !sent {
+            // The encoder thread stopped on an error, which is returned from there.
+            return self.finish();
+        }
+
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Waits until all frames have been written.
+    pub fn finish(&mut self) -> GameResult {
+        self.sender = None;
+
+        match self.thread.take() {
+            Some(thread) => {
+                thread.join().map_err(|_| GameError::RenderError("The capture thread has panicked.".to_string()))?
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+struct Recording {
+    recorder: Recorder,
+    format: CaptureFormat,
+    path: String,
+}
+
+/// Screenshot and recording hotkeys of the game window.
+///
+/// Print Screen saves a screenshot, Ctrl+Print Screen toggles recording an animated GIF and Ctrl+Shift+Print Screen
+/// toggles recording a PNG frame sequence. Captures are stored in `CAPTURE_DIR` at the native resolution of the canvas.
+pub struct Capture {
+    recording: Option<Recording>,
+    screenshot_requested: bool,
+    /// Ticks which have passed since the last recorded frame.
+    pending_ticks: u32,
+    key_held: bool,
+}
+
+impl Capture {
+    pub fn new() -> Capture {
+        Capture { recording: None, screenshot_requested: false, pending_ticks: 0, key_held: false }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    pub fn process_keys(&mut self, ctx: &mut Context) {
+        // The SDL2 backend reports Print Screen as SysRq.
+        let pressed =
+            keyboard::is_key_pressed(ctx, ScanCode::Snapshot) || keyboard::is_key_pressed(ctx, ScanCode::Sysrq);
+        let triggered = pressed && !self.key_held;
+        self.key_held = pressed;
+
+        if !triggered {
+            return;
+        }
+
+        let mods = keyboard::active_mods(ctx);
+        if !mods.ctrl() {
+            self.screenshot_requested = true;
+        } else if self.recording.is_some() {
+            self.stop_recording();
+        } else {
+            let format = if mods.shift() { CaptureFormat::Frames } else { CaptureFormat::Gif };
+            if let Err(err) = self.start_recording(ctx, format) {
+                log::error!("Failed to start recording: {}", err);
+            }
+        }
+    }
+
+    /// Counts the game ticks, a frame is recorded only if at least one has passed since the previous one.
+    pub fn add_ticks(&mut self, ticks: u32) {
+        if self.recording.is_some() {
+            self.pending_ticks = self.pending_ticks.saturating_add(ticks);
+        }
+    }
+
+    fn start_recording(&mut self, ctx: &mut Context, format: CaptureFormat) -> GameResult {
+        filesystem::user_create_dir(ctx, CAPTURE_DIR)?;
+
+        let name = chrono::Local::now().format("%Y-%m-%d_%H-%M-%S").to_string();
+        let (recorder, path) = match format {
+            CaptureFormat::Gif => {
+                let path = format!("{}/{}.gif", CAPTURE_DIR, name);
+                (Recorder::gif(Box::new(filesystem::user_create(ctx, &path)?))?, path)
+            }
+            CaptureFormat::Frames => {
+                let path = format!("{}/{}", CAPTURE_DIR, name);
+                filesystem::user_create_dir(ctx, &path)?;
+                (Recorder::frames()?, path)
+            }
+        };
+
+        log::info!("Recording to {}.", path);
+        self.recording = Some(Recording { recorder, format, path });
+        self.pending_ticks = 0;
+
+        Ok(())
+    }
+
+    fn stop_recording(&mut self) {
+        if let Some(mut recording) = self.recording.take() {
+            match recording.recorder.finish() {
+                Ok(()) => {
+                    log::info!("Saved {} frames to {}.", recording.recorder.frames_count(), recording.path)
+                }
+                Err(err) => log::error!("Failed to save the recording to {}: {}", recording.path, err),
+            }
+        }
+    }
+
+    /// Captures the frame drawn by the current scene, called before any overlays are drawn on top of it.
+    pub fn capture_frame(&mut self, state: &SharedGameState, ctx: &mut Context) {
+        if self.screenshot_requested {
+            self.screenshot_requested = false;
+
+            match Capture::take_screenshot(state, ctx) {
+                Ok(path) => log::info!("Saved screenshot to {}.", path),
+                Err(err) => log::error!("Failed to save a screenshot: {}", err),
+            }
+        }
+
+        if self.pending_ticks == 0 {
+            return;
+        }
+
+        let Some(recording) = &mut self.recording else {
+            return;
+        };
+
+        let delay = tick_delay(state, std::mem::take(&mut self.pending_ticks));
+
+        let result = read_canvas(ctx, state.scale).and_then(|image| {
+            let file: Option<CaptureFile> = match recording.format {
+                CaptureFormat::Gif => None,
+                CaptureFormat::Frames => {
+                    let path = format!("{}/{:06}.png", recording.path, recording.recorder.frames_count());
+                    Some(Box::new(filesystem::user_create(ctx, path)?))
+                }
+            };
+
+            recording.recorder.push_frame(image, delay, file)
+        });
+
+        if let Err(err) = result {
+            log::error!("Recording stopped: {}", err);
+            self.stop_recording();
+        }
+    }
+
+    fn take_screenshot(state: &SharedGameState, ctx: &mut Context) -> GameResult<String> {
+        let image = read_canvas(ctx, state.scale)?;
+
+        filesystem::user_create_dir(ctx, CAPTURE_DIR)?;
+        let path = format!("{}/{}.png", CAPTURE_DIR, chrono::Local::now().format("%Y-%m-%d_%H-%M-%S-%3f"));
+        write_png(filesystem::user_create(ctx, &path)?, &image)?;
+
+        Ok(path)
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        self.stop_recording();
+    }
+}
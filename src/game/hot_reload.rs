@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::common::FILE_TYPES;
+
+/// How often the data directories are scanned for modified files.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A game asset which can be reloaded while the game is running.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangedAsset {
+    /// Path of a .tsc file relative to the data root, eg. `Stage/Cave.tsc`.
+    TextScript(String),
+    /// Map name of a changed .pxm or .pxpack file, or tileset name of a changed .pxa file.
+    Map(String),
+    /// Texture name, as used by `TextureSet`.
+    Texture(String),
+    LuaScript,
+}
+
+impl ChangedAsset {
+    /// Maps a changed file to the asset it belongs to. `roots` are the data roots from `EngineConstants::base_paths`.
+    pub fn from_path(path: &str, roots: &[String]) -> Option<ChangedAsset> {
+        // The most specific root wins, eg. `/base/Stage/Cave.tsc` is `Stage/Cave.tsc` rather than `base/Stage/...`.
+        let resource = roots.iter().filter_map(|root| path.strip_prefix(root.as_str())).min_by_key(|res| res.len())?;
+        let (name, ext) = resource.rsplit_once('.')?;
+        let ext = format!(".{}", ext.to_ascii_lowercase());
+
+        match ext.as_str() {
+            ".tsc" => Some(ChangedAsset::TextScript(resource.to_owned())),
+            ".pxm" | ".pxa" | ".pxpack" => {
+                let name = name.strip_prefix("Stage/").or_else(|| name.strip_prefix("stage/"))?;
+                Some(ChangedAsset::Map(name.to_owned()))
+            }
+            ".lua" => Some(ChangedAsset::LuaScript),
+            _ if FILE_TYPES.contains(&ext.as_str()) => {
+                Some(ChangedAsset::Texture(name.strip_suffix(".glow").unwrap_or(name).to_owned()))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Watches the physical data directories for modified files on a background thread.
+pub struct HotReloadWatcher {
+    receiver: Receiver<String>,
+    running: Arc<AtomicBool>,
+    /// Changes which couldn't have been applied yet.
+    deferred: Vec<ChangedAsset>,
+}
+
+impl HotReloadWatcher {
+    pub fn new(roots: Vec<PathBuf>) -> HotReloadWatcher {
+        let (sender, receiver) = channel();
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+
+        log::info!("Watching {:?} for modified assets.", roots);
+
+        let result = thread::Builder::new().name("hot reload".to_owned()).spawn(move || {
+            let mut files = HashMap::new();
+            for root in &roots {
+                scan_dir(root, root, &mut files);
+            }
+
+            while thread_running.load(Ordering::Relaxed) {
+                thread::sleep(POLL_INTERVAL);
+
+                let mut current = HashMap::new();
+                for root in &roots {
+                    scan_dir(root, root, &mut current);
+                }
+
+                for (path, modified) in &current {
+                    if files.get(path) != Some(modified) && sender.send(path.clone()).is_err() {
+                        return;
+                    }
+                }
+
+                files = current;
+            }
+        });
+
+        if let Err(err) = result {
+            log::warn!("Failed to start the hot reload thread: {}", err);
+        }
+
+        HotReloadWatcher { receiver, running, deferred: Vec::new() }
+    }
+
+    /// Returns the assets modified since the last call, along with the deferred ones.
+    pub fn poll(&mut self, roots: &[String]) -> Vec<ChangedAsset> {
+        let mut changes = std::mem::take(&mut self.deferred);
+
+        for path in self.receiver.try_iter() {
+            log::info!("Modified asset: {}", path);
+
+            if let Some(asset) = ChangedAsset::from_path(&path, roots) {
+                if !changes.contains(&asset) {
+                    changes.push(asset);
+                }
+            }
+        }
+
+        changes
+    }
+
+    /// Keeps the changes to be returned by the next `poll`.
+    pub fn defer(&mut self, changes: Vec<ChangedAsset>) {
+        self.deferred.extend(changes);
+    }
+}
+
+impl Drop for HotReloadWatcher {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Collects the modification times of files in `dir`, keyed by their absolute path in the VFS mounted at `root`.
+fn scan_dir(root: &Path, dir: &Path, files: &mut HashMap<String, SystemTime>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+
+        let path = entry.path();
+        if metadata.is_dir() {
+            scan_dir(root, &path, files);
+        } else if let (Ok(modified), Ok(relative)) = (metadata.modified(), path.strip_prefix(root)) {
+            let mut vfs_path = String::new();
+            for component in relative.components() {
+                vfs_path.push('/');
+                vfs_path.push_str(&component.as_os_str().to_string_lossy());
+            }
+
+            files.insert(vfs_path, modified);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed_asset_from_path() {
+        let roots = vec!["/base/".to_owned(), "/".to_owned()];
+
+        assert_eq!(
+            ChangedAsset::from_path("/base/Stage/Cave.tsc", &roots),
+            Some(ChangedAsset::TextScript("Stage/Cave.tsc".to_owned()))
+        );
+        assert_eq!(ChangedAsset::from_path("/Stage/Cave.PXM", &roots), Some(ChangedAsset::Map("Cave".to_owned())));
+        assert_eq!(
+            ChangedAsset::from_path("/base/Npc/NpcCemet.glow.png", &roots),
+            Some(ChangedAsset::Texture("Npc/NpcCemet".to_owned()))
+        );
+        assert_eq!(ChangedAsset::from_path("/base/scripts/main.lua", &roots), Some(ChangedAsset::LuaScript));
+        assert_eq!(ChangedAsset::from_path("/base/Stage/Cave.pxe", &roots), None);
+    }
+}
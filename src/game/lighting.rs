@@ -0,0 +1,229 @@
+//! Data-driven point lights, drawn into the lightmap on top of the built-in lighting.
+//!
+//! Lights are defined per stage in the `lighting` section of `Stage/<map>.json`, and can be attached to
+//! NPCs (by type or by event number), bullets, carets, every instance of a tile, or a fixed tile position.
+//! Scripts can change them at runtime with `<DRK` and `<LNP`.
+//!
+//! ```json
+//! {
+//!   "lighting": {
+//!     "darkness": 0.7,
+//!     "ambient_color": [200, 200, 255],
+//!     "lights": [
+//!       { "attach": "npc_type", "id": 17, "color": [255, 180, 80], "radius": 48, "flicker": 0.3 },
+//!       { "attach": "npc_event", "id": 300, "color": [80, 255, 80], "radius": 24 },
+//!       { "attach": "bullet", "id": 13, "color": [255, 120, 0], "radius": 20 },
+//!       { "attach": "caret", "id": 2, "radius": 16 },
+//!       { "attach": "tile", "id": 72, "color": [255, 200, 120], "radius": 40, "flicker": 0.15 },
+//!       { "attach": "point", "x": 10, "y": 4, "radius": 96 }
+//!     ]
+//!   }
+//! }
+//! ```
+use crate::common::Color;
+use crate::scene::game_scene::LightingMode;
+use crate::util::rng::XorShift;
+
+/// Color of the lightmap in stages which don't set their darkness, same as the built-in lighting.
+const DEFAULT_AMBIENT: (u8, u8, u8) = (100, 100, 110);
+
+/// Ticks between two random brightness values of a flickering light.
+const FLICKER_PERIOD: f64 = 4.0;
+
+fn default_color() -> [u8; 3] {
+    [255, 255, 255]
+}
+
+fn default_radius() -> f32 {
+    32.0
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Light {
+    #[serde(default = "default_color")]
+    pub color: [u8; 3],
+    /// Radius in pixels.
+    #[serde(default = "default_radius")]
+    pub radius: f32,
+    /// How much the brightness randomly varies over time, from 0 (steady) to 1.
+    #[serde(default)]
+    pub flicker: f32,
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Light { color: default_color(), radius: default_radius(), flicker: 0.0 }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(tag = "attach", rename_all = "snake_case")]
+pub enum LightTarget {
+    /// Every NPC of given type.
+    NpcType { id: u16 },
+    /// Every NPC with given event number.
+    NpcEvent { id: u16 },
+    /// Every bullet of given type.
+    Bullet { id: u16 },
+    /// Every caret of given type.
+    Caret { id: u8 },
+    /// Every instance of given tile on the foreground layer.
+    Tile { id: u16 },
+    /// The center of the tile at given position.
+    Point { x: u16, y: u16 },
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct LightDef {
+    #[serde(flatten)]
+    pub target: LightTarget,
+    #[serde(flatten)]
+    pub light: Light,
+}
+
+fn default_ambient_color() -> [u8; 3] {
+    [255, 255, 255]
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct StageLighting {
+    /// Darkness of the areas not reached by any light, from 0 (lighting disabled) to 1 (pitch black).
+    /// If not set, the lighting mode is picked from the background of the stage, like in the original game.
+    #[serde(default)]
+    pub darkness: Option<f32>,
+    /// Color of the ambient light, before it's darkened.
+    #[serde(default = "default_ambient_color")]
+    pub ambient_color: [u8; 3],
+    #[serde(default)]
+    pub lights: Vec<LightDef>,
+}
+
+impl Default for StageLighting {
+    fn default() -> Self {
+        StageLighting { darkness: None, ambient_color: default_ambient_color(), lights: Vec::new() }
+    }
+}
+
+/// Lights of the current stage, initialized from the stage metadata and modified by scripts.
+pub struct LightSystem {
+    pub darkness: Option<f32>,
+    pub ambient_color: [u8; 3],
+    pub lights: Vec<LightDef>,
+}
+
+impl LightSystem {
+    pub fn new(lighting: &StageLighting) -> LightSystem {
+        LightSystem {
+            darkness: lighting.darkness.map(|darkness| darkness.clamp(0.0, 1.0)),
+            ambient_color: lighting.ambient_color,
+            lights: lighting.lights.clone(),
+        }
+    }
+
+    /// Returns the lighting mode to use, `default` is the one picked from the stage background.
+    pub fn lighting_mode(&self, default: LightingMode) -> LightingMode {
+        match self.darkness {
+            Some(darkness) if darkness > 0.0 => LightingMode::Ambient,
+            Some(_) => LightingMode::None,
+            None => default,
+        }
+    }
+
+    /// Color the lightmap is cleared with, before any lights are drawn.
+    pub fn ambient(&self) -> Color {
+        let Some(darkness) = self.darkness else {
+            return Color::from_rgb(DEFAULT_AMBIENT.0, DEFAULT_AMBIENT.1, DEFAULT_AMBIENT.2);
+        };
+
+        let brightness = 1.0 - darkness;
+        let [r, g, b] = self.ambient_color;
+        Color::from_rgb(
+            (r as f32 * brightness) as u8,
+            (g as f32 * brightness) as u8,
+            (b as f32 * brightness) as u8,
+        )
+    }
+
+    /// Attaches a light to the NPCs with given event number, replacing the previous one. `None` removes the light.
+    pub fn set_npc_event_light(&mut self, event_num: u16, light: Option<Light>) {
+        let target = LightTarget::NpcEvent { id: event_num };
+        self.lights.retain(|def| def.target != target);
+
+        if let Some(light) = light {
+            self.lights.push(LightDef { target, light });
+        }
+    }
+
+    pub fn lights_for(&self, target: LightTarget) -> impl Iterator<Item = &Light> {
+        self.lights.iter().filter(move |def| def.target == target).map(|def| &def.light)
+    }
+
+    /// Returns the color of the light at given time, `seed` keeps the lights attached to different objects
+    /// from flickering in sync.
+    pub fn light_color(light: &Light, seed: u32, tick: u32, frame_time: f64) -> (u8, u8, u8) {
+        let [r, g, b] = light.color;
+        if light.flicker <= 0.0 {
+            return (r, g, b);
+        }
+
+        let time = (tick as f64 + frame_time) / FLICKER_PERIOD;
+        let step = time.floor() as u32;
+        let t = time.fract() as f32;
+
+        let noise = |step: u32| {
+            let rng = XorShift::new(((step.wrapping_mul(0x9e37_79b1) ^ seed.wrapping_mul(0x85eb_ca77)) | 1) as i32);
+            rng.next_u32() as f32 / u32::MAX as f32
+        };
+
+        let value = noise(step) * (1.0 - t) + noise(step.wrapping_add(1)) * t;
+        let brightness = 1.0 - light.flicker.clamp(0.0, 1.0) * value;
+
+        ((r as f32 * brightness) as u8, (g as f32 * brightness) as u8, (b as f32 * brightness) as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lights() {
+        let lighting: StageLighting = serde_json::from_str(
+            r#"{
+                "darkness": 0.5,
+                "lights": [
+                    { "attach": "npc_type", "id": 17, "color": [255, 0, 0], "radius": 48, "flicker": 0.25 },
+                    { "attach": "point", "x": 3, "y": 4 }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(lighting.darkness, Some(0.5));
+        assert_eq!(lighting.ambient_color, [255, 255, 255]);
+        assert_eq!(
+            lighting.lights,
+            vec![
+                LightDef {
+                    target: LightTarget::NpcType { id: 17 },
+                    light: Light { color: [255, 0, 0], radius: 48.0, flicker: 0.25 },
+                },
+                LightDef { target: LightTarget::Point { x: 3, y: 4 }, light: Light::default() },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_npc_event_light() {
+        let mut lights = LightSystem::new(&StageLighting::default());
+        let light = Light { radius: 16.0, ..Light::default() };
+
+        lights.set_npc_event_light(100, Some(Light::default()));
+        lights.set_npc_event_light(100, Some(light));
+        assert_eq!(lights.lights_for(LightTarget::NpcEvent { id: 100 }).collect::<Vec<_>>(), vec![&light]);
+
+        lights.set_npc_event_light(100, None);
+        assert!(lights.lights.is_empty());
+        assert_eq!(lights.lighting_mode(LightingMode::BackgroundOnly), LightingMode::BackgroundOnly);
+    }
+}
@@ -3,7 +3,7 @@ use std::io;
 use std::io::{BufRead, BufReader, Read};
 use std::sync::Arc;
 
-use byteorder::{ReadBytesExt, LE};
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
 
 use crate::common::{Color, Rect};
 use crate::framework::context::Context;
@@ -113,11 +113,12 @@ impl Map {
 
         let width = map_data.read_u16::<LE>()?;
         let height = map_data.read_u16::<LE>()?;
+        let tile_count = width as usize * height as usize;
 
         let mut tiles_u16 = Vec::<u16>::new();
 
         //layered file
-        if fsize == ((width * height) as u64 * std::mem::size_of::<u16>() as u64 * 4 + 8)
+        if fsize == (tile_count as u64 * std::mem::size_of::<u16>() as u64 * 4 + 8)
         {
             // Layer order in file:
             //0 far back
@@ -131,7 +132,7 @@ impl Map {
             //2 back
             //3 far front
 
-            let layer_size = (width * height) as usize;
+            let layer_size = tile_count;
 
             // Temp vector to pull slices from, we will read the entire file into it
             let mut tiles = vec![0u16; layer_size * 4];
@@ -151,9 +152,9 @@ impl Map {
 
         }
         // Normal file
-        else if fsize == ((width * height) as u64 + 8)
+        else if fsize == (tile_count as u64 + 8)
         {
-            let mut tiles = vec![0u8; (width * height) as usize];
+            let mut tiles = vec![0u8; tile_count];
             map_data.read_exact(&mut tiles)?;
 
             //copy the tiles out to a u16 vector
@@ -173,6 +174,56 @@ impl Map {
         Ok(Map { width, height, tiles: tiles_u16, attrib, tile_size: TileSize::Tile16x16 })
     }
 
+    /// Writes the map in PXM format, using the NOXID 4-layer variant if the map has more than one layer.
+    pub fn write_pxm<W: io::Write>(&self, mut out: W) -> GameResult {
+        let layer_size = self.width as usize * self.height as usize;
+        let layered = self.tiles.len() == layer_size * 4;
+
+        if !layered && self.tiles.len() != layer_size {
+            return Err(GameError::InvalidValue(format!(
+                "Map has {} tiles, expected {} for {}x{}.",
+                self.tiles.len(),
+                layer_size,
+                self.width,
+                self.height
+            )));
+        }
+
+        out.write_all(b"PXM")?;
+        out.write_u8(if layered { 0x21 } else { 0x10 })?;
+        out.write_u16::<LE>(self.width)?;
+        out.write_u16::<LE>(self.height)?;
+
+        if layered {
+            // Reverse of the reordering done by load_pxm: far back, back, foreground, far front.
+            for layer in [1, 2, 0, 3] {
+                for &tile in &self.tiles[layer * layer_size..(layer + 1) * layer_size] {
+                    out.write_u16::<LE>(tile)?;
+                }
+            }
+        } else {
+            for &tile in &self.tiles {
+                let tile = u8::try_from(tile).map_err(|_| {
+                    GameError::InvalidValue(format!("Tile {} doesn't fit in a single layer PXM map.", tile))
+                })?;
+                out.write_u8(tile)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the tile attributes in PXA format.
+    pub fn write_pxa<W: io::Write>(&self, mut out: W) -> GameResult {
+        let mut attrib = [0u8; 0x100];
+        let len = self.attrib.len().min(attrib.len());
+        attrib[..len].copy_from_slice(&self.attrib[..len]);
+
+        out.write_all(&attrib)?;
+
+        Ok(())
+    }
+
 
     pub fn load_pxpack<R: io::Read>(
         mut map_data: R,
@@ -396,6 +447,39 @@ impl Map {
         Ok(Map { width: width_fg, height: height_fg, tiles: tiles_u16, attrib, tile_size: TileSize::Tile8x8 })
     }
 
+    /// Resizes the map, keeping all of its layers. `offset` is the position the old top left tile is moved to,
+    /// tiles which end up outside of the map are discarded and the new ones are set to 0.
+    pub fn resize(&mut self, width: u16, height: u16, offset: (i32, i32)) {
+        let (old_width, old_height) = (self.width as usize, self.height as usize);
+        let old_size = old_width * old_height;
+        let new_size = width as usize * height as usize;
+        let layers = if old_size == 0 { 1 } else { (self.tiles.len() / old_size).max(1) };
+        let mut tiles = vec![0u16; new_size * layers];
+
+        for layer in 0..layers {
+            for y in 0..old_height {
+                let new_y = y as i32 + offset.1;
+                if new_y < 0 || new_y >= height as i32 {
+                    continue;
+                }
+
+                for x in 0..old_width {
+                    let new_x = x as i32 + offset.0;
+                    if new_x < 0 || new_x >= width as i32 {
+                        continue;
+                    }
+
+                    let new_index = layer * new_size + new_y as usize * width as usize + new_x as usize;
+                    tiles[new_index] = self.tiles[layer * old_size + y * old_width + x];
+                }
+            }
+        }
+
+        self.width = width;
+        self.height = height;
+        self.tiles = tiles;
+    }
+
     pub fn get_attribute(&self, x: usize, y: usize) -> u8 {
         if x >= self.width as usize || y >= self.height as usize {
             return 0;
@@ -576,7 +660,7 @@ impl Map {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct NPCData {
     pub id: u16,
     pub x: i16,
@@ -622,6 +706,25 @@ impl NPCData {
 
         Ok(npcs)
     }
+
+    /// Writes the entity list in PXE format, version 0x10 with the layer field.
+    pub fn write_to<W: io::Write>(npcs: &[NPCData], mut out: W) -> GameResult {
+        out.write_all(b"PXE")?;
+        out.write_u8(0x10)?;
+        out.write_u32::<LE>(npcs.len() as u32)?;
+
+        for npc in npcs {
+            out.write_i16::<LE>(npc.x)?;
+            out.write_i16::<LE>(npc.y)?;
+            out.write_u16::<LE>(npc.flag_num)?;
+            out.write_u16::<LE>(npc.event_num)?;
+            out.write_u16::<LE>(npc.npc_type)?;
+            out.write_u16::<LE>(npc.flags)?;
+            out.write_u8(npc.layer)?;
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -732,3 +835,69 @@ impl WaterParams {
         self.entries.get(&tile).unwrap_or(&DEFAULT_ENTRY)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_pxm_roundtrip() {
+        let attrib = vec![0x41u8; 0x100];
+
+        for tiles in [vec![1u16, 2, 3, 4, 5, 6], (0..24).collect::<Vec<u16>>()] {
+            let map = Map { width: 3, height: 2, tiles, attrib: attrib.clone(), tile_size: TileSize::Tile16x16 };
+
+            let (mut map_data, mut attrib_data) = (Vec::new(), Vec::new());
+            map.write_pxm(&mut map_data).unwrap();
+            map.write_pxa(&mut attrib_data).unwrap();
+            assert_eq!(attrib_data.len(), 0x100);
+
+            let loaded = Map::load_pxm(Cursor::new(map_data), Cursor::new(attrib_data)).unwrap();
+            assert_eq!((loaded.width, loaded.height), (3, 2));
+            assert_eq!(loaded.tiles, map.tiles);
+        }
+
+        // More tiles than fit in an u16.
+        let tiles = vec![7; 300 * 300];
+        let map = Map { width: 300, height: 300, tiles, attrib: attrib.clone(), tile_size: TileSize::Tile16x16 };
+        let mut map_data = Vec::new();
+        map.write_pxm(&mut map_data).unwrap();
+        let loaded = Map::load_pxm(Cursor::new(map_data), Cursor::new(attrib.clone())).unwrap();
+        assert_eq!(loaded.tiles, map.tiles);
+
+        let map = Map { width: 1, height: 1, tiles: vec![0x100], attrib, tile_size: TileSize::Tile16x16 };
+        assert!(map.write_pxm(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_resize() {
+        let tiles = (1..=6).collect();
+        let mut map = Map { width: 3, height: 2, tiles, attrib: Vec::new(), tile_size: TileSize::Tile16x16 };
+
+        map.resize(2, 3, (-1, 1));
+        assert_eq!((map.width, map.height), (2, 3));
+        assert_eq!(map.tiles, vec![0, 0, 2, 3, 5, 6]);
+
+        let tiles = (1..=8).collect();
+        let mut map = Map { width: 1, height: 2, tiles, attrib: Vec::new(), tile_size: TileSize::Tile16x16 };
+
+        map.resize(2, 2, (1, 0));
+        assert_eq!(map.tiles, vec![0, 1, 0, 2, 0, 3, 0, 4, 0, 5, 0, 6, 0, 7, 0, 8]);
+    }
+
+    #[test]
+    fn test_pxe_roundtrip() {
+        let npcs = vec![
+            NPCData { id: 170, x: 10, y: -2, flag_num: 300, event_num: 400, npc_type: 46, flags: 0x8100, layer: 0 },
+            NPCData { id: 171, x: 0, y: 5, flag_num: 0, event_num: 0, npc_type: 1, flags: 0, layer: 2 },
+        ];
+
+        let mut data = Vec::new();
+        NPCData::write_to(&npcs, &mut data).unwrap();
+
+        let loaded = NPCData::load_from(Cursor::new(data)).unwrap();
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", npcs));
+    }
+}
@@ -14,22 +14,28 @@ use crate::framework::error::GameResult;
 use crate::framework::graphics;
 use crate::framework::graphics::VSyncMode;
 use crate::framework::ui::UI;
+use crate::game::capture::Capture;
 use crate::game::filesystem_container::FilesystemContainer;
+use crate::game::server::ServerOptions;
 use crate::game::shared_game_state::{Fps, SharedGameState, TimingMode};
 use crate::graphics::texture_set::{G_MAG, I_MAG};
 use crate::scene::loading_scene::LoadingScene;
 use crate::scene::Scene;
 
+pub mod capture;
 pub mod caret;
 pub mod filesystem_container;
 pub mod frame;
+pub mod hot_reload;
 pub mod inventory;
+pub mod lighting;
 pub mod map;
 pub mod npc;
 pub mod physics;
 pub mod player;
 pub mod profile;
 pub mod scripting;
+pub mod server;
 pub mod settings;
 pub mod shared_game_state;
 pub mod stage;
@@ -38,6 +44,7 @@ pub mod weapon;
 pub struct LaunchOptions {
     pub server_mode: bool,
     pub editor: bool,
+    pub server_options: ServerOptions,
 }
 
 lazy_static! {
@@ -55,6 +62,7 @@ pub struct Game {
     next_tick_draw: u128,
     present: bool,
     fps: Fps,
+    capture: Capture,
 }
 
 impl Game {
@@ -70,12 +78,15 @@ impl Game {
             next_tick_draw: 0,
             present: true,
             fps: Fps::new(),
+            capture: Capture::new(),
         };
 
         Ok(s)
     }
 
     pub(crate) fn update(&mut self, ctx: &mut Context) -> GameResult {
+        self.capture.process_keys(ctx);
+
         if let Some(scene) = &mut self.scene {
             let state_ref = unsafe { &mut *self.state.get() };
 
@@ -116,9 +127,11 @@ impl Game {
                         scene.tick(state_ref, ctx)?;
                     }
                     self.fps.tick_count = self.fps.tick_count.saturating_add(self.loops as u32);
+                    self.capture.add_ticks(self.loops);
                 }
                 TimingMode::FrameSynchronized => {
                     scene.tick(state_ref, ctx)?;
+                    self.capture.add_ticks(1);
                 }
             }
         }
@@ -181,6 +194,11 @@ impl Game {
             let n2 = (self.next_tick - self.last_tick) as f64;
             state_ref.frame_time = if state_ref.settings.motion_interpolation { n1 / n2 } else { 1.0 };
         }
+
+        // Recorded frames show the state right after a tick, so they match the game logic exactly.
+        if self.capture.is_recording() {
+            state_ref.frame_time = 1.0;
+        }
         unsafe {
             G_MAG = if state_ref.settings.subpixel_coords { state_ref.scale } else { 1.0 };
             I_MAG = state_ref.scale;
@@ -192,6 +210,8 @@ impl Game {
 
         if let Some(scene) = &mut self.scene {
             scene.draw(state_ref, ctx)?;
+            self.capture.capture_frame(state_ref, ctx);
+
             if state_ref.settings.touch_controls && state_ref.settings.display_touch_controls {
                 state_ref.touch_controls.draw(
                     state_ref.canvas_size,
@@ -321,6 +341,10 @@ pub fn init(options: LaunchOptions) -> GameResult {
     let mut game = Box::pin(Game::new(&mut context)?);
     game.state.get_mut().fs_container = Some(fs_container);
 
+    if options.server_mode {
+        return server::run(game.as_mut().get_mut(), &mut context, &options.server_options);
+    }
+
     #[cfg(feature = "discord-rpc")]
     if game.state.get_mut().settings.discord_rpc {
         game.state.get_mut().discord_rpc.enabled = true;
@@ -0,0 +1,382 @@
+//! Data-driven NPC types defined by mods in `custom_npcs.json`.
+//!
+//! Each definition registers a new NPC type above the vanilla range, together with its stats, sprites
+//! and a list of behaviours which are applied in order every tick, so later ones take precedence
+//! when they touch the same fields (eg. `chase` after `walk` overrides the walking direction).
+//!
+//! ```json
+//! {
+//!   "npcs": [
+//!     {
+//!       "id": 400,
+//!       "life": 8,
+//!       "damage": 2,
+//!       "flags": 32,
+//!       "spritesheet": "Npc/NpcCustom",
+//!       "hit_bounds": [6, 6, 6, 6],
+//!       "display_bounds": [8, 8, 8, 8],
+//!       "frames_left": [[0, 0, 16, 16], [16, 0, 32, 16]],
+//!       "frames_right": [[0, 16, 16, 32], [16, 16, 32, 32]],
+//!       "anim_speed": 4,
+//!       "behaviours": [
+//!         { "type": "walk", "speed": 256 },
+//!         { "type": "shoot", "npc_type": 11, "interval": 100, "speed": 512, "range": 10 },
+//!         { "type": "drop_xp", "amount": 3 }
+//!       ]
+//!     }
+//!   ]
+//! }
+//! ```
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use num_traits::abs;
+
+use crate::common::{Direction, Rect};
+use crate::framework::context::Context;
+use crate::framework::error::{GameError, GameResult};
+use crate::framework::filesystem;
+use crate::game::npc::list::NPCList;
+use crate::game::npc::{NPCFlag, NPCTable, NPCTableEntry, NPC};
+use crate::game::player::Player;
+use crate::game::shared_game_state::SharedGameState;
+
+/// Highest NPC type handled by the engine, custom types have to be above it.
+pub const MAX_BUILTIN_NPC_TYPE: u16 = 370;
+
+/// Spritesheet ids above this value refer to textures registered by custom NPC definitions.
+pub const CUSTOM_SPRITESHEET_BASE: u16 = 0x100;
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum CustomNPCSpritesheet {
+    /// Built-in spritesheet id, same as the ones used by npc.tbl.
+    Id(u16),
+    /// Path to a texture, relative to the data directory.
+    Path(String),
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CustomBehaviour {
+    /// Moves horizontally in the facing direction, turning around when hitting a wall.
+    Walk { speed: i32 },
+    /// Jumps every `interval` ticks spent on the ground.
+    Hop {
+        interval: u16,
+        jump_speed: i32,
+        #[serde(default)]
+        horizontal_speed: i32,
+        #[serde(default)]
+        sound: Option<u8>,
+    },
+    /// Accelerates towards the closest player if they're within `range` tiles.
+    Chase {
+        speed: i32,
+        range: i32,
+        #[serde(default = "default_acceleration")]
+        acceleration: i32,
+    },
+    /// Spawns a `npc_type` projectile aimed at the closest player every `interval` ticks.
+    Shoot {
+        npc_type: u16,
+        interval: u16,
+        speed: i32,
+        range: i32,
+        #[serde(default)]
+        sound: Option<u8>,
+    },
+    /// Amount of experience dropped on death.
+    DropXp { amount: u16 },
+}
+
+fn default_acceleration() -> i32 {
+    0x20
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_size() -> u8 {
+    1
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct CustomNPCDef {
+    pub id: u16,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub life: u16,
+    #[serde(default)]
+    pub damage: u16,
+    /// Raw NPC flags, same as in npc.tbl.
+    #[serde(default)]
+    pub flags: u16,
+    #[serde(default = "default_size")]
+    pub size: u8,
+    #[serde(default)]
+    pub death_sound: u8,
+    #[serde(default)]
+    pub hurt_sound: u8,
+    pub spritesheet: CustomNPCSpritesheet,
+    pub hit_bounds: Rect<u8>,
+    pub display_bounds: Rect<u8>,
+    pub frames_left: Vec<Rect<u16>>,
+    /// Uses `frames_left` if not set.
+    #[serde(default)]
+    pub frames_right: Option<Vec<Rect<u16>>>,
+    /// Ticks between animation frames, animation is disabled if set to 0.
+    #[serde(default)]
+    pub anim_speed: u16,
+    #[serde(default = "default_true")]
+    pub gravity: bool,
+    #[serde(default)]
+    pub behaviours: Vec<CustomBehaviour>,
+}
+
+impl CustomNPCDef {
+    fn experience(&self) -> u32 {
+        self.behaviours
+            .iter()
+            .map(|b| match b {
+                CustomBehaviour::DropXp { amount } => *amount as u32,
+                _ => 0,
+            })
+            .sum()
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct CustomNPCFile {
+    pub npcs: Vec<CustomNPCDef>,
+}
+
+impl NPCTable {
+    /// Loads custom NPC definitions from `custom_npcs.json` found in the data directories, if there's one.
+    pub fn load_custom_npcs(&mut self, ctx: &mut Context, roots: &Vec<String>) -> GameResult {
+        if let Ok(file) = filesystem::open_find(ctx, roots, "custom_npcs.json") {
+            match serde_json::from_reader::<_, CustomNPCFile>(file) {
+                Ok(defs) => {
+                    for def in defs.npcs {
+                        if let Err(err) = self.register_custom(def) {
+                            log::warn!("Failed to register custom NPC: {}", err);
+                        }
+                    }
+                }
+                Err(err) => log::warn!("Failed to deserialize custom NPCs: {}", err),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn register_custom(&mut self, def: CustomNPCDef) -> GameResult {
+        if def.id <= MAX_BUILTIN_NPC_TYPE {
+            return Err(GameError::InvalidValue(format!(
+                "Custom NPC type {} collides with built-in types (0-{}).",
+                def.id, MAX_BUILTIN_NPC_TYPE
+            )));
+        }
+
+        if def.frames_left.is_empty() {
+            return Err(GameError::InvalidValue(format!("Custom NPC type {} has no animation frames.", def.id)));
+        }
+
+        let spritesheet_id = match &def.spritesheet {
+            CustomNPCSpritesheet::Id(id) if *id >= CUSTOM_SPRITESHEET_BASE => {
+                return Err(GameError::InvalidValue(format!(
+                    "Custom NPC type {} uses spritesheet id {}, built-in ids are below {}.",
+                    def.id, id, CUSTOM_SPRITESHEET_BASE
+                )));
+            }
+            CustomNPCSpritesheet::Id(id) => *id,
+            CustomNPCSpritesheet::Path(path) => {
+                let idx = match self.custom_textures.iter().position(|p| p == path) {
+                    Some(idx) => idx,
+                    None => {
+                        self.custom_textures.push(path.clone());
+                        self.custom_textures.len() - 1
+                    }
+                };
+
+                CUSTOM_SPRITESHEET_BASE + idx as u16
+            }
+        };
+
+        let idx = def.id as usize;
+        while self.entries.len() <= idx {
+            self.entries.push(NPCTableEntry::default());
+        }
+
+        self.entries[idx] = NPCTableEntry {
+            npc_flags: NPCFlag(def.flags),
+            life: def.life,
+            spritesheet_id,
+            death_sound: def.death_sound,
+            hurt_sound: def.hurt_sound,
+            size: def.size,
+            experience: def.experience(),
+            damage: def.damage as u32,
+            display_bounds: def.display_bounds,
+            hit_bounds: def.hit_bounds,
+        };
+
+        self.custom.insert(def.id, Rc::new(def));
+
+        Ok(())
+    }
+
+    /// Returns the definition of a custom NPC type.
+    pub fn get_custom(&self, npc_type: u16) -> Option<Rc<CustomNPCDef>> {
+        self.custom.get(&npc_type).cloned()
+    }
+}
+
+pub(crate) type CustomNPCMap = HashMap<u16, Rc<CustomNPCDef>>;
+
+impl NPC {
+    pub(crate) fn tick_custom(
+        &mut self,
+        state: &mut SharedGameState,
+        players: [&mut Player; 2],
+        npc_list: &NPCList,
+        def: &CustomNPCDef,
+    ) -> GameResult {
+        if self.action_num == 0 {
+            self.action_num = 1;
+            self.action_counter = 0;
+            self.action_counter2 = 0;
+        }
+
+        self.action_counter = self.action_counter.wrapping_add(1);
+
+        let player = self.get_closest_player_ref(&players);
+        let in_range = |npc: &NPC, range: i32| {
+            abs(npc.x - player.x) < range * 0x2000 && abs(npc.y - player.y) < range * 0x2000
+        };
+
+        for behaviour in &def.behaviours {
+            match *behaviour {
+                CustomBehaviour::Walk { speed } => {
+                    if self.direction == Direction::Left && self.flags.hit_left_wall() {
+                        self.direction = Direction::Right;
+                    } else if self.direction == Direction::Right && self.flags.hit_right_wall() {
+                        self.direction = Direction::Left;
+                    }
+
+                    self.vel_x = self.direction.vector_x() * speed;
+                }
+                CustomBehaviour::Hop { interval, jump_speed, horizontal_speed, sound } => {
+                    if self.flags.hit_bottom_wall() {
+                        if self.action_num == 2 {
+                            self.action_num = 1;
+                            self.vel_x = 0;
+                        }
+
+                        self.action_counter2 += 1;
+                        if self.action_counter2 > interval {
+                            self.action_counter2 = 0;
+                            self.action_num = 2;
+                            self.vel_x = self.direction.vector_x() * horizontal_speed;
+                            self.vel_y = -jump_speed;
+
+                            if let Some(sound) = sound {
+                                state.sound_manager.play_sfx(sound);
+                            }
+                        }
+                    }
+                }
+                CustomBehaviour::Chase { speed, range, acceleration } => {
+                    if in_range(self, range) {
+                        self.face_player(player);
+                        self.vel_x = (self.vel_x + self.direction.vector_x() * acceleration).clamp(-speed, speed);
+                    }
+                }
+                CustomBehaviour::Shoot { npc_type, interval, speed, range, sound } => {
+                    if interval != 0 && self.action_counter % interval == 0 && in_range(self, range) {
+                        let angle = ((player.y - self.y) as f64).atan2((player.x - self.x) as f64);
+
+                        let mut npc = NPC::create(npc_type, &state.npc_table);
+                        npc.cond.set_alive(true);
+                        npc.x = self.x;
+                        npc.y = self.y;
+                        npc.direction = self.direction;
+                        npc.vel_x = (angle.cos() * speed as f64) as i32;
+                        npc.vel_y = (angle.sin() * speed as f64) as i32;
+
+                        let _ = npc_list.spawn(0x100, npc);
+
+                        if let Some(sound) = sound {
+                            state.sound_manager.play_sfx(sound);
+                        }
+                    }
+                }
+                CustomBehaviour::DropXp { .. } => {}
+            }
+        }
+
+        if def.gravity {
+            self.vel_y += 0x40;
+            self.clamp_fall_speed();
+        }
+
+        self.x += self.vel_x;
+        self.y += self.vel_y;
+
+        let frames = match (&def.frames_right, self.direction) {
+            (Some(frames), Direction::Right) if !frames.is_empty() => frames,
+            _ => &def.frames_left,
+        };
+
+        if def.anim_speed > 0 {
+            self.animate(def.anim_speed, 0, frames.len() as u16 - 1);
+        }
+
+        self.anim_rect = frames.get(self.anim_num as usize).copied().unwrap_or(frames[0]);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_custom_npc_parse() {
+        let json = r#"{
+            "npcs": [{
+                "id": 400,
+                "life": 8,
+                "spritesheet": "Npc/NpcCustom",
+                "hit_bounds": [6, 6, 6, 6],
+                "display_bounds": [8, 8, 8, 8],
+                "frames_left": [[0, 0, 16, 16]],
+                "behaviours": [
+                    { "type": "hop", "interval": 30, "jump_speed": 1535 },
+                    { "type": "drop_xp", "amount": 3 },
+                    { "type": "drop_xp", "amount": 2 }
+                ]
+            }]
+        }"#;
+
+        let file: CustomNPCFile = serde_json::from_str(json).unwrap();
+        let def = file.npcs.into_iter().next().unwrap();
+        assert!(def.gravity);
+        assert_eq!(def.experience(), 5);
+
+        let mut table = NPCTable::new();
+        assert!(table.register_custom(CustomNPCDef { id: 100, ..def.clone() }).is_err());
+        let spritesheet = CustomNPCSpritesheet::Id(CUSTOM_SPRITESHEET_BASE);
+        assert!(table.register_custom(CustomNPCDef { spritesheet, ..def.clone() }).is_err());
+        table.register_custom(def).unwrap();
+
+        let entry = table.get_entry(400).unwrap();
+        assert_eq!(entry.life, 8);
+        assert_eq!(entry.spritesheet_id, CUSTOM_SPRITESHEET_BASE);
+        assert_eq!(&*table.get_texture_ref(entry.spritesheet_id), "Npc/NpcCustom");
+        assert!(table.get_custom(400).is_some());
+    }
+}
@@ -17,6 +17,7 @@ use crate::framework::context::Context;
 use crate::framework::error::GameResult;
 use crate::game::frame::Frame;
 use crate::game::npc::boss::BossNPC;
+use crate::game::npc::custom::{CustomNPCMap, CUSTOM_SPRITESHEET_BASE};
 use crate::game::npc::list::NPCList;
 use crate::game::physics::PhysicalEntity;
 use crate::game::player::Player;
@@ -27,6 +28,7 @@ use crate::util::rng::Xoroshiro32PlusPlus;
 
 pub mod ai;
 pub mod boss;
+pub mod custom;
 pub mod list;
 pub mod utils;
 
@@ -618,7 +620,10 @@ impl GameEntity<([&mut Player; 2], &NPCList, &mut Stage, &mut BulletManager, &mu
             368 => self.tick_n368_gclone(state, players, npc_list),
             369 => self.tick_n369_gclone_curly_clone(state, players, npc_list),
             370 => self.tick_n370_second_quote(state, players, npc_list),
-            _ => Ok(()),
+            _ => match state.npc_table.get_custom(self.npc_type) {
+                Some(def) => self.tick_custom(state, players, npc_list, &def),
+                None => Ok(()),
+            },
         }?;
 
         // I don't know where the best place to put this is, but let's try putting it here
@@ -798,7 +803,7 @@ impl PhysicalEntity for NPC {
 pub struct NPCTableEntry {
     pub npc_flags: NPCFlag,
     pub life: u16,
-    pub spritesheet_id: u8,
+    pub spritesheet_id: u16,
     pub death_sound: u8,
     pub hurt_sound: u8,
     pub size: u8,
@@ -808,15 +813,41 @@ pub struct NPCTableEntry {
     pub hit_bounds: Rect<u8>,
 }
 
+impl Default for NPCTableEntry {
+    fn default() -> Self {
+        NPCTableEntry {
+            npc_flags: NPCFlag(0),
+            life: 0,
+            spritesheet_id: 0,
+            death_sound: 0,
+            hurt_sound: 0,
+            size: 0,
+            experience: 0,
+            damage: 0,
+            display_bounds: Rect::new(0, 0, 0, 0),
+            hit_bounds: Rect::new(0, 0, 0, 0),
+        }
+    }
+}
+
 pub struct NPCTable {
     entries: Vec<NPCTableEntry>,
+    /// Definitions of NPC types registered through `custom_npcs.json`.
+    custom: CustomNPCMap,
+    /// Textures used by custom NPC types, indexed from `CUSTOM_SPRITESHEET_BASE`.
+    custom_textures: Vec<String>,
     pub stage_textures: Rc<RefCell<StageTexturePaths>>,
 }
 
 impl NPCTable {
     #[allow(clippy::new_without_default)]
     pub fn new() -> NPCTable {
-        NPCTable { entries: Vec::new(), stage_textures: Rc::new(RefCell::new(StageTexturePaths::new())) }
+        NPCTable {
+            entries: Vec::new(),
+            custom: CustomNPCMap::new(),
+            custom_textures: Vec::new(),
+            stage_textures: Rc::new(RefCell::new(StageTexturePaths::new())),
+        }
     }
 
     pub fn load_from<R: io::Read>(mut data: R) -> GameResult<NPCTable> {
@@ -829,18 +860,7 @@ impl NPCTable {
         let mut f = Cursor::new(buf);
 
         for _ in 0..count {
-            table.entries.push(NPCTableEntry {
-                npc_flags: NPCFlag(0),
-                life: 0,
-                spritesheet_id: 0,
-                death_sound: 0,
-                hurt_sound: 0,
-                size: 0,
-                experience: 0,
-                damage: 0,
-                display_bounds: Rect::new(0, 0, 0, 0),
-                hit_bounds: Rect::new(0, 0, 0, 0),
-            });
+            table.entries.push(NPCTableEntry::default());
         }
 
         for npc in &mut table.entries {
@@ -852,7 +872,7 @@ impl NPCTable {
         }
 
         for npc in &mut table.entries {
-            npc.spritesheet_id = f.read_u8()?;
+            npc.spritesheet_id = f.read_u8()? as u16;
         }
 
         for npc in &mut table.entries {
@@ -941,6 +961,12 @@ impl NPCTable {
             23 => TexRef::from_str("Npc/NpcRegu"),
             26 => TexRef::from_str("TextBox"),
             27 => TexRef::from_str("Face"),
+            id if id >= CUSTOM_SPRITESHEET_BASE => {
+                match self.custom_textures.get((id - CUSTOM_SPRITESHEET_BASE) as usize) {
+                    Some(path) => TexRef { variant: TexRefVariant::Custom(path) },
+                    None => TexRef::from_str("Npc/Npc0"),
+                }
+            }
             _ => TexRef::from_str("Npc/Npc0"),
         }
     }
@@ -955,6 +981,7 @@ enum TexRefVariant<'a> {
     StageTileset(Ref<'a, StageTexturePaths>),
     StageNPC1(Ref<'a, StageTexturePaths>),
     StageNPC2(Ref<'a, StageTexturePaths>),
+    Custom(&'a str),
 }
 
 impl TexRef<'_> {
@@ -974,6 +1001,7 @@ impl Deref for TexRef<'_> {
             TexRefVariant::StageTileset(paths) => &paths.tileset_fg,
             TexRefVariant::StageNPC1(paths) => &paths.npc1,
             TexRefVariant::StageNPC2(paths) => &paths.npc2,
+            TexRefVariant::Custom(path) => path,
         }
     }
 }
@@ -42,7 +42,7 @@ impl NPC {
                 entry.damage as u16,
                 entry.npc_flags,
                 entry.experience as u16,
-                entry.spritesheet_id as u16,
+                entry.spritesheet_id,
             ),
             None => (2, 0, 0, NPCFlag(0), 0, 0),
         };
@@ -331,6 +331,9 @@ impl NPCList {
 
             state.set_flag(npc.flag_num as usize, true);
 
+            #[cfg(feature = "scripting-lua")]
+            state.lua.queue_npc_death(npc);
+
             if npc.npc_flags.show_damage() {
                 if npc.popup.value != 0 {
                     npc.popup.update_displayed_value();
@@ -22,7 +22,7 @@ use crate::util::rng::RNG;
 mod player_hit;
 pub mod skin;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, serde::Serialize, serde::Deserialize)]
 #[repr(u8)]
 pub enum ControlMode {
     Normal = 0,
@@ -1001,7 +1001,7 @@ impl GameEntity<&NPCList> for Player {
                 let vec_y = 0x1400;
 
                 if let Some(entry) = state.npc_table.get_entry(136) {
-                    let sprite = &*state.npc_table.get_texture_ref(entry.spritesheet_id as u16);
+                    let sprite = &*state.npc_table.get_texture_ref(entry.spritesheet_id);
                     let batch = state.texture_set.get_or_load_batch(ctx, &state.constants, sprite)?;
 
                     let (off_x, frame_id) = if self.direction == Direction::Left {
@@ -1,14 +1,18 @@
+use std::collections::BTreeMap;
 use std::io;
+use std::io::Cursor;
 
 use byteorder::{BE, LE, ReadBytesExt, WriteBytesExt};
 use num_traits::{clamp, FromPrimitive};
 
 use crate::common::{Direction, FadeState, get_timestamp};
 use crate::framework::context::Context;
-use crate::framework::error::GameError::ResourceLoadError;
+use crate::framework::error::GameError::{InvalidValue, ResourceLoadError};
 use crate::framework::error::GameResult;
 use crate::game::player::{ControlMode, TargetPlayer};
-use crate::game::shared_game_state::{GameDifficulty, SharedGameState};
+use crate::game::shared_game_state::{
+    GameDifficulty, PlayerSkinLocation, SharedGameState, GAME_FLAG_COUNT, VARIABLE_COUNT,
+};
 use crate::game::weapon::{WeaponLevel, WeaponType};
 use crate::scene::game_scene::GameScene;
 
@@ -25,6 +29,20 @@ pub struct TeleporterSlotData {
     pub event_num: u32,
 }
 
+/// Tiles changed by scripts in a stage, as pairs of the index in map tiles and the tile type.
+pub struct StageTileDiff {
+    pub stage_id: u32,
+    pub tiles: Vec<(u32, u16)>,
+}
+
+// Extended save sections, stored after the vanilla layout as a big endian tag, the little endian length
+// of the contents and the contents. Vanilla and older engine versions ignore them, unknown ones are skipped.
+const SECTION_FLAGS: u32 = 0x464c4758; // FLGX
+const SECTION_VARIABLES: u32 = 0x56415253; // VARS
+const SECTION_TILES: u32 = 0x54494c45; // TILE
+const SECTION_SKIN: u32 = 0x534b494e; // SKIN
+const SECTION_MOD_DATA: u32 = 0x4d4f4444; // MODD
+
 pub struct GameProfile {
     pub current_map: u32,
     pub current_song: u32,
@@ -46,6 +64,13 @@ pub struct GameProfile {
     pub flags: [u8; 1000],
     pub timestamp: u64,
     pub difficulty: u8,
+    /// Flags above the vanilla 8000, packed the same way as `flags`. Trailing zeroes are omitted.
+    pub extra_flags: Vec<u8>,
+    /// TSC variables, trailing zeroes are omitted.
+    pub variables: Vec<i32>,
+    pub tile_diffs: Vec<StageTileDiff>,
+    pub player2_skin: Option<PlayerSkinLocation>,
+    pub mod_data: BTreeMap<String, BTreeMap<String, String>>,
 }
 
 impl GameProfile {
@@ -132,6 +157,36 @@ impl GameProfile {
             }
         }
 
+        for (idx, &flags) in self.extra_flags.iter().enumerate() {
+            for bit in 0..8 {
+                if flags & (1 << bit) != 0 {
+                    state.set_flag(self.flags.len() * 8 + idx * 8 + bit, true);
+                }
+            }
+        }
+
+        state.variables = vec![0; VARIABLE_COUNT];
+        for (idx, &value) in self.variables.iter().enumerate() {
+            state.set_variable(idx, value);
+        }
+
+        for diff in self.tile_diffs.iter().filter(|diff| diff.stage_id as usize == game_scene.stage_id) {
+            for &(index, tile) in &diff.tiles {
+                if let Some(ptr) = game_scene.stage.map.tiles.get_mut(index as usize) {
+                    *ptr = tile;
+                    game_scene.stage.tile_edits.insert(index as usize, tile);
+                }
+            }
+        }
+
+        if let Some(skin) = self.player2_skin {
+            if (skin.texture_index as usize) < state.constants.player_skin_paths.len() {
+                state.player2_skin_location = skin;
+            }
+        }
+
+        state.mod_data = self.mod_data.clone();
+
         state.textscript_vm.start_script(0);
 
         game_scene.player1.equip.0 = self.equipment as u16;
@@ -235,12 +290,28 @@ impl GameProfile {
             }
         }
 
+        let mut all_flags = vec![0u8; GAME_FLAG_COUNT / 8];
+        state.game_flags.copy_to_slice(&mut all_flags);
+
         let mut flags = [0u8; 1000];
-        state.game_flags.copy_to_slice(&mut flags);
+        flags.copy_from_slice(&all_flags[..1000]);
+
+        let extra_flags_len = all_flags[1000..].iter().rposition(|&f| f != 0).map_or(0, |idx| idx + 1);
+        let extra_flags = all_flags[1000..1000 + extra_flags_len].to_vec();
 
         let timestamp = get_timestamp();
         let difficulty = state.difficulty as u8;
 
+        let variable_count = state.variables.iter().rposition(|&v| v != 0).map_or(0, |idx| idx + 1);
+        let variables = state.variables[..variable_count].to_vec();
+
+        // Vanilla reloads stages from scratch when entering them, so only the current one's changes are kept.
+        let mut tile_diffs = Vec::new();
+        if !game_scene.stage.tile_edits.is_empty() {
+            let tiles = game_scene.stage.tile_edits.iter().map(|(&index, &tile)| (index as u32, tile)).collect();
+            tile_diffs.push(StageTileDiff { stage_id: current_map, tiles });
+        }
+
         GameProfile {
             current_map,
             current_song,
@@ -262,6 +333,11 @@ impl GameProfile {
             flags,
             timestamp,
             difficulty,
+            extra_flags,
+            variables,
+            tile_diffs,
+            player2_skin: Some(state.player2_skin_location),
+            mod_data: state.mod_data.clone(),
         }
     }
 
@@ -311,6 +387,51 @@ impl GameProfile {
         data.write_u64::<LE>(self.timestamp)?;
         data.write_u8(self.difficulty)?;
 
+        if !self.extra_flags.is_empty() {
+            write_section(&mut data, SECTION_FLAGS, &self.extra_flags)?;
+        }
+
+        if !self.variables.is_empty() {
+            let mut section = Vec::new();
+            for value in self.variables.iter().copied() {
+                section.write_i32::<LE>(value)?;
+            }
+            write_section(&mut data, SECTION_VARIABLES, &section)?;
+        }
+
+        if !self.tile_diffs.is_empty() {
+            let mut section = Vec::new();
+            for diff in &self.tile_diffs {
+                section.write_u32::<LE>(diff.stage_id)?;
+                section.write_u32::<LE>(diff.tiles.len() as u32)?;
+                for &(index, tile) in &diff.tiles {
+                    section.write_u32::<LE>(index)?;
+                    section.write_u16::<LE>(tile)?;
+                }
+            }
+            write_section(&mut data, SECTION_TILES, &section)?;
+        }
+
+        if let Some(skin) = self.player2_skin {
+            let mut section = Vec::new();
+            section.write_u16::<LE>(skin.texture_index)?;
+            section.write_u16::<LE>(skin.offset)?;
+            write_section(&mut data, SECTION_SKIN, &section)?;
+        }
+
+        if !self.mod_data.is_empty() {
+            let mut section = Vec::new();
+            for (mod_name, entries) in &self.mod_data {
+                write_string(&mut section, mod_name)?;
+                section.write_u32::<LE>(entries.len() as u32)?;
+                for (key, value) in entries {
+                    write_string(&mut section, key)?;
+                    write_string(&mut section, value)?;
+                }
+            }
+            write_section(&mut data, SECTION_MOD_DATA, &section)?;
+        }
+
         Ok(())
     }
 
@@ -389,7 +510,7 @@ impl GameProfile {
         let timestamp = data.read_u64::<LE>().unwrap_or(0);
         let difficulty = data.read_u8().unwrap_or(0);
 
-        Ok(GameProfile {
+        let mut profile = GameProfile {
             current_map,
             current_song,
             pos_x,
@@ -410,6 +531,217 @@ impl GameProfile {
             flags,
             timestamp,
             difficulty,
-        })
+            extra_flags: Vec::new(),
+            variables: Vec::new(),
+            tile_diffs: Vec::new(),
+            player2_skin: None,
+            mod_data: BTreeMap::new(),
+        };
+
+        // Saves made by vanilla and older versions of the engine end here. Anything that can't be parsed after
+        // that is most likely garbage, so the sections read so far are kept instead of rejecting the whole save.
+        while let Ok(tag) = data.read_u32::<BE>() {
+            let Ok(len) = data.read_u32::<LE>() else {
+                log::warn!("Ignoring trailing data at the end of the save file.");
+                break;
+            };
+
+            let mut section = Vec::new();
+            if data.by_ref().take(len as u64).read_to_end(&mut section)? != len as usize {
+                log::warn!("Save section {:08x} is truncated, ignoring the rest of the save file.", tag);
+                break;
+            }
+
+            if let Err(err) = profile.read_section(tag, &section) {
+                log::warn!("Cannot read save section {:08x}: {}, ignoring the rest of the save file.", tag, err);
+                break;
+            }
+        }
+
+        Ok(profile)
+    }
+
+    fn read_section(&mut self, tag: u32, section: &[u8]) -> GameResult {
+        let mut data = Cursor::new(section);
+
+        match tag {
+            SECTION_FLAGS => {
+                let max_len = GAME_FLAG_COUNT / 8 - self.flags.len();
+                self.extra_flags = section[..section.len().min(max_len)].to_vec();
+            }
+            SECTION_VARIABLES => {
+                let count = (section.len() / 4).min(VARIABLE_COUNT);
+                self.variables = (0..count).map(|_| data.read_i32::<LE>()).collect::<io::Result<_>>()?;
+            }
+            SECTION_TILES => {
+                while (data.position() as usize) < section.len() {
+                    let stage_id = data.read_u32::<LE>()?;
+                    let count = data.read_u32::<LE>()?;
+                    let mut tiles = Vec::new();
+                    for _ in 0..count {
+                        tiles.push((data.read_u32::<LE>()?, data.read_u16::<LE>()?));
+                    }
+
+                    self.tile_diffs.push(StageTileDiff { stage_id, tiles });
+                }
+            }
+            SECTION_SKIN => {
+                let texture_index = data.read_u16::<LE>()?;
+                let offset = data.read_u16::<LE>()?;
+                self.player2_skin = Some(PlayerSkinLocation::new(texture_index, offset));
+            }
+            SECTION_MOD_DATA => {
+                while (data.position() as usize) < section.len() {
+                    let mod_name = read_string(&mut data)?;
+                    let count = data.read_u32::<LE>()?;
+                    let entries = self.mod_data.entry(mod_name).or_default();
+                    for _ in 0..count {
+                        let key = read_string(&mut data)?;
+                        entries.insert(key, read_string(&mut data)?);
+                    }
+                }
+            }
+            _ => log::warn!("Skipping unknown save section {:08x}.", tag),
+        }
+
+        Ok(())
+    }
+}
+
+fn write_section<W: io::Write>(data: &mut W, tag: u32, section: &[u8]) -> GameResult {
+    data.write_u32::<BE>(tag)?;
+    data.write_u32::<LE>(section.len() as u32)?;
+    data.write_all(section)?;
+
+    Ok(())
+}
+
+fn write_string<W: io::Write>(data: &mut W, string: &str) -> GameResult {
+    if string.len() > u16::MAX as usize {
+        return Err(InvalidValue(format!("String is too long to be saved ({} bytes).", string.len())));
+    }
+
+    data.write_u16::<LE>(string.len() as u16)?;
+    data.write_all(string.as_bytes())?;
+
+    Ok(())
+}
+
+fn read_string<R: io::Read>(data: &mut R) -> GameResult<String> {
+    let len = data.read_u16::<LE>()? as usize;
+    let mut buf = vec![0u8; len];
+    data.read_exact(&mut buf)?;
+
+    String::from_utf8(buf).map_err(|_| ResourceLoadError("Invalid UTF-8 string in save data.".to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a save in the vanilla Profile.dat layout, which ends right after the flags.
+    fn vanilla_save() -> Vec<u8> {
+        let mut data = Vec::new();
+        data.write_u64::<BE>(0x446f303431323230).unwrap();
+        data.write_u32::<LE>(12).unwrap(); // map
+        data.write_u32::<LE>(8).unwrap(); // song
+        data.write_i32::<LE>(0x6000).unwrap();
+        data.write_i32::<LE>(0x4000).unwrap();
+        data.write_u32::<LE>(2).unwrap(); // direction
+        data.write_u16::<LE>(6).unwrap(); // max life
+        data.write_u16::<LE>(0).unwrap(); // stars
+        data.write_u16::<LE>(5).unwrap(); // life
+        data.resize(0x218, 0); // weapons, items, teleporter slots and map flags
+        data.write_u32::<BE>(0x464c4147).unwrap();
+
+        let mut flags = [0u8; 1000];
+        flags[0] = 0b0000_0010;
+        data.extend_from_slice(&flags);
+
+        assert_eq!(data.len(), 0x604);
+        data
+    }
+
+    #[test]
+    fn test_vanilla_import() {
+        let profile = GameProfile::load_from_save(Cursor::new(vanilla_save())).unwrap();
+
+        assert_eq!(profile.current_map, 12);
+        assert_eq!(profile.current_song, 8);
+        assert_eq!((profile.pos_x, profile.pos_y), (0x6000, 0x4000));
+        assert_eq!(profile.direction, Direction::Right);
+        assert_eq!((profile.max_life, profile.life), (6, 5));
+        assert_eq!(profile.flags[0], 0b0000_0010);
+        assert_eq!(profile.timestamp, 0);
+        assert_eq!(profile.difficulty, 0);
+        assert!(profile.extra_flags.is_empty());
+        assert!(profile.variables.is_empty());
+        assert!(profile.tile_diffs.is_empty());
+        assert!(profile.player2_skin.is_none());
+        assert!(profile.mod_data.is_empty());
+    }
+
+    fn extended_profile() -> GameProfile {
+        let mut profile = GameProfile::load_from_save(Cursor::new(vanilla_save())).unwrap();
+        profile.extra_flags = vec![0, 0x80, 1];
+        profile.variables = vec![1, -2, i32::MAX];
+        profile.tile_diffs = vec![
+            StageTileDiff { stage_id: 3, tiles: vec![(10, 0x41), (11, 0)] },
+            StageTileDiff { stage_id: 70, tiles: vec![(0, 0x1ff)] },
+        ];
+        profile.player2_skin = Some(PlayerSkinLocation::new(2, 16));
+        profile.mod_data.entry("mod".to_owned()).or_default().insert("key".to_owned(), "value".to_owned());
+        profile
+    }
+
+    #[test]
+    fn test_sections_roundtrip() {
+        let profile = extended_profile();
+        let mut data = Vec::new();
+        profile.write_save(&mut data).unwrap();
+
+        let loaded = GameProfile::load_from_save(Cursor::new(data)).unwrap();
+
+        assert_eq!(loaded.current_map, 12);
+        assert_eq!(loaded.extra_flags, profile.extra_flags);
+        assert_eq!(loaded.variables, profile.variables);
+        assert_eq!(loaded.tile_diffs.len(), 2);
+        for (loaded, diff) in loaded.tile_diffs.iter().zip(profile.tile_diffs.iter()) {
+            assert_eq!(loaded.stage_id, diff.stage_id);
+            assert_eq!(loaded.tiles, diff.tiles);
+        }
+        assert!(loaded.player2_skin == profile.player2_skin);
+        assert_eq!(loaded.mod_data, profile.mod_data);
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        let profile = extended_profile();
+        let mut data = Vec::new();
+        profile.write_save(&mut data).unwrap();
+
+        // a truncated last section is dropped, the sections before it are kept
+        let truncated = GameProfile::load_from_save(Cursor::new(&data[..data.len() - 3])).unwrap();
+        assert_eq!(truncated.variables, profile.variables);
+        assert!(truncated.player2_skin == profile.player2_skin);
+        assert!(truncated.mod_data.is_empty());
+
+        // unknown sections are skipped, garbage that doesn't form a section is ignored
+        let mut garbage = data.clone();
+        write_section(&mut garbage, 0x4a554e4b, &[1, 2, 3, 4]).unwrap();
+        garbage.extend_from_slice(&[0xff, 0xff, 0xff]);
+        let loaded = GameProfile::load_from_save(Cursor::new(garbage)).unwrap();
+        assert_eq!(loaded.mod_data, profile.mod_data);
+
+        // a section with a bogus length doesn't allocate or fail the whole save
+        let mut bogus = vanilla_save();
+        bogus.write_u32::<BE>(SECTION_VARIABLES).unwrap();
+        bogus.write_u32::<LE>(u32::MAX).unwrap();
+        bogus.extend_from_slice(&[1, 0, 0, 0]);
+        let loaded = GameProfile::load_from_save(Cursor::new(bogus)).unwrap();
+        assert_eq!(loaded.current_map, 12);
+        assert!(loaded.variables.is_empty());
+
+        assert!(GameProfile::load_from_save(Cursor::new(&data[..0x300])).is_err());
     }
 }
@@ -0,0 +1,539 @@
+//! Embedded Lua runtime, lets mods extend the game logic without adding new TSC opcodes.
+//!
+//! Every `scripts/*.lua` file found in the data directories is executed when resources are (re)loaded.
+//! Scripts register their handlers through the global `doukutsu` table:
+//!
+//! ```lua
+//! doukutsu.on("stage_load", function(game, stage_id) end)
+//! doukutsu.on("tick", function(game) end)
+//! doukutsu.on("npc_death", function(game, npc) end)
+//! doukutsu.on("event_start", function(game, event_num) end)
+//! ```
+//!
+//! The `game` table passed to handlers is only valid for the duration of the call.
+
+use std::cell::RefCell;
+use std::io::Read;
+use std::rc::Rc;
+
+use mlua::{Function, Lua, LuaOptions, Scope, StdLib, Table, Value};
+use num_traits::FromPrimitive;
+
+use crate::common::Direction;
+use crate::components::tilemap::TileLayer;
+use crate::framework::context::Context;
+use crate::framework::error::GameResult;
+use crate::framework::filesystem;
+use crate::game::npc::NPC;
+use crate::game::player::Player;
+use crate::game::shared_game_state::SharedGameState;
+use crate::game::weapon::WeaponType;
+use crate::scene::game_scene::GameScene;
+
+const HOOKS_KEY: &str = "doukutsu_hooks";
+
+/// Game events scripts can subscribe to.
+#[derive(Debug, Clone, Copy)]
+pub enum LuaHook {
+    StageLoad(usize),
+    Tick,
+    NPCDeath(NPCInfo),
+    EventStart(u16),
+}
+
+impl LuaHook {
+    fn name(&self) -> &'static str {
+        match self {
+            LuaHook::StageLoad(_) => "stage_load",
+            LuaHook::Tick => "tick",
+            LuaHook::NPCDeath(_) => "npc_death",
+            LuaHook::EventStart(_) => "event_start",
+        }
+    }
+}
+
+/// Snapshot of an NPC passed to scripts, the NPC itself might be already gone once the hook runs.
+#[derive(Debug, Clone, Copy)]
+pub struct NPCInfo {
+    pub id: u16,
+    pub npc_type: u16,
+    pub x: i32,
+    pub y: i32,
+    pub life: u16,
+    pub flag_num: u16,
+    pub event_num: u16,
+    pub action_num: u16,
+}
+
+impl NPCInfo {
+    pub fn from_npc(npc: &NPC) -> NPCInfo {
+        NPCInfo {
+            id: npc.id,
+            npc_type: npc.npc_type,
+            x: npc.x,
+            y: npc.y,
+            life: npc.life,
+            flag_num: npc.flag_num,
+            event_num: npc.event_num,
+            action_num: npc.action_num,
+        }
+    }
+
+    fn to_table<'lua>(&self, lua: &'lua Lua) -> mlua::Result<Table<'lua>> {
+        let table = lua.create_table()?;
+        table.set("id", self.id)?;
+        table.set("type", self.npc_type)?;
+        table.set("x", self.x)?;
+        table.set("y", self.y)?;
+        table.set("life", self.life)?;
+        table.set("flag", self.flag_num)?;
+        table.set("event", self.event_num)?;
+        table.set("action", self.action_num)?;
+
+        Ok(table)
+    }
+}
+
+pub struct LuaScriptingState {
+    runtime: Option<Rc<Lua>>,
+    /// NPC deaths are reported from places without access to the scene, so they're dispatched at the end of the tick.
+    pending_npc_deaths: Vec<NPCInfo>,
+}
+
+impl LuaScriptingState {
+    pub fn new() -> LuaScriptingState {
+        LuaScriptingState { runtime: None, pending_npc_deaths: Vec::new() }
+    }
+
+    /// Drops the current runtime and executes all scripts found in `scripts/` of given data directories.
+    pub fn reload_scripts(&mut self, ctx: &mut Context, roots: &Vec<String>) -> GameResult {
+        let mut paths = Vec::new();
+        for root in roots.iter() {
+            if let Ok(files) = filesystem::read_dir(ctx, [root, "scripts/"].join("")) {
+                let mut files: Vec<_> =
+                    files.filter(|f| f.to_string_lossy().to_lowercase().ends_with(".lua")).collect();
+                files.sort();
+                paths.extend(files);
+            }
+        }
+
+        let mut scripts = Vec::new();
+        for path in paths {
+            let mut source = String::new();
+            filesystem::open(ctx, &path)?.read_to_string(&mut source)?;
+            scripts.push((path.to_string_lossy().into_owned(), source));
+        }
+
+        self.load_scripts(scripts)
+    }
+
+    /// Drops the current runtime and executes given scripts, as pairs of the script name and its source.
+    pub fn load_scripts(&mut self, scripts: Vec<(String, String)>) -> GameResult {
+        self.runtime = None;
+        self.pending_npc_deaths.clear();
+
+        if scripts.is_empty() {
+            return Ok(());
+        }
+
+        // Scripts come from mods, so they get no access to the file system, processes or native modules.
+        let libs = StdLib::TABLE | StdLib::STRING | StdLib::MATH | StdLib::UTF8 | StdLib::COROUTINE;
+        let lua = Lua::new_with(libs, LuaOptions::default())?;
+        for name in ["dofile", "loadfile", "require"] {
+            lua.globals().set(name, Value::Nil)?;
+        }
+
+        lua.set_named_registry_value(HOOKS_KEY, lua.create_table()?)?;
+
+        let doukutsu = lua.create_table()?;
+        doukutsu.set(
+            "on",
+            lua.create_function(|lua, (name, handler): (String, Function)| {
+                let hooks: Table = lua.named_registry_value(HOOKS_KEY)?;
+                let handlers = match hooks.get::<_, Option<Table>>(name.as_str())? {
+                    Some(handlers) => handlers,
+                    None => {
+                        let handlers = lua.create_table()?;
+                        hooks.set(name.as_str(), handlers.clone())?;
+                        handlers
+                    }
+                };
+
+                handlers.raw_set(handlers.raw_len() + 1, handler)
+            })?,
+        )?;
+        doukutsu.set(
+            "log",
+            lua.create_function(|_, message: String| {
+                log::info!("[lua] {}", message);
+                Ok(())
+            })?,
+        )?;
+        lua.globals().set("doukutsu", doukutsu)?;
+
+        for (name, source) in scripts {
+            // a broken script shouldn't take the whole game down
+            if let Err(e) = lua.load(&source).set_name(name.as_str()).exec() {
+                log::error!("Failed to load script {}: {}", name, e);
+                continue;
+            }
+
+            log::info!("Loaded script {}.", name);
+        }
+
+        self.runtime = Some(Rc::new(lua));
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn is_active(&self) -> bool {
+        self.runtime.is_some()
+    }
+
+    pub fn queue_npc_death(&mut self, npc: &NPC) {
+        if self.is_active() {
+            self.pending_npc_deaths.push(NPCInfo::from_npc(npc));
+        }
+    }
+
+    /// Runs the per-tick handlers and dispatches NPC deaths queued during the tick.
+    pub fn run_tick(state: &mut SharedGameState, game_scene: &mut GameScene) -> GameResult {
+        LuaScriptingState::run_hook(state, game_scene, LuaHook::Tick)?;
+
+        while !state.lua.pending_npc_deaths.is_empty() {
+            let deaths = std::mem::take(&mut state.lua.pending_npc_deaths);
+            for npc in deaths {
+                LuaScriptingState::run_hook(state, game_scene, LuaHook::NPCDeath(npc))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Calls all handlers registered for given hook, script errors are logged and otherwise ignored.
+    pub fn run_hook(state: &mut SharedGameState, game_scene: &mut GameScene, hook: LuaHook) -> GameResult {
+        let Some(lua) = state.lua.runtime.clone() else {
+            return Ok(());
+        };
+
+        let hooks: Table = lua.named_registry_value(HOOKS_KEY)?;
+        let Some(handlers) = hooks.get::<_, Option<Table>>(hook.name())? else {
+            return Ok(());
+        };
+
+        let game = RefCell::new(HookContext { state, game_scene });
+        let result = lua.scope(|scope| {
+            let api = create_api(&lua, scope, &game)?;
+
+            for handler in handlers.sequence_values::<Function>() {
+                let handler = handler?;
+
+                match hook {
+                    LuaHook::StageLoad(stage_id) => handler.call::<_, ()>((api.clone(), stage_id))?,
+                    LuaHook::Tick => handler.call::<_, ()>(api.clone())?,
+                    LuaHook::NPCDeath(npc) => handler.call::<_, ()>((api.clone(), npc.to_table(&lua)?))?,
+                    LuaHook::EventStart(event_num) => handler.call::<_, ()>((api.clone(), event_num))?,
+                }
+            }
+
+            Ok(())
+        });
+
+        if let Err(e) = result {
+            log::error!("Lua {} handler failed: {}", hook.name(), e);
+        }
+
+        Ok(())
+    }
+}
+
+struct HookContext<'a> {
+    state: &'a mut SharedGameState,
+    game_scene: &'a mut GameScene,
+}
+
+impl HookContext<'_> {
+    fn player(&mut self, idx: u8) -> &mut Player {
+        if idx == 2 {
+            &mut self.game_scene.player2
+        } else {
+            &mut self.game_scene.player1
+        }
+    }
+}
+
+fn create_api<'lua, 'scope>(
+    lua: &'lua Lua,
+    scope: &Scope<'lua, 'scope>,
+    game: &'scope RefCell<HookContext<'_>>,
+) -> mlua::Result<Table<'lua>> {
+    let api = lua.create_table()?;
+
+    api.set("stage_id", game.borrow().game_scene.stage_id)?;
+
+    // flags
+    api.set("get_flag", scope.create_function(move |_, id: usize| Ok(game.borrow().state.get_flag(id)))?)?;
+    api.set(
+        "set_flag",
+        scope.create_function(move |_, (id, value): (usize, bool)| {
+            game.borrow_mut().state.set_flag(id, value);
+            Ok(())
+        })?,
+    )?;
+    api.set("get_variable", scope.create_function(move |_, id: usize| Ok(game.borrow().state.get_variable(id)))?)?;
+    api.set(
+        "set_variable",
+        scope.create_function(move |_, (id, value): (usize, i32)| {
+            game.borrow_mut().state.set_variable(id, value);
+            Ok(())
+        })?,
+    )?;
+
+    // data persisted in saves, namespaced by the mod name
+    api.set(
+        "get_mod_data",
+        scope.create_function(move |_, (mod_name, key): (String, String)| {
+            Ok(game.borrow().state.mod_data.get(&mod_name).and_then(|entries| entries.get(&key)).cloned())
+        })?,
+    )?;
+    api.set(
+        "set_mod_data",
+        scope.create_function(move |_, (mod_name, key, value): (String, String, Option<String>)| {
+            let mut game = game.borrow_mut();
+            let entries = game.state.mod_data.entry(mod_name).or_default();
+            match value {
+                Some(value) => entries.insert(key, value),
+                None => entries.remove(&key),
+            };
+            Ok(())
+        })?,
+    )?;
+    api.set(
+        "start_event",
+        scope.create_function(move |_, event_num: u16| {
+            game.borrow_mut().state.textscript_vm.start_script(event_num);
+            Ok(())
+        })?,
+    )?;
+
+    // players
+    api.set(
+        "player",
+        scope.create_function(move |lua, idx: u8| {
+            let mut game = game.borrow_mut();
+            let player = game.player(idx);
+
+            let table = lua.create_table()?;
+            table.set("alive", player.cond.alive())?;
+            table.set("x", player.x)?;
+            table.set("y", player.y)?;
+            table.set("vel_x", player.vel_x)?;
+            table.set("vel_y", player.vel_y)?;
+            table.set("life", player.life)?;
+            table.set("max_life", player.max_life)?;
+
+            Ok(table)
+        })?,
+    )?;
+    api.set(
+        "set_player_pos",
+        scope.create_function(move |_, (idx, x, y): (u8, i32, i32)| {
+            let mut game = game.borrow_mut();
+            let player = game.player(idx);
+            player.x = x;
+            player.y = y;
+            Ok(())
+        })?,
+    )?;
+    api.set(
+        "set_player_life",
+        scope.create_function(move |_, (idx, life): (u8, u16)| {
+            let mut game = game.borrow_mut();
+            let player = game.player(idx);
+            player.life = life.min(player.max_life);
+            Ok(())
+        })?,
+    )?;
+
+    // NPCs
+    api.set(
+        "npcs",
+        scope.create_function(move |lua, ()| {
+            let game = game.borrow();
+            let npcs = lua.create_table()?;
+            for npc in game.game_scene.npc_list.iter_alive() {
+                npcs.raw_set(npcs.raw_len() + 1, NPCInfo::from_npc(npc).to_table(lua)?)?;
+            }
+
+            Ok(npcs)
+        })?,
+    )?;
+    api.set(
+        "spawn_npc",
+        scope.create_function(move |_, (npc_type, x, y, direction): (u16, i32, i32, Option<usize>)| {
+            let game = game.borrow();
+            let mut npc = NPC::create(npc_type, &game.state.npc_table);
+            npc.cond.set_alive(true);
+            npc.x = x;
+            npc.y = y;
+            npc.direction = direction.and_then(Direction::from_int_facing).unwrap_or(Direction::Left);
+
+            Ok(game.game_scene.npc_list.spawn(0x100, npc).is_ok())
+        })?,
+    )?;
+    api.set(
+        "set_npc_pos",
+        scope.create_function(move |_, (id, x, y): (usize, i32, i32)| {
+            if let Some(npc) = game.borrow().game_scene.npc_list.get_npc(id) {
+                npc.x = x;
+                npc.y = y;
+            }
+            Ok(())
+        })?,
+    )?;
+    api.set(
+        "kill_npc",
+        scope.create_function(move |_, id: usize| {
+            let mut game = game.borrow_mut();
+            let HookContext { state, game_scene } = &mut *game;
+            game_scene.npc_list.kill_npc(id, true, false, state);
+            Ok(())
+        })?,
+    )?;
+
+    // inventory, shared by both players like in TSC
+    api.set(
+        "has_item",
+        scope.create_function(move |_, item_id: u16| Ok(game.borrow().game_scene.inventory_player1.has_item(item_id)))?,
+    )?;
+    api.set(
+        "add_item",
+        scope.create_function(move |_, item_id: u16| {
+            let mut game = game.borrow_mut();
+            let game_scene = &mut *game.game_scene;
+            for inventory in [&mut game_scene.inventory_player1, &mut game_scene.inventory_player2] {
+                if !inventory.has_item(item_id) {
+                    inventory.add_item(item_id);
+                }
+            }
+            Ok(())
+        })?,
+    )?;
+    api.set(
+        "remove_item",
+        scope.create_function(move |_, item_id: u16| {
+            let mut game = game.borrow_mut();
+            game.game_scene.inventory_player1.remove_item(item_id);
+            game.game_scene.inventory_player2.remove_item(item_id);
+            Ok(())
+        })?,
+    )?;
+    api.set(
+        "has_weapon",
+        scope.create_function(move |_, weapon_id: u8| {
+            let weapon_type: Option<WeaponType> = FromPrimitive::from_u8(weapon_id);
+            Ok(weapon_type.map_or(false, |wtype| game.borrow().game_scene.inventory_player1.has_weapon(wtype)))
+        })?,
+    )?;
+    api.set(
+        "add_weapon",
+        scope.create_function(move |_, (weapon_id, max_ammo): (u8, u16)| {
+            let mut game = game.borrow_mut();
+            if let Some(wtype) = FromPrimitive::from_u8(weapon_id) {
+                game.game_scene.inventory_player1.add_weapon(wtype, max_ammo);
+                game.game_scene.inventory_player2.add_weapon(wtype, max_ammo);
+            }
+            Ok(())
+        })?,
+    )?;
+    api.set(
+        "remove_weapon",
+        scope.create_function(move |_, weapon_id: u8| {
+            let mut game = game.borrow_mut();
+            if let Some(wtype) = FromPrimitive::from_u8(weapon_id) {
+                game.game_scene.inventory_player1.remove_weapon(wtype);
+                game.game_scene.inventory_player2.remove_weapon(wtype);
+            }
+            Ok(())
+        })?,
+    )?;
+
+    // stage
+    api.set(
+        "tile_at",
+        scope.create_function(move |_, (x, y): (usize, usize)| Ok(game.borrow().game_scene.stage.tile_at(x, y)))?,
+    )?;
+    api.set(
+        "change_tile",
+        scope.create_function(move |_, (x, y, tile_type, layer): (usize, usize, u16, Option<u8>)| {
+            let layer = match layer.unwrap_or(2) {
+                3 => TileLayer::FarForeground,
+                0 => TileLayer::Background,
+                1 => TileLayer::Middleground,
+                _ => TileLayer::Foreground,
+            };
+
+            Ok(game.borrow_mut().game_scene.stage.change_tile_layer(x, y, tile_type, layer))
+        })?,
+    )?;
+
+    Ok(api)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT: &str = r#"
+        doukutsu.on("stage_load", function(game, stage_id)
+            game.set_variable(1, stage_id + 10)
+        end)
+        doukutsu.on("tick", function(game)
+            game.set_variable(2, game.get_variable(2) + 1)
+        end)
+        doukutsu.on("npc_death", function(game, npc)
+            game.set_flag(npc.flag, true)
+        end)
+        doukutsu.on("event_start", function(game, event_num)
+            if game.get_flag(300) then
+                game.set_variable(3, event_num)
+            end
+        end)
+
+        local sandboxed = os == nil and io == nil and dofile == nil and loadfile == nil and require == nil
+        doukutsu.on("stage_load", function(game)
+            game.set_variable(4, sandboxed and 1 or 0)
+        end)
+    "#;
+
+    #[test]
+    fn test_hooks() {
+        let mut ctx = Box::new(Context::new());
+        let mut state = SharedGameState::new_headless(&mut ctx);
+        let mut scene = GameScene::new_empty(&mut state, &mut ctx);
+
+        state.lua.load_scripts(vec![("test.lua".to_owned(), SCRIPT.to_owned())]).unwrap();
+        assert!(state.lua.is_active());
+
+        LuaScriptingState::run_hook(&mut state, &mut scene, LuaHook::StageLoad(5)).unwrap();
+        assert_eq!(state.get_variable(1), 15);
+        assert_eq!(state.get_variable(4), 1);
+
+        LuaScriptingState::run_tick(&mut state, &mut scene).unwrap();
+        assert_eq!(state.get_variable(2), 1);
+
+        LuaScriptingState::run_hook(&mut state, &mut scene, LuaHook::EventStart(200)).unwrap();
+        assert_eq!(state.get_variable(3), 0);
+
+        let mut npc = NPC::empty();
+        npc.flag_num = 300;
+        state.lua.queue_npc_death(&npc);
+        LuaScriptingState::run_tick(&mut state, &mut scene).unwrap();
+        assert!(state.get_flag(300));
+        assert_eq!(state.get_variable(2), 2);
+
+        LuaScriptingState::run_hook(&mut state, &mut scene, LuaHook::EventStart(200)).unwrap();
+        assert_eq!(state.get_variable(3), 200);
+    }
+}
@@ -1 +1,3 @@
+#[cfg(feature = "scripting-lua")]
+pub mod lua;
 pub mod tsc;
@@ -0,0 +1,260 @@
+use std::path::{Path, PathBuf};
+
+use crate::game::scripting::tsc::diagnostics::TSCSeverity;
+use crate::game::scripting::tsc::encryption::{decrypt_tsc, encrypt_tsc};
+use crate::game::scripting::tsc::lint::read_number;
+use crate::game::scripting::tsc::text_script::{TextScript, TextScriptEncoding};
+
+/// Options of the command line text script tools, which run instead of the game if any of them is used.
+#[derive(Clone, Debug, Default)]
+pub struct TSCToolOptions {
+    /// Scripts to check for mistakes.
+    pub lint: Vec<PathBuf>,
+    /// Scripts to convert, pairs of input and output paths.
+    pub convert: Vec<(PathBuf, PathBuf)>,
+    /// Scripts or directories of scripts to compile, decompile and compile again.
+    pub roundtrip: Vec<PathBuf>,
+    /// Encoding of the input scripts, Shift-JIS if not set.
+    pub encoding: Option<TextScriptEncoding>,
+    /// Encoding of converted scripts, same as input if not set.
+    pub output_encoding: Option<TextScriptEncoding>,
+    /// Encrypt converted scripts, like the freeware version expects.
+    pub encrypt: bool,
+    /// Use `\r\n` line endings in converted scripts.
+    pub crlf: bool,
+}
+
+impl TSCToolOptions {
+    /// Parses a tool-specific argument, returns false if the argument is unknown.
+    pub fn parse_arg(&mut self, arg: &str, value: &mut dyn Iterator<Item = String>) -> Result<bool, String> {
+        let mut next_value = || value.next().ok_or_else(|| format!("Missing value for {}.", arg));
+
+        match arg {
+            "--lint-tsc" => self.lint.push(PathBuf::from(next_value()?)),
+            "--convert-tsc" => {
+                let input = PathBuf::from(next_value()?);
+                let output = PathBuf::from(next_value()?);
+                self.convert.push((input, output));
+            }
+            "--roundtrip-tsc" => self.roundtrip.push(PathBuf::from(next_value()?)),
+            "--tsc-encoding" => self.encoding = Some(TextScriptEncoding::from(next_value()?.as_str())),
+            "--tsc-output-encoding" => self.output_encoding = Some(TextScriptEncoding::from(next_value()?.as_str())),
+            "--tsc-encrypt" => self.encrypt = true,
+            "--tsc-crlf" => self.crlf = true,
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    pub fn is_set(&self) -> bool {
+        !self.lint.is_empty() || !self.convert.is_empty() || !self.roundtrip.is_empty()
+    }
+
+    /// Runs the requested tools, returns true if any of them failed.
+    pub fn run(&self) -> bool {
+        let mut failed = false;
+
+        if !self.lint.is_empty() {
+            failed |= lint_files(&self.lint);
+        }
+
+        if !self.convert.is_empty() {
+            failed |= self.convert_files();
+        }
+
+        if !self.roundtrip.is_empty() {
+            failed |= self.roundtrip_files();
+        }
+
+        failed
+    }
+
+    fn convert_files(&self) -> bool {
+        let encoding = self.encoding.unwrap_or(TextScriptEncoding::ShiftJIS);
+        let output_encoding = self.output_encoding.unwrap_or(encoding);
+        let mut failed = false;
+
+        for (input, output) in &self.convert {
+            match self.convert_script(input, output, encoding, output_encoding) {
+                Ok(()) => eprintln!("{} -> {}", input.display(), output.display()),
+                Err(err) => {
+                    eprintln!("{}: error: {}", input.display(), err);
+                    failed = true;
+                }
+            }
+        }
+
+        failed
+    }
+
+    fn convert_script(
+        &self,
+        input: &Path,
+        output: &Path,
+        encoding: TextScriptEncoding,
+        output_encoding: TextScriptEncoding,
+    ) -> Result<(), String> {
+        let data = read_script(input).map_err(|err| err.to_string())?;
+        let script = TextScript::compile_with_diagnostics(&data, false, encoding).map_err(|d| d.to_string())?;
+        let mut data = script.decompile(output_encoding, self.crlf).map_err(|err| err.to_string())?;
+
+        if self.encrypt && !data.is_empty() {
+            encrypt_tsc(&mut data);
+        }
+
+        std::fs::write(output, data).map_err(|err| err.to_string())
+    }
+
+    fn roundtrip_files(&self) -> bool {
+        let encoding = self.encoding.unwrap_or(TextScriptEncoding::ShiftJIS);
+        let mut paths = Vec::new();
+        for path in &self.roundtrip {
+            collect_scripts(path, &mut paths);
+        }
+
+        let (mut identical, mut normalized, mut failed) = (0, 0, 0);
+
+        for path in &paths {
+            match roundtrip_script(path, encoding) {
+                Ok(true) => identical += 1,
+                Ok(false) => normalized += 1,
+                Err(err) => {
+                    eprintln!("{}: error: {}", path.display(), err);
+                    failed += 1;
+                }
+            }
+        }
+
+        eprintln!(
+            "{} script(s): {} byte-identical, {} normalized, {} failed.",
+            paths.len(),
+            identical,
+            normalized,
+            failed
+        );
+
+        failed > 0
+    }
+}
+
+/// Compiles, decompiles and recompiles a script, returns true if the decompiled source is byte-identical
+/// to the original one, or an error if the compiled bytecode differs.
+fn roundtrip_script(path: &Path, encoding: TextScriptEncoding) -> Result<bool, String> {
+    let data = read_script(path).map_err(|err| err.to_string())?;
+    let crlf = data.windows(2).any(|w| w == b"\r\n");
+
+    let script = TextScript::compile_with_diagnostics(&data, false, encoding).map_err(|d| d.to_string())?;
+    let decompiled = script.decompile(encoding, crlf).map_err(|err| err.to_string())?;
+    let recompiled = TextScript::compile_with_diagnostics(&decompiled, false, encoding)
+        .map_err(|d| format!("decompiled script doesn't compile: {}", d))?;
+
+    for id in script.get_event_ids() {
+        if script.event_map.get(&id) != recompiled.event_map.get(&id) {
+            return Err(format!("bytecode of event {} differs after round trip.", id));
+        }
+    }
+
+    if script.event_map.len() != recompiled.event_map.len() {
+        return Err("decompiled script defines different events.".to_owned());
+    }
+
+    Ok(decompiled == data)
+}
+
+fn collect_scripts(path: &Path, out: &mut Vec<PathBuf>) {
+    if !path.is_dir() {
+        out.push(path.to_path_buf());
+        return;
+    }
+
+    let Ok(entries) = std::fs::read_dir(path) else {
+        eprintln!("{}: error: can't read directory.", path.display());
+        return;
+    };
+
+    let mut entries: Vec<PathBuf> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+    entries.sort();
+
+    for entry in entries {
+        if entry.is_dir() {
+            collect_scripts(&entry, out);
+        } else if entry.extension().map_or(false, |ext| ext.eq_ignore_ascii_case("tsc")) {
+            out.push(entry);
+        }
+    }
+}
+
+/// Loads a text script from disk, decrypting it if needed.
+fn read_script(path: &Path) -> std::io::Result<Vec<u8>> {
+    let mut data = std::fs::read(path)?;
+
+    // Decrypted scripts always start with an event definition.
+    if data.iter().find(|c| !c.is_ascii_whitespace()).map_or(false, |&c| c != b'#') {
+        decrypt_tsc(&mut data);
+    }
+
+    Ok(data)
+}
+
+/// Returns the numbers of all events defined in the script.
+fn defined_events(data: &[u8]) -> Vec<u16> {
+    let mut events = Vec::new();
+    let mut line_start = true;
+
+    for (pos, &chr) in data.iter().enumerate() {
+        if chr == b'#' && line_start {
+            if let Some(event_num) = read_number(data, pos + 1) {
+                events.push(event_num as u16);
+            }
+        }
+
+        line_start = chr == b'\n';
+    }
+
+    events
+}
+
+/// Lints specified script files and prints the diagnostics to stderr.
+///
+/// Events of `Head.tsc`, if it's one of the files, are considered available to all other scripts.
+/// Returns true if any errors were found.
+pub fn lint_files(paths: &[PathBuf]) -> bool {
+    let mut scripts = Vec::new();
+    let mut head_events = Vec::new();
+    let mut has_errors = false;
+
+    for path in paths {
+        match read_script(path) {
+            Ok(data) => {
+                let is_head = path.file_name().map_or(false, |name| name.eq_ignore_ascii_case("Head.tsc"));
+                if is_head {
+                    head_events = defined_events(&data);
+                }
+
+                scripts.push((path, data));
+            }
+            Err(err) => {
+                eprintln!("{}: error: {}", path.display(), err);
+                has_errors = true;
+            }
+        }
+    }
+
+    let (mut errors, mut warnings) = (0, 0);
+
+    for (path, data) in scripts {
+        for diagnostic in TextScript::lint(&data, &head_events) {
+            match diagnostic.severity {
+                TSCSeverity::Error => errors += 1,
+                TSCSeverity::Warning => warnings += 1,
+            }
+
+            eprintln!("{}", diagnostic.with_file(&path.display().to_string()));
+        }
+    }
+
+    eprintln!("{} error(s), {} warning(s) in {} file(s).", errors, warnings, paths.len());
+
+    has_errors || errors > 0
+}
@@ -8,6 +8,7 @@ use crate::framework::error::GameError::ParseError;
 use crate::framework::error::GameResult;
 use crate::game::scripting::tsc::bytecode_utils::{put_string, put_varint};
 use crate::game::scripting::tsc::credit_script::CreditScript;
+use crate::game::scripting::tsc::diagnostics::TSCDiagnostic;
 use crate::game::scripting::tsc::opcodes::{CreditOpCode, TSCOpCode};
 use crate::game::scripting::tsc::parse_utils::{expect_char, read_number, skip_until};
 use crate::game::scripting::tsc::text_script::{TextScript, TextScriptEncoding};
@@ -15,24 +16,38 @@ use crate::game::scripting::tsc::text_script::{TextScript, TextScriptEncoding};
 impl TextScript {
     /// Compiles a decrypted text script data into internal bytecode.
     pub fn compile(data: &[u8], strict: bool, encoding: TextScriptEncoding) -> GameResult<TextScript> {
+        Ok(TextScript::compile_with_diagnostics(data, strict, encoding)?)
+    }
+
+    /// Same as [TextScript::compile], but returns the error along with its position in the source.
+    pub fn compile_with_diagnostics(
+        data: &[u8],
+        strict: bool,
+        encoding: TextScriptEncoding,
+    ) -> Result<TextScript, TSCDiagnostic> {
         let mut event_map = HashMap::new();
         let mut iter = data.iter().copied().peekable();
         let mut last_event = 0;
 
         while let Some(&chr) = iter.peek() {
+            let offset = data.len() - iter.len();
+
             match chr {
                 b'#' => {
                     iter.next();
-                    let event_num = read_number(&mut iter)? as u16;
+                    let event_num =
+                        read_number(&mut iter).map_err(|e| TSCDiagnostic::from_error(e, offset).locate(data))? as u16;
                     if iter.peek().is_some() {
-                        skip_until(b'\n', &mut iter)?;
+                        skip_until(b'\n', &mut iter)
+                            .map_err(|e| TSCDiagnostic::from_error(e, offset).with_event(event_num).locate(data))?;
                         iter.next();
                     }
                     last_event = event_num;
 
                     if event_map.contains_key(&event_num) {
                         if strict {
-                            return Err(ParseError(format!("Event {} has been defined twice.", event_num)));
+                            let message = format!("Event {} has been defined twice.", event_num);
+                            return Err(TSCDiagnostic::error(offset, message).with_event(event_num).locate(data));
                         }
 
                         match skip_until(b'#', &mut iter).ok() {
@@ -45,7 +60,8 @@ impl TextScript {
                         }
                     }
 
-                    let bytecode = TextScript::compile_event(&mut iter, strict, encoding)?;
+                    let bytecode = TextScript::compile_event(&mut iter, data.len(), strict, encoding)
+                        .map_err(|e| e.with_event(event_num).locate(data))?;
                     log::info!("Successfully compiled event #{} ({} bytes generated).", event_num, bytecode.len());
                     event_map.insert(event_num, bytecode);
                 }
@@ -59,7 +75,8 @@ impl TextScript {
                         continue;
                     }
 
-                    return Err(ParseError(format!("Unexpected token in event {}: {}", last_event, n as char)));
+                    let message = format!("Unexpected token in event {}: {}", last_event, n as char);
+                    return Err(TSCDiagnostic::error(offset, message).with_event(last_event).locate(data));
                 }
             }
         }
@@ -67,11 +84,12 @@ impl TextScript {
         Ok(TextScript { event_map })
     }
 
-    fn compile_event<I: Iterator<Item=u8>>(
+    fn compile_event<I: ExactSizeIterator<Item=u8>>(
         iter: &mut Peekable<I>,
+        data_len: usize,
         strict: bool,
         encoding: TextScriptEncoding,
-    ) -> GameResult<Vec<u8>> {
+    ) -> Result<Vec<u8>, TSCDiagnostic> {
         let mut bytecode = Vec::new();
         let mut char_buf = Vec::with_capacity(16);
         let mut allow_next_event = true;
@@ -96,15 +114,17 @@ impl TextScript {
                         put_string(&mut char_buf, &mut bytecode, encoding);
                     }
 
+                    let offset = data_len - iter.len();
+
                     iter.next();
-                    let n = iter
-                        .next_tuple::<(u8, u8, u8)>()
-                        .map(|t| [t.0, t.1, t.2])
-                        .ok_or_else(|| ParseError("Script unexpectedly ended.".to_owned()))?;
+                    let n = iter.next_tuple::<(u8, u8, u8)>().map(|t| [t.0, t.1, t.2]).ok_or_else(|| {
+                        TSCDiagnostic::error(offset, "Script unexpectedly ended.".to_owned())
+                    })?;
 
                     let code = String::from_utf8_lossy(&n);
 
-                    TextScript::compile_code(&code, strict, iter, &mut bytecode)?;
+                    TextScript::compile_code(&code, strict, iter, &mut bytecode)
+                        .map_err(|e| TSCDiagnostic::from_error(e, offset).with_opcode(&code))?;
                 }
                 b'\r' => {
                     iter.next();
@@ -221,7 +241,12 @@ impl TextScript {
             | TSCOpCode::ACH
             | TSCOpCode::S2MV
             | TSCOpCode::S2PJ
-            | TSCOpCode::PSH => {
+            | TSCOpCode::PSH
+            | TSCOpCode::VJE
+            | TSCOpCode::VJN
+            | TSCOpCode::VJG
+            | TSCOpCode::VJL
+            | TSCOpCode::DRK => {
                 let operand = read_number(iter)?;
                 put_varint(instr as i32, out);
                 put_varint(operand as i32, out);
@@ -241,7 +266,15 @@ impl TextScript {
             | TSCOpCode::SMP
             | TSCOpCode::PSp
             | TSCOpCode::IpN
-            | TSCOpCode::FFm => {
+            | TSCOpCode::FFm
+            | TSCOpCode::VAe
+            | TSCOpCode::VAp
+            | TSCOpCode::VAm
+            | TSCOpCode::VVe
+            | TSCOpCode::VVp
+            | TSCOpCode::VVm
+            | TSCOpCode::VCM
+            | TSCOpCode::VCV => {
                 let operand_a = read_number(iter)?;
                 if strict {
                     expect_char(b':', iter)?;
@@ -276,7 +309,7 @@ impl TextScript {
                 put_varint(operand_c as i32, out);
             }
             // Four operand codes
-            TSCOpCode::TRA | TSCOpCode::MNP | TSCOpCode::SNP | TSCOpCode::CML => {
+            TSCOpCode::TRA | TSCOpCode::MNP | TSCOpCode::SNP | TSCOpCode::CML | TSCOpCode::LNP => {
                 let operand_a = read_number(iter)?;
                 if strict {
                     expect_char(b':', iter)?;
@@ -7,7 +7,7 @@ use crate::framework::error::GameError::InvalidValue;
 use crate::framework::error::GameResult;
 use crate::game::scripting::tsc::bytecode_utils::read_cur_varint;
 use crate::game::scripting::tsc::opcodes::TSCOpCode;
-use crate::game::scripting::tsc::text_script::TextScript;
+use crate::game::scripting::tsc::text_script::{TextScript, TextScriptEncoding};
 
 impl TextScript {
     pub fn decompile_event(&self, id: u16) -> GameResult<String> {
@@ -89,7 +89,6 @@ impl TextScript {
                         | TSCOpCode::ITp
                         | TSCOpCode::ITm
                         | TSCOpCode::AMm
-                        | TSCOpCode::UNJ
                         | TSCOpCode::MPJ
                         | TSCOpCode::YNJ
                         | TSCOpCode::EVE
@@ -102,7 +101,12 @@ impl TextScript {
                         | TSCOpCode::ACH
                         | TSCOpCode::S2MV
                         | TSCOpCode::S2PJ
-                        | TSCOpCode::PSH => {
+                        | TSCOpCode::PSH
+                        | TSCOpCode::VJE
+                        | TSCOpCode::VJN
+                        | TSCOpCode::VJG
+                        | TSCOpCode::VJL
+                        | TSCOpCode::DRK => {
                             let par_a = read_cur_varint(&mut cursor)?;
 
                             writeln!(&mut result, "{:?}({})", op, par_a).unwrap();
@@ -118,10 +122,19 @@ impl TextScript {
                         | TSCOpCode::ITJ
                         | TSCOpCode::SKJ
                         | TSCOpCode::AMJ
+                        | TSCOpCode::UNJ
                         | TSCOpCode::SMP
                         | TSCOpCode::PSp
                         | TSCOpCode::IpN
-                        | TSCOpCode::FFm => {
+                        | TSCOpCode::FFm
+                        | TSCOpCode::VAe
+                        | TSCOpCode::VAp
+                        | TSCOpCode::VAm
+                        | TSCOpCode::VVe
+                        | TSCOpCode::VVp
+                        | TSCOpCode::VVm
+                        | TSCOpCode::VCM
+                        | TSCOpCode::VCV => {
                             let par_a = read_cur_varint(&mut cursor)?;
                             let par_b = read_cur_varint(&mut cursor)?;
 
@@ -136,7 +149,7 @@ impl TextScript {
                             writeln!(&mut result, "{:?}({}, {}, {})", op, par_a, par_b, par_c).unwrap();
                         }
                         // Four operand codes
-                        TSCOpCode::TRA | TSCOpCode::MNP | TSCOpCode::SNP | TSCOpCode::CML => {
+                        TSCOpCode::TRA | TSCOpCode::MNP | TSCOpCode::SNP | TSCOpCode::CML | TSCOpCode::LNP => {
                             let par_a = read_cur_varint(&mut cursor)?;
                             let par_b = read_cur_varint(&mut cursor)?;
                             let par_c = read_cur_varint(&mut cursor)?;
@@ -184,4 +197,126 @@ impl TextScript {
             Err(InvalidValue("Unknown script.".to_string()))
         }
     }
+
+    /// Turns the compiled script back into TSC source in specified encoding.
+    ///
+    /// Events are written in ascending order, and since comments, `\r` characters and invalid separators
+    /// are discarded by the compiler, the output is normalized: arguments are separated with `:` and lines end
+    /// with `\n` (or `\r\n` if `crlf` is set). Compiling the result produces the same bytecode.
+    pub fn decompile(&self, encoding: TextScriptEncoding, crlf: bool) -> GameResult<Vec<u8>> {
+        if let TextScriptEncoding::UTF16BE | TextScriptEncoding::UTF16LE = encoding {
+            return Err(InvalidValue(format!("Text scripts can't be stored as {:?}.", encoding)));
+        }
+
+        let line_ending: &[u8] = if crlf { b"\r\n" } else { b"\n" };
+        let mut result = Vec::new();
+
+        for id in self.get_event_ids() {
+            if !result.is_empty() && !result.ends_with(b"\n") {
+                result.extend_from_slice(line_ending);
+            }
+
+            result.extend_from_slice(format!("#{:04}", id).as_bytes());
+            result.extend_from_slice(line_ending);
+
+            let bytecode = &self.event_map[&id];
+            let mut cursor: Cursor<&[u8]> = Cursor::new(bytecode);
+
+            while (cursor.position() as usize) < bytecode.len() {
+                let op_num = read_cur_varint(&mut cursor)?;
+                let op: TSCOpCode = FromPrimitive::from_i32(op_num)
+                    .ok_or_else(|| InvalidValue(format!("Unknown opcode {} in event {}.", op_num, id)))?;
+
+                match op {
+                    TSCOpCode::_NOP | TSCOpCode::_END => {}
+                    TSCOpCode::_UNI => {
+                        return Err(InvalidValue(format!("Unimplemented opcode in event {}.", id)));
+                    }
+                    TSCOpCode::_STR => {
+                        let len = read_cur_varint(&mut cursor)?;
+                        let mut text = String::with_capacity(len as usize);
+
+                        for _ in 0..len {
+                            let chr = read_cur_varint(&mut cursor)? as u32;
+                            text.push(std::char::from_u32(chr).unwrap_or('\u{fffd}'));
+                        }
+
+                        if crlf {
+                            text = text.replace('\n', "\r\n");
+                        }
+
+                        let (encoded, _, had_errors) = <&encoding_rs::Encoding>::from(encoding).encode(&text);
+                        if had_errors {
+                            return Err(InvalidValue(format!(
+                                "Text of event {} can't be represented in {:?}: {}",
+                                id, encoding, text
+                            )));
+                        }
+
+                        result.extend_from_slice(&encoded);
+                    }
+                    _ => {
+                        let code: &'static str = op.into();
+                        result.push(b'<');
+                        result.extend_from_slice(code.as_bytes());
+
+                        for i in 0..op.operand_count() {
+                            if i > 0 {
+                                result.push(b':');
+                            }
+
+                            let operand = read_cur_varint(&mut cursor)?;
+                            if put_number(operand, &mut result).is_none() {
+                                let message =
+                                    format!("Argument {} of <{} in event {} is out of range.", operand, code, id);
+                                return Err(InvalidValue(message));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if !result.is_empty() && !result.ends_with(b"\n") {
+            result.extend_from_slice(line_ending);
+        }
+
+        Ok(result)
+    }
+}
+
+/// Writes a 4 digit TSC formatted number, reverses what `read_number` does.
+/// Values above 9999 are written using characters past '9', like some mods do.
+fn put_number(value: i32, out: &mut Vec<u8>) -> Option<()> {
+    let mut remaining = value;
+    if remaining < 0 {
+        return None;
+    }
+
+    for place in [1000, 100, 10, 1] {
+        let digit = (remaining / place).min(0xff);
+        remaining -= digit * place;
+        out.push(b'0'.wrapping_add(digit as u8));
+    }
+
+    (remaining == 0).then_some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decompile_roundtrip() {
+        let source = concat!(
+            "#0090\r\n<KEY<MSGHello, <FL+0100world!<NOD<END\r\n",
+            "#0100\r\n<WAI:000<TRA0012:0090:0005:0010<END\r\n",
+        );
+        let script = TextScript::compile(source.as_bytes(), false, TextScriptEncoding::UTF8).unwrap();
+        let decompiled = script.decompile(TextScriptEncoding::UTF8, true).unwrap();
+        assert_eq!(String::from_utf8_lossy(&decompiled), source);
+
+        let recompiled = TextScript::compile(&decompiled, false, TextScriptEncoding::UTF8).unwrap();
+        assert_eq!(script.event_map, recompiled.event_map);
+    }
 }
@@ -0,0 +1,107 @@
+use std::fmt;
+
+use crate::framework::error::GameError;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TSCSeverity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for TSCSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TSCSeverity::Warning => f.write_str("warning"),
+            TSCSeverity::Error => f.write_str("error"),
+        }
+    }
+}
+
+/// A problem found while compiling or linting a text script, along with its position in the source.
+#[derive(Debug, Clone)]
+pub struct TSCDiagnostic {
+    pub severity: TSCSeverity,
+    pub file: Option<String>,
+    pub event: Option<u16>,
+    /// Line number, starting from 1.
+    pub line: usize,
+    /// Column number in bytes, starting from 1.
+    pub column: usize,
+    /// Opcode the diagnostic refers to, without the leading `<`.
+    pub opcode: Option<String>,
+    pub message: String,
+    /// Byte offset in the source, used to compute the line and column.
+    pub offset: usize,
+}
+
+impl TSCDiagnostic {
+    pub fn new(severity: TSCSeverity, offset: usize, message: String) -> TSCDiagnostic {
+        TSCDiagnostic { severity, file: None, event: None, line: 0, column: 0, opcode: None, message, offset }
+    }
+
+    pub fn error(offset: usize, message: String) -> TSCDiagnostic {
+        TSCDiagnostic::new(TSCSeverity::Error, offset, message)
+    }
+
+    pub fn warning(offset: usize, message: String) -> TSCDiagnostic {
+        TSCDiagnostic::new(TSCSeverity::Warning, offset, message)
+    }
+
+    /// Creates an error diagnostic out of an error returned by the parsing helpers.
+    pub fn from_error(err: GameError, offset: usize) -> TSCDiagnostic {
+        let message = match err {
+            GameError::ParseError(message) => message,
+            err => err.to_string(),
+        };
+
+        TSCDiagnostic::error(offset, message)
+    }
+
+    pub fn with_event(mut self, event: u16) -> TSCDiagnostic {
+        self.event = Some(event);
+        self
+    }
+
+    pub fn with_opcode(mut self, opcode: &str) -> TSCDiagnostic {
+        self.opcode = Some(opcode.to_owned());
+        self
+    }
+
+    pub fn with_file(mut self, file: &str) -> TSCDiagnostic {
+        self.file = Some(file.to_owned());
+        self
+    }
+
+    /// Computes the line and column from the byte offset in specified source.
+    pub fn locate(mut self, data: &[u8]) -> TSCDiagnostic {
+        let offset = self.offset.min(data.len());
+        let line_start = data[..offset].iter().rposition(|&c| c == b'\n').map_or(0, |pos| pos + 1);
+
+        self.line = data[..offset].iter().filter(|&&c| c == b'\n').count() + 1;
+        self.column = offset - line_start + 1;
+        self
+    }
+}
+
+impl fmt::Display for TSCDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file)?;
+        }
+
+        write!(f, "{}:{}: {}: {}", self.line, self.column, self.severity, self.message)?;
+
+        match (self.event, &self.opcode) {
+            (Some(event), Some(opcode)) => write!(f, " (event #{:04}, <{})", event, opcode),
+            (Some(event), None) => write!(f, " (event #{:04})", event),
+            (None, Some(opcode)) => write!(f, " (<{})", opcode),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+impl From<TSCDiagnostic> for GameError {
+    fn from(diagnostic: TSCDiagnostic) -> GameError {
+        GameError::ParseError(diagnostic.to_string())
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use crate::game::scripting::tsc::diagnostics::TSCDiagnostic;
+use crate::game::scripting::tsc::opcodes::TSCOpCode;
+use crate::game::scripting::tsc::text_script::TextScript;
+use crate::game::shared_game_state::{GAME_FLAG_COUNT, VARIABLE_COUNT};
+
+/// Amount of flags available to <FL+, <FL- and <FLJ in vanilla.
+pub const FLAG_COUNT: i32 = 8000;
+
+struct Jump {
+    offset: usize,
+    event: u16,
+    opcode: String,
+    target: u16,
+}
+
+/// Reads a TSC number from the source, returns None if it ends prematurely.
+/// Like the compiler, this doesn't reject non-digit characters.
+pub(crate) fn read_number(data: &[u8], pos: usize) -> Option<i32> {
+    let digits = data.get(pos..pos + 4)?;
+    Some(digits.iter().fold(0, |acc, &v| acc * 10 + v.wrapping_sub(b'0') as i32))
+}
+
+impl TextScript {
+    /// Checks a decrypted text script for mistakes which the compiler silently accepts or only reports at runtime.
+    ///
+    /// `external_events` are the events defined outside of the script (eg. in Head.tsc) that can be jumped to.
+    pub fn lint(data: &[u8], external_events: &[u16]) -> Vec<TSCDiagnostic> {
+        let mut diagnostics = Vec::new();
+        let mut events = HashSet::new();
+        let mut jumps = Vec::new();
+
+        let mut pos = 0;
+        let mut event = None;
+        let mut line_start = true;
+        let mut terminated_by = None;
+
+        while pos < data.len() {
+            let chr = data[pos];
+
+            if chr == b'#' && line_start {
+                let Some(event_num) = read_number(data, pos + 1) else {
+                    diagnostics.push(TSCDiagnostic::error(pos, "Script unexpectedly ended.".to_owned()));
+                    break;
+                };
+
+                let event_num = event_num as u16;
+                if !events.insert(event_num) {
+                    let message = format!("Event {} has been defined twice, only the first one is used.", event_num);
+                    diagnostics.push(TSCDiagnostic::warning(pos, message).with_event(event_num));
+                }
+
+                event = Some(event_num);
+                terminated_by = None;
+                pos = data[pos..].iter().position(|&c| c == b'\n').map_or(data.len(), |p| pos + p + 1);
+                continue;
+            }
+
+            line_start = chr == b'\n';
+
+            let Some(event_num) = event else {
+                pos += 1;
+                continue;
+            };
+
+            if chr.is_ascii_whitespace() {
+                pos += 1;
+                continue;
+            }
+
+            if let Some(opcode) = terminated_by.take() {
+                let message = format!("Unreachable code after <{}.", opcode);
+                diagnostics.push(TSCDiagnostic::warning(pos, message).with_event(event_num));
+            }
+
+            if chr != b'<' {
+                pos += 1;
+                continue;
+            }
+
+            let Some(code) = data.get(pos + 1..pos + 4) else {
+                let diagnostic = TSCDiagnostic::error(pos, "Script unexpectedly ended.".to_owned());
+                diagnostics.push(diagnostic.with_event(event_num));
+                break;
+            };
+
+            let code = String::from_utf8_lossy(code).into_owned();
+            let Ok(op) = TSCOpCode::from_str(&code) else {
+                let message = format!("Unknown opcode: {}", code);
+                diagnostics.push(TSCDiagnostic::error(pos, message).with_event(event_num).with_opcode(&code));
+                pos += 4;
+                continue;
+            };
+
+            let op_offset = pos;
+            let operand_count = op.operand_count();
+            let mut operands = Vec::with_capacity(operand_count);
+            pos += 4;
+
+            for idx in 0..operand_count {
+                if idx > 0 {
+                    if data.get(pos) != Some(&b':') {
+                        break;
+                    }
+
+                    pos += 1;
+                }
+
+                let Some(operand) = read_number(data, pos) else {
+                    break;
+                };
+
+                if !data[pos..pos + 4].iter().all(u8::is_ascii_digit) {
+                    let message = format!("Argument {} of <{} contains non-digit characters.", idx + 1, code);
+                    diagnostics.push(TSCDiagnostic::warning(pos, message).with_event(event_num).with_opcode(&code));
+                }
+
+                operands.push(operand);
+                pos += 4;
+            }
+
+            let has_extra_operand = operand_count > 0
+                && data.get(pos) == Some(&b':')
+                && data.get(pos + 1).map_or(false, |c| c.is_ascii_digit());
+
+            if operands.len() != operand_count || has_extra_operand {
+                let message = format!(
+                    "<{} expects {} argument{}, found {}.",
+                    code,
+                    operand_count,
+                    if operand_count == 1 { "" } else { "s" },
+                    if has_extra_operand { "more".to_owned() } else { operands.len().to_string() }
+                );
+                diagnostics.push(TSCDiagnostic::error(op_offset, message).with_event(event_num).with_opcode(&code));
+                continue;
+            }
+
+            if let Some(idx) = op.jump_operand() {
+                let target = operands[idx] as u16;
+                jumps.push(Jump { offset: op_offset, event: event_num, opcode: code.clone(), target });
+            }
+
+            let flags: &[i32] = match op {
+                TSCOpCode::FLp | TSCOpCode::FLm | TSCOpCode::FLJ => &operands[..1],
+                TSCOpCode::FFm => &operands[..2],
+                _ => &[],
+            };
+
+            for &flag in flags {
+                let message = if !(0..GAME_FLAG_COUNT as i32).contains(&flag) {
+                    format!("Flag {} is outside of the {} flag range.", flag, GAME_FLAG_COUNT)
+                } else if flag >= FLAG_COUNT {
+                    let note = "only this engine supports it";
+                    format!("Flag {} is outside of the vanilla {} flag range, {}.", flag, FLAG_COUNT, note)
+                } else {
+                    continue;
+                };
+
+                let diagnostic = TSCDiagnostic::warning(op_offset, message).with_event(event_num);
+                diagnostics.push(diagnostic.with_opcode(&code));
+            }
+
+            let variables: &[i32] = match op {
+                TSCOpCode::VAe | TSCOpCode::VAp | TSCOpCode::VAm | TSCOpCode::VCM => &operands[..1],
+                TSCOpCode::VVe | TSCOpCode::VVp | TSCOpCode::VVm | TSCOpCode::VCV => &operands[..2],
+                _ => &[],
+            };
+
+            for &variable in variables {
+                if variable as usize >= VARIABLE_COUNT {
+                    let message = format!("Variable {} is outside of the {} variable range.", variable, VARIABLE_COUNT);
+                    let diagnostic = TSCDiagnostic::warning(op_offset, message).with_event(event_num);
+                    diagnostics.push(diagnostic.with_opcode(&code));
+                }
+            }
+
+            if op.is_terminator() {
+                terminated_by = Some(code);
+            }
+        }
+
+        for jump in jumps {
+            if !events.contains(&jump.target) && !external_events.contains(&jump.target) {
+                let message = format!("Jump to event {} which doesn't exist.", jump.target);
+                let diagnostic = TSCDiagnostic::warning(jump.offset, message).with_event(jump.event);
+                diagnostics.push(diagnostic.with_opcode(&jump.opcode));
+            }
+        }
+
+        diagnostics.sort_by_key(|d| d.offset);
+        diagnostics.into_iter().map(|d| d.locate(data)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::scripting::tsc::diagnostics::TSCSeverity;
+
+    #[test]
+    fn test_lint() {
+        let script = b"#0090\n<MSGHello<NOD<END\n\
+            #0100\n<FLJ8001:0200<EVE0090<END\nunreachable\n\
+            #0101\n<FOO<FLJ0001<WAI0010:0001\n";
+        let diagnostics = TextScript::lint(script, &[]);
+        let summary: Vec<_> = diagnostics.iter().map(|d| (d.severity, d.line, d.column, d.event)).collect();
+
+        assert_eq!(
+            summary,
+            vec![
+                (TSCSeverity::Warning, 4, 1, Some(100)),
+                (TSCSeverity::Warning, 4, 1, Some(100)),
+                (TSCSeverity::Warning, 5, 1, Some(100)),
+                (TSCSeverity::Error, 7, 1, Some(101)),
+                (TSCSeverity::Error, 7, 5, Some(101)),
+                (TSCSeverity::Error, 7, 13, Some(101)),
+            ]
+        );
+        assert!(TextScript::lint(script, &[200]).iter().all(|d| !d.message.starts_with("Jump")));
+    }
+}
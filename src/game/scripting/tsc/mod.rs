@@ -1,8 +1,11 @@
 mod bytecode_utils;
+pub mod cli;
 mod compiler;
 pub mod credit_script;
 mod decompiler;
+pub mod diagnostics;
 mod encryption;
+pub mod lint;
 mod opcodes;
 mod parse_utils;
 pub mod text_script;
@@ -1,7 +1,7 @@
 use num_derive::FromPrimitive;
 
 /// Engine's text script VM operation codes.
-#[derive(EnumString, Debug, FromPrimitive, PartialEq, Copy, Clone)]
+#[derive(EnumString, IntoStaticStr, Debug, FromPrimitive, PartialEq, Copy, Clone)]
 pub enum TSCOpCode {
     // ---- Internal opcodes (used by bytecode, no TSC representation)
     /// internal: no operation
@@ -89,7 +89,7 @@ pub enum TSCOpCode {
     MS3,
     /// <MSG, Displays text on bottom of the screen with background.
     MSG,
-    /// <NUMxxxx, Displays a value from AM+, buggy in vanilla.
+    /// <NUMxxxx, Displays a value from AM+, buggy in vanilla. Values from 1000 display variable xxxx-1000
     NUM,
 
     /// <ANPxxxx:yyyy:zzzz, Changes the animation state of NPC tagged with
@@ -270,10 +270,163 @@ pub enum TSCOpCode {
     CML,
     /// <SMLwwww:xxxx:yyyy, Subtracts 1 from tile type at (xxxx,yyyy) on layer wwww [0/back, 1/mid, 2/fore, 3/far fore]
     SML,
+    /// <VA=xxxx:yyyy, Sets variable xxxx to yyyy
+    #[strum(serialize = "VA=")]
+    VAe,
+    /// <VA+xxxx:yyyy, Adds yyyy to variable xxxx
+    #[strum(serialize = "VA+")]
+    VAp,
+    /// <VA-xxxx:yyyy, Subtracts yyyy from variable xxxx
+    #[strum(serialize = "VA-")]
+    VAm,
+    /// <VV=xxxx:yyyy, Sets variable xxxx to the value of variable yyyy
+    #[strum(serialize = "VV=")]
+    VVe,
+    /// <VV+xxxx:yyyy, Adds the value of variable yyyy to variable xxxx
+    #[strum(serialize = "VV+")]
+    VVp,
+    /// <VV-xxxx:yyyy, Subtracts the value of variable yyyy from variable xxxx
+    #[strum(serialize = "VV-")]
+    VVm,
+    /// <VCMxxxx:yyyy, Compares variable xxxx with yyyy, the result is used by <VJE, <VJN, <VJG and <VJL
+    VCM,
+    /// <VCVxxxx:yyyy, Compares variable xxxx with variable yyyy
+    VCV,
+    /// <VJExxxx, Jumps to event xxxx if the last compared values were equal
+    VJE,
+    /// <VJNxxxx, Jumps to event xxxx if the last compared values were not equal
+    VJN,
+    /// <VJGxxxx, Jumps to event xxxx if the last compared variable was greater than the other value
+    VJG,
+    /// <VJLxxxx, Jumps to event xxxx if the last compared variable was less than the other value
+    VJL,
+    /// <DRKxxxx, Sets the ambient darkness of the stage to xxxx percent, 0 turns the lighting off
+    DRK,
+    /// <LNPwwww:xxxx:yyyy:zzzz, Attaches a light to NPCs with event wwww, colored xxxx (0RGB, one digit from 0 to 9
+    /// per channel), with a radius of yyyy pixels and zzzz percent of flicker. Radius of 0 removes the light.
+    LNP,
 
 
 }
 
+impl TSCOpCode {
+    /// Returns the number of arguments this opcode takes in the script source.
+    pub fn operand_count(self) -> usize {
+        match self {
+            TSCOpCode::BOA
+            | TSCOpCode::BSL
+            | TSCOpCode::FOM
+            | TSCOpCode::QUA
+            | TSCOpCode::UNI
+            | TSCOpCode::MYB
+            | TSCOpCode::MYD
+            | TSCOpCode::FAI
+            | TSCOpCode::FAO
+            | TSCOpCode::WAI
+            | TSCOpCode::FAC
+            | TSCOpCode::GIT
+            | TSCOpCode::NUM
+            | TSCOpCode::DNA
+            | TSCOpCode::DNP
+            | TSCOpCode::FLm
+            | TSCOpCode::FLp
+            | TSCOpCode::MPp
+            | TSCOpCode::SKm
+            | TSCOpCode::SKp
+            | TSCOpCode::EQp
+            | TSCOpCode::EQm
+            | TSCOpCode::MLp
+            | TSCOpCode::ITp
+            | TSCOpCode::ITm
+            | TSCOpCode::AMm
+            | TSCOpCode::MPJ
+            | TSCOpCode::YNJ
+            | TSCOpCode::EVE
+            | TSCOpCode::XX1
+            | TSCOpCode::SIL
+            | TSCOpCode::LIp
+            | TSCOpCode::SOU
+            | TSCOpCode::CMU
+            | TSCOpCode::SSS
+            | TSCOpCode::ACH
+            | TSCOpCode::S2MV
+            | TSCOpCode::S2PJ
+            | TSCOpCode::PSH
+            | TSCOpCode::VJE
+            | TSCOpCode::VJN
+            | TSCOpCode::VJG
+            | TSCOpCode::VJL
+            | TSCOpCode::DRK => 1,
+            TSCOpCode::FON
+            | TSCOpCode::FOB
+            | TSCOpCode::MOV
+            | TSCOpCode::AMp
+            | TSCOpCode::NCJ
+            | TSCOpCode::ECJ
+            | TSCOpCode::FLJ
+            | TSCOpCode::ITJ
+            | TSCOpCode::SKJ
+            | TSCOpCode::AMJ
+            | TSCOpCode::UNJ
+            | TSCOpCode::SMP
+            | TSCOpCode::PSp
+            | TSCOpCode::IpN
+            | TSCOpCode::FFm
+            | TSCOpCode::VAe
+            | TSCOpCode::VAp
+            | TSCOpCode::VAm
+            | TSCOpCode::VVe
+            | TSCOpCode::VVp
+            | TSCOpCode::VVm
+            | TSCOpCode::VCM
+            | TSCOpCode::VCV => 2,
+            TSCOpCode::ANP
+            | TSCOpCode::CNP
+            | TSCOpCode::INP
+            | TSCOpCode::TAM
+            | TSCOpCode::CMP
+            | TSCOpCode::INJ
+            | TSCOpCode::SML => 3,
+            TSCOpCode::TRA | TSCOpCode::MNP | TSCOpCode::SNP | TSCOpCode::CML | TSCOpCode::LNP => 4,
+            _ => 0,
+        }
+    }
+
+    /// Returns the index of the argument holding an event number this opcode jumps to, if there's one.
+    ///
+    /// <TRA is not included, as it targets an event of another stage.
+    pub fn jump_operand(self) -> Option<usize> {
+        match self {
+            TSCOpCode::EVE
+            | TSCOpCode::MPJ
+            | TSCOpCode::YNJ
+            | TSCOpCode::PSH
+            | TSCOpCode::S2PJ
+            | TSCOpCode::VJE
+            | TSCOpCode::VJN
+            | TSCOpCode::VJG
+            | TSCOpCode::VJL => Some(0),
+            TSCOpCode::FLJ
+            | TSCOpCode::ITJ
+            | TSCOpCode::SKJ
+            | TSCOpCode::AMJ
+            | TSCOpCode::UNJ
+            | TSCOpCode::NCJ
+            | TSCOpCode::ECJ => Some(1),
+            TSCOpCode::INJ => Some(2),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the execution never continues past this opcode within the same event.
+    pub fn is_terminator(self) -> bool {
+        matches!(
+            self,
+            TSCOpCode::END | TSCOpCode::EVE | TSCOpCode::TRA | TSCOpCode::LDP | TSCOpCode::INI | TSCOpCode::ESC
+        )
+    }
+}
+
 #[derive(FromPrimitive, PartialEq, Copy, Clone)]
 pub enum CreditOpCode {
     /// Internal, no operation
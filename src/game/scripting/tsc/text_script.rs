@@ -18,12 +18,15 @@ use crate::entity::GameEntity;
 use crate::framework::context::Context;
 use crate::framework::error::GameResult;
 use crate::game::frame::UpdateTarget;
+use crate::game::lighting::Light;
 use crate::game::npc::NPC;
 use crate::game::player::{ControlMode, TargetPlayer};
+#[cfg(feature = "scripting-lua")]
+use crate::game::scripting::lua::{LuaHook, LuaScriptingState};
 use crate::game::scripting::tsc::bytecode_utils::read_cur_varint;
 use crate::game::scripting::tsc::encryption::decrypt_tsc;
 use crate::game::scripting::tsc::opcodes::TSCOpCode;
-use crate::game::shared_game_state::ReplayState;
+use crate::game::shared_game_state::{ReplayKind, ReplayState};
 use crate::game::shared_game_state::SharedGameState;
 use crate::game::weapon::WeaponType;
 use crate::graphics::font::{Font, Symbols};
@@ -33,6 +36,9 @@ use crate::components::tilemap::TileLayer;
 
 const TSC_SUBSTITUTION_MAP_SIZE: usize = 1;
 
+/// <NUM indices starting from this one print TSC variables instead of the item/ammo counters.
+pub const NUM_VARIABLE_BASE: usize = 1000;
+
 bitfield! {
     pub struct TextScriptFlags(u16);
     impl Debug;
@@ -44,7 +50,7 @@ bitfield! {
     pub cutscene_skip, set_cutscene_skip: 7;
 }
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone, serde::Serialize, serde::Deserialize)]
 #[allow(non_camel_case_types)]
 #[repr(u8)]
 pub enum TextScriptEncoding {
@@ -271,6 +277,9 @@ pub struct TextScriptVM {
     /// Requires `constants.textscript.reset_invicibility_on_any_script`
     pub reset_invicibility: bool,
     pub numbers: [u16; 4],
+    /// Result of the last <VCM or <VCV, checked by the variable jump opcodes. It carries over to the following
+    /// events, but isn't stored in saves, scripts have to compare again after a profile has been loaded.
+    pub compare_result: Ordering,
     pub face: u16,
     pub item: u16,
     pub current_line: TextScriptLine,
@@ -347,6 +356,7 @@ impl TextScriptVM {
             suspend: true,
             reset_invicibility: false,
             numbers: [0; 4],
+            compare_result: Ordering::Equal,
             face: 0,
             item: 0,
             current_line: TextScriptLine::Line1,
@@ -449,6 +459,16 @@ impl TextScriptVM {
                 TextScriptExecutionState::Running(event, ip) => {
                     state.control_flags.set_interactions_disabled(true);
 
+                    #[cfg(feature = "scripting-lua")]
+                    if ip == 0 {
+                        LuaScriptingState::run_hook(state, game_scene, LuaHook::EventStart(event))?;
+
+                        // the handler might have started another event
+                        if state.textscript_vm.state != TextScriptExecutionState::Running(event, 0) {
+                            continue;
+                        }
+                    }
+
                     // The `!event` case gets optimized out on None match
                     match (cached_event, !event) {
                         (None, bevent) | (Some((bevent, _)), _) if bevent != event => {
@@ -1245,6 +1265,82 @@ impl TextScriptVM {
 
                 exec_state = TextScriptExecutionState::Running(event, cursor.position() as u32);
             }
+            TSCOpCode::VAe
+            | TSCOpCode::VAp
+            | TSCOpCode::VAm
+            | TSCOpCode::VVe
+            | TSCOpCode::VVp
+            | TSCOpCode::VVm => {
+                let index = read_cur_varint(&mut cursor)? as usize;
+                let operand = read_cur_varint(&mut cursor)?;
+
+                let value = match op {
+                    TSCOpCode::VVe | TSCOpCode::VVp | TSCOpCode::VVm => state.get_variable(operand as usize),
+                    _ => operand,
+                };
+                let current = state.get_variable(index);
+
+                let result = match op {
+                    TSCOpCode::VAp | TSCOpCode::VVp => current.wrapping_add(value),
+                    TSCOpCode::VAm | TSCOpCode::VVm => current.wrapping_sub(value),
+                    _ => value,
+                };
+                state.set_variable(index, result);
+
+                exec_state = TextScriptExecutionState::Running(event, cursor.position() as u32);
+            }
+            TSCOpCode::VCM | TSCOpCode::VCV => {
+                let index = read_cur_varint(&mut cursor)? as usize;
+                let operand = read_cur_varint(&mut cursor)?;
+
+                let value = if op == TSCOpCode::VCV { state.get_variable(operand as usize) } else { operand };
+                state.textscript_vm.compare_result = state.get_variable(index).cmp(&value);
+
+                exec_state = TextScriptExecutionState::Running(event, cursor.position() as u32);
+            }
+            TSCOpCode::VJE | TSCOpCode::VJN | TSCOpCode::VJG | TSCOpCode::VJL => {
+                let event_num = read_cur_varint(&mut cursor)? as u16;
+
+                let result = state.textscript_vm.compare_result;
+                let jump = match op {
+                    TSCOpCode::VJE => result == Ordering::Equal,
+                    TSCOpCode::VJN => result != Ordering::Equal,
+                    TSCOpCode::VJG => result == Ordering::Greater,
+                    _ => result == Ordering::Less,
+                };
+
+                if jump {
+                    state.textscript_vm.clear_text_box();
+                    exec_state = TextScriptExecutionState::Running(event_num, 0);
+                } else {
+                    exec_state = TextScriptExecutionState::Running(event, cursor.position() as u32);
+                }
+            }
+            TSCOpCode::DRK => {
+                let darkness = read_cur_varint(&mut cursor)?.clamp(0, 100) as f32 / 100.0;
+
+                game_scene.lights.darkness = Some(darkness);
+                game_scene.lighting_mode = game_scene.lights.lighting_mode(game_scene.lighting_mode);
+
+                exec_state = TextScriptExecutionState::Running(event, cursor.position() as u32);
+            }
+            TSCOpCode::LNP => {
+                let event_num = read_cur_varint(&mut cursor)? as u16;
+                let color = read_cur_varint(&mut cursor)?.clamp(0, 999);
+                let radius = read_cur_varint(&mut cursor)?.max(0);
+                let flicker = read_cur_varint(&mut cursor)?.clamp(0, 100);
+
+                // Each digit of the color is a channel from 0 to 9.
+                let channel = |digit: i32| (digit % 10 * 255 / 9) as u8;
+                let light = (radius > 0).then(|| Light {
+                    color: [channel(color / 100), channel(color / 10), channel(color)],
+                    radius: radius as f32,
+                    flicker: flicker as f32 / 100.0,
+                });
+                game_scene.lights.set_npc_event_light(event_num, light);
+
+                exec_state = TextScriptExecutionState::Running(event, cursor.position() as u32);
+            }
 
             TSCOpCode::MLp => {
                 let life = read_cur_varint(&mut cursor)? as u16;
@@ -1336,7 +1432,13 @@ impl TextScriptVM {
             TSCOpCode::NUM => {
                 let index = read_cur_varint(&mut cursor)? as usize;
 
-                if let Some(num) = state.textscript_vm.numbers.get(index) {
+                let num = if index >= NUM_VARIABLE_BASE {
+                    Some(state.get_variable(index - NUM_VARIABLE_BASE) as i64)
+                } else {
+                    state.textscript_vm.numbers.get(index).map(|&num| num as i64)
+                };
+
+                if let Some(num) = num {
                     let mut str = num.to_string().chars().collect();
 
                     match state.textscript_vm.current_line {
@@ -1659,7 +1761,7 @@ impl TextScriptVM {
                         npc.size = entry.size;
                         npc.exp = entry.experience as u16;
                         npc.damage = entry.damage as u16;
-                        npc.spritesheet_id = entry.spritesheet_id as u16;
+                        npc.spritesheet_id = entry.spritesheet_id;
 
                         npc.cond.set_alive(true);
                         npc.action_num = 0;
@@ -2005,7 +2107,7 @@ impl TextScriptVM {
             TSCOpCode::STC => {
                 let new_record = game_scene.nikumaru.save_counter(state, ctx)?;
 
-                if state.replay_state == ReplayState::Recording {
+                if state.replay_state == ReplayState::Recording && game_scene.replay.kind != ReplayKind::Session {
                     game_scene.replay.stop_recording(state, ctx, new_record)?;
                 }
 
@@ -2076,3 +2178,57 @@ impl TextScript {
         self.event_map.contains_key(&id)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT: &str = "#0100
+<VA=0001:0010<VA+0001:0005<VA-0001:0003<VV=0002:0001<VV+0002:0001<VV-0002:0003<VCM0001:0012<VJE0200<END
+#0200
+<VA=0010:0001<VCV0002:0001<VJG0300<VJL0400<END
+#0300
+<VA=0011:0001<END
+#0400
+<VA=0012:0001<VJN0500<END
+#0500
+<VA=0013:0001<NUM1001<WAI9999<END
+#0600
+<VA=1500:0007<VV+0001:1500<NUM2500<WAI9999<END
+";
+
+    fn run_event(state: &mut SharedGameState, game_scene: &mut GameScene, ctx: &mut Context, event_num: u16) {
+        state.textscript_vm.start_script(event_num);
+        TextScriptVM::run(state, game_scene, ctx).unwrap();
+    }
+
+    #[test]
+    fn test_variables() {
+        let mut ctx = Box::new(Context::new());
+        let mut state = SharedGameState::new_headless(&mut ctx);
+        let mut scene = GameScene::new_empty(&mut state, &mut ctx);
+
+        let script = TextScript::compile(SCRIPT.as_bytes(), false, TextScriptEncoding::UTF8).unwrap();
+        state.textscript_vm.set_scene_script(script);
+
+        run_event(&mut state, &mut scene, &mut ctx, 100);
+        assert_eq!(state.get_variable(1), 12);
+        assert_eq!(state.get_variable(2), 24);
+        assert_eq!(state.get_variable(10), 1);
+        assert_eq!(state.get_variable(11), 1);
+        assert_eq!(state.get_variable(12), 0);
+        assert_eq!(state.textscript_vm.compare_result, Ordering::Greater);
+
+        // the comparison result carries over to the next event
+        run_event(&mut state, &mut scene, &mut ctx, 400);
+        assert_eq!(state.get_variable(12), 1);
+        assert_eq!(state.get_variable(13), 1);
+        assert_eq!(state.textscript_vm.line_1.iter().collect::<String>(), "12");
+
+        // out of range variables are ignored when written and read as 0
+        let variables = state.variables.clone();
+        run_event(&mut state, &mut scene, &mut ctx, 600);
+        assert_eq!(state.variables, variables);
+        assert_eq!(state.textscript_vm.line_1.iter().collect::<String>(), "0");
+    }
+}
@@ -0,0 +1,448 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::common::FadeState;
+use crate::components::replay::Replay;
+use crate::framework::backend::init_backend;
+use crate::framework::context::Context;
+use crate::framework::error::{GameError, GameResult};
+use crate::framework::graphics;
+use crate::framework::render_software::SoftwareRenderer;
+use crate::game::capture::{read_canvas, tick_delay, CaptureFile, Recorder};
+use crate::game::npc::NPC;
+use crate::game::player::Player;
+use crate::game::scripting::tsc::text_script::TextScriptExecutionState;
+use crate::game::shared_game_state::{ReplayKind, ReplayState, SharedGameState};
+use crate::game::Game;
+use crate::graphics::texture_set::{G_MAG, I_MAG};
+use crate::scene::game_scene::GameScene;
+use crate::scene::Scene;
+use crate::util::bitvec::BitVec;
+use crate::util::rng::XorShift;
+
+/// Options of the headless simulation runner, set from the command line when `--server-mode` is used.
+#[derive(Clone, Debug, Default)]
+pub struct ServerOptions {
+    /// Stage to load, if not set a new game is started like it would be from the title screen.
+    pub stage_id: Option<usize>,
+    /// Player starting position in tiles, only used together with `stage_id`.
+    pub player_pos: Option<(i16, i16)>,
+    /// Event to run after the stage has been loaded, only used together with `stage_id`.
+    pub event: Option<u16>,
+    /// Path to the replay file fed through `ReplayController`, the stage and mod are taken from it if it has keyframes.
+    pub replay_path: Option<PathBuf>,
+    /// Replay keyframe to start the playback from, only used with replays that have keyframes.
+    pub checkpoint: usize,
+    /// Stop the simulation at the first detected replay desync.
+    pub stop_on_desync: bool,
+    /// Seed of the game RNG, ignored when a replay is loaded.
+    pub seed: i32,
+    /// Maximum amount of ticks to simulate.
+    pub max_ticks: Option<u32>,
+    /// Where to write the state dump, stdout is used if not set.
+    pub dump_path: Option<PathBuf>,
+    /// Where to write a frame rendered after every tick, as an animated GIF if the path ends with `.gif`,
+    /// or as numbered PNG files in that directory otherwise.
+    pub capture_path: Option<PathBuf>,
+}
+
+impl ServerOptions {
+    /// Parses a runner-specific argument, returns false if the argument is unknown.
+    pub fn parse_arg(&mut self, arg: &str, value: &mut dyn Iterator<Item = String>) -> Result<bool, String> {
+        let mut next_value = || value.next().ok_or_else(|| format!("Missing value for {}.", arg));
+
+        match arg {
+            "--stage" => self.stage_id = Some(parse_number(arg, &next_value()?)?),
+            "--pos" => {
+                let pos = next_value()?;
+                let (x, y) = pos.split_once(',').ok_or_else(|| format!("Invalid value for {}: {}", arg, pos))?;
+                self.player_pos = Some((parse_number(arg, x)?, parse_number(arg, y)?));
+            }
+            "--event" => self.event = Some(parse_number(arg, &next_value()?)?),
+            "--replay" => self.replay_path = Some(PathBuf::from(next_value()?)),
+            "--checkpoint" => self.checkpoint = parse_number(arg, &next_value()?)?,
+            "--stop-on-desync" => self.stop_on_desync = true,
+            "--seed" => self.seed = parse_number(arg, &next_value()?)?,
+            "--ticks" => self.max_ticks = Some(parse_number(arg, &next_value()?)?),
+            "--dump" => self.dump_path = Some(PathBuf::from(next_value()?)),
+            "--capture" => self.capture_path = Some(PathBuf::from(next_value()?)),
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    /// Checks whether the runner has a way to end the simulation.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.replay_path.is_none() && self.max_ticks.is_none() {
+            return Err("Server mode requires --replay or --ticks to be set.".to_owned());
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(arg: &str, value: &str) -> Result<T, String> {
+    value.trim().parse().map_err(|_| format!("Invalid value for {}: {}", arg, value))
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitReason {
+    ReplayFinished,
+    Desync,
+    TickLimit,
+    SceneChanged,
+    Shutdown,
+}
+
+#[derive(serde::Serialize)]
+pub struct DesyncDump {
+    pub tick: u32,
+    pub parts: Vec<&'static str>,
+}
+
+#[derive(serde::Serialize)]
+pub struct PlayerDump {
+    pub alive: bool,
+    pub x: i32,
+    pub y: i32,
+    pub vel_x: i32,
+    pub vel_y: i32,
+    pub life: u16,
+    pub max_life: u16,
+}
+
+impl PlayerDump {
+    fn from_player(player: &Player) -> PlayerDump {
+        PlayerDump {
+            alive: player.cond.alive(),
+            x: player.x,
+            y: player.y,
+            vel_x: player.vel_x,
+            vel_y: player.vel_y,
+            life: player.life,
+            max_life: player.max_life,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct NPCDump {
+    pub id: u16,
+    pub npc_type: u16,
+    pub x: i32,
+    pub y: i32,
+    pub vel_x: i32,
+    pub vel_y: i32,
+    pub life: u16,
+    pub direction: u8,
+    pub action_num: u16,
+    pub anim_num: u16,
+    pub flag_num: u16,
+    pub event_num: u16,
+}
+
+impl NPCDump {
+    fn from_npc(npc: &NPC) -> NPCDump {
+        NPCDump {
+            id: npc.id,
+            npc_type: npc.npc_type,
+            x: npc.x,
+            y: npc.y,
+            vel_x: npc.vel_x,
+            vel_y: npc.vel_y,
+            life: npc.life,
+            direction: npc.direction as u8,
+            action_num: npc.action_num,
+            anim_num: npc.anim_num,
+            flag_num: npc.flag_num,
+            event_num: npc.event_num,
+        }
+    }
+}
+
+/// Machine-readable snapshot of the simulation, written as JSON once the runner exits.
+#[derive(serde::Serialize)]
+pub struct StateDump {
+    pub engine_version: &'static str,
+    pub exit_reason: ExitReason,
+    pub ticks: u32,
+    pub stage_id: Option<usize>,
+    pub stage_name: Option<String>,
+    pub game_rng: u64,
+    /// First replay desync, if any was detected.
+    pub desync: Option<DesyncDump>,
+    /// Indices of all set flags.
+    pub game_flags: Vec<usize>,
+    pub skip_flags: Vec<usize>,
+    pub map_flags: Vec<usize>,
+    pub players: Vec<PlayerDump>,
+    pub npcs: Vec<NPCDump>,
+}
+
+impl StateDump {
+    pub fn new(state: &SharedGameState, scene: Option<&GameScene>, ticks: u32, exit_reason: ExitReason) -> StateDump {
+        let set_bits = |bits: &BitVec| bits.iter().enumerate().filter(|(_, set)| *set).map(|(i, _)| i).collect();
+
+        let mut dump = StateDump {
+            engine_version: env!("CARGO_PKG_VERSION"),
+            exit_reason,
+            ticks,
+            stage_id: None,
+            stage_name: None,
+            game_rng: state.game_rng.dump_state(),
+            desync: None,
+            game_flags: set_bits(&state.game_flags),
+            skip_flags: set_bits(&state.skip_flags),
+            map_flags: set_bits(&state.map_flags),
+            players: Vec::new(),
+            npcs: Vec::new(),
+        };
+
+        if let Some(scene) = scene {
+            dump.stage_id = Some(scene.stage_id);
+            dump.stage_name = Some(scene.stage.data.name.clone());
+            dump.desync = scene.replay.desync.as_ref().map(|d| DesyncDump { tick: d.tick, parts: d.parts.clone() });
+            dump.players.push(PlayerDump::from_player(&scene.player1));
+            dump.players.push(PlayerDump::from_player(&scene.player2));
+            dump.npcs = scene.npc_list.iter_alive().map(|npc| NPCDump::from_npc(npc)).collect();
+        }
+
+        dump
+    }
+}
+
+/// Runs the game without a window or audio, as fast as possible, and dumps the resulting state.
+pub fn run(game: &mut Game, ctx: &mut Context, options: &ServerOptions) -> GameResult {
+    options.validate().map_err(GameError::ConfigError)?;
+
+    ctx.screen_size = (640.0, 480.0);
+
+    let mut recorder = None;
+    if let Some(path) = &options.capture_path {
+        // Textures aren't loaded in headless mode, the sound manager has been already initialized at this point.
+        ctx.headless = false;
+        ctx.renderer = Some(Box::new(SoftwareRenderer::new(640, 480)));
+
+        recorder = Some(if is_gif_path(path) {
+            Recorder::gif(Box::new(File::create(path)?))?
+        } else {
+            std::fs::create_dir_all(path)?;
+            Recorder::frames()?
+        });
+    } else {
+        let backend = init_backend(true, ctx.size_hint)?;
+        let event_loop = backend.create_event_loop(ctx)?;
+        ctx.renderer = Some(event_loop.new_renderer(ctx as *mut Context)?);
+    }
+
+    let state = unsafe { &mut *game.state.get() };
+    let mut replay = None;
+
+    if let Some(path) = &options.replay_path {
+        log::info!("Loading replay from {}.", path.display());
+
+        let mut data = Replay::new();
+        data.load_from(File::open(path)?)?;
+        state.set_replay_mods(&data.header);
+        replay = Some(data);
+    }
+
+    state.handle_resize(ctx)?;
+    state.reload_resources(ctx)?;
+
+    let mut scene = match replay {
+        Some(replay) if !replay.keyframes.is_empty() => {
+            replay.header.check_compatibility(state)?;
+            Box::new(replay.seek(state, ctx, options.checkpoint)?)
+        }
+        Some(replay) => {
+            let mut scene = create_scene(state, ctx, options)?;
+            scene.replay = replay;
+            scene.replay.start_playback(state, ReplayKind::Last)?;
+            scene
+        }
+        None => {
+            let scene = create_scene(state, ctx, options)?;
+            state.game_rng = XorShift::new(options.seed);
+            scene
+        }
+    };
+
+    scene.init(state, ctx)?;
+
+    let mut scene: Box<dyn Scene> = scene;
+    let mut ticks = 0u32;
+
+    let exit_reason = loop {
+        if options.replay_path.is_some() && state.replay_state == ReplayState::None {
+            break ExitReason::ReplayFinished;
+        }
+
+        if options.max_ticks.map_or(false, |max_ticks| ticks >= max_ticks) {
+            break ExitReason::TickLimit;
+        }
+
+        scene.tick(state, ctx)?;
+        ticks += 1;
+
+        if let (Some(recorder), Some(path)) = (&mut recorder, &options.capture_path) {
+            capture_frame(&*scene, state, ctx, recorder, path)?;
+        }
+
+        if options.stop_on_desync
+            && scene.downcast_ref::<GameScene>().map_or(false, |scene| scene.replay.desync.is_some())
+        {
+            break ExitReason::Desync;
+        }
+
+        if state.shutdown {
+            break ExitReason::Shutdown;
+        }
+
+        if let Some(next_scene) = state.next_scene.take() {
+            if !next_scene.is::<GameScene>() {
+                break ExitReason::SceneChanged;
+            }
+
+            scene = next_scene;
+            scene.init(state, ctx)?;
+        }
+    };
+
+    log::info!("Simulation finished after {} ticks ({:?}).", ticks, exit_reason);
+
+    if let (Some(recorder), Some(path)) = (&mut recorder, &options.capture_path) {
+        recorder.finish()?;
+        log::info!("Saved {} frames to {}.", recorder.frames_count(), path.display());
+    }
+
+    let dump = StateDump::new(state, scene.downcast_ref::<GameScene>().ok(), ticks, exit_reason);
+    let json = serde_json::to_string_pretty(&dump)?;
+
+    if let Some(path) = &options.dump_path {
+        let mut file = File::create(path)?;
+        file.write_all(json.as_bytes())?;
+    } else {
+        println!("{}", json);
+    }
+
+    Ok(())
+}
+
+fn is_gif_path(path: &Path) -> bool {
+    path.extension().map_or(false, |ext| ext.eq_ignore_ascii_case("gif"))
+}
+
+/// Draws the scene as it is right after the last tick and queues the frame for writing.
+fn capture_frame(
+    scene: &dyn Scene,
+    state: &mut SharedGameState,
+    ctx: &mut Context,
+    recorder: &mut Recorder,
+    path: &Path,
+) -> GameResult {
+    state.frame_time = 1.0;
+    unsafe {
+        G_MAG = if state.settings.subpixel_coords { state.scale } else { 1.0 };
+        I_MAG = state.scale;
+    }
+
+    graphics::prepare_draw(ctx)?;
+    graphics::clear(ctx, [0.0, 0.0, 0.0, 1.0].into());
+    scene.draw(state, ctx)?;
+    graphics::present(ctx)?;
+
+    let image = read_canvas(ctx, state.scale)?;
+    let file: Option<CaptureFile> = if is_gif_path(path) {
+        None
+    } else {
+        Some(Box::new(File::create(path.join(format!("{:06}.png", recorder.frames_count())))?))
+    };
+
+    recorder.push_frame(image, tick_delay(state, 1), file)
+}
+
+fn create_scene(state: &mut SharedGameState, ctx: &mut Context, options: &ServerOptions) -> GameResult<Box<GameScene>> {
+    let Some(stage_id) = options.stage_id else {
+        state.start_new_game(ctx)?;
+
+        return match state.next_scene.take().map(|scene| scene.downcast::<GameScene>()) {
+            Some(Ok(scene)) => Ok(scene),
+            _ => Err(GameError::InvalidValue("Failed to start a new game.".to_owned())),
+        };
+    };
+
+    if stage_id >= state.stages.len() {
+        return Err(GameError::InvalidValue(format!("Stage {} is out of bounds of the stage table.", stage_id)));
+    }
+
+    state.reset();
+
+    let mut scene = GameScene::new(state, ctx, stage_id)?;
+    let (pos_x, pos_y) = options.player_pos.unwrap_or(state.constants.game.new_game_player_pos);
+    scene.player1.cond.set_alive(true);
+    scene.player1.x = pos_x as i32 * scene.stage.map.tile_size.as_int() * 0x200;
+    scene.player1.y = pos_y as i32 * scene.stage.map.tile_size.as_int() * 0x200;
+
+    state.reset_map_flags();
+    state.control_flags.set_control_enabled(true);
+    state.control_flags.set_tick_world(true);
+    state.fade_state = FadeState::Hidden;
+    state.textscript_vm.state = match options.event {
+        Some(event) => TextScriptExecutionState::Running(event, 0),
+        None => TextScriptExecutionState::Ended,
+    };
+
+    Ok(Box::new(scene))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<ServerOptions, String> {
+        let mut options = ServerOptions::default();
+        let mut args = args.iter().map(|arg| arg.to_string());
+
+        while let Some(arg) = args.next() {
+            if !options.parse_arg(&arg, &mut args)? {
+                return Err(format!("Unknown argument {}.", arg));
+            }
+        }
+
+        Ok(options)
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse(&["--stage", "13", "--pos", "10, 8", "--event", "90", "--seed", "-5", "--ticks", "600"]);
+        let options = options.unwrap();
+        assert_eq!(options.stage_id, Some(13));
+        assert_eq!(options.player_pos, Some((10, 8)));
+        assert_eq!(options.event, Some(90));
+        assert_eq!(options.seed, -5);
+        assert_eq!(options.max_ticks, Some(600));
+        assert!(options.validate().is_ok());
+
+        let options = parse(&["--replay", "a.rep", "--checkpoint", "2", "--stop-on-desync"]).unwrap();
+        assert_eq!(options.replay_path, Some(PathBuf::from("a.rep")));
+        assert_eq!(options.checkpoint, 2);
+        assert!(options.stop_on_desync);
+        assert!(options.validate().is_ok());
+    }
+
+    #[test]
+    fn test_parse_invalid_args() {
+        assert!(parse(&["--stage", "13"]).unwrap().validate().is_err());
+        assert!(parse(&["--stage"]).is_err());
+        assert!(parse(&["--stage", "x"]).is_err());
+        assert!(parse(&["--pos", "10"]).is_err());
+        assert!(parse(&["--pos", "10,y"]).is_err());
+        assert!(parse(&["--pos", "10,8,3"]).is_err());
+        assert!(parse(&["--ticks", "-1"]).is_err());
+        assert!(parse(&["--unknown"]).is_err());
+    }
+}
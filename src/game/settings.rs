@@ -87,6 +87,12 @@ pub struct Settings {
     pub discord_rpc: bool,
     #[serde(default = "default_true")]
     pub allow_strafe: bool,
+    /// Paths of the mods loaded on top of the base game, mods enabled later override the earlier ones.
+    #[serde(default)]
+    pub active_mods: Vec<String>,
+    /// Names of the post-processing shaders from `shaders/`, in the order they're applied.
+    #[serde(default)]
+    pub post_process: Vec<String>,
 }
 
 fn default_true() -> bool {
@@ -467,6 +473,8 @@ impl Default for Settings {
             cutscene_skip_mode: CutsceneSkipMode::Hold,
             discord_rpc: true,
             allow_strafe: true,
+            active_mods: Vec::new(),
+            post_process: Vec::new(),
         }
     }
 }
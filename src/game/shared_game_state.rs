@@ -1,9 +1,11 @@
+use std::collections::BTreeMap;
 use std::{cmp, ops::Div};
 
 use chrono::{Datelike, Local};
 
 use crate::common::{ControlFlags, Direction, FadeState};
 use crate::components::draw_common::{draw_number, Alignment};
+use crate::components::replay::ReplayHeader;
 use crate::data::vanilla::VanillaExtractor;
 #[cfg(feature = "discord-rpc")]
 use crate::discord::DiscordRPC;
@@ -15,16 +17,20 @@ use crate::framework::graphics::{create_texture_mutable, set_render_target};
 use crate::framework::vfs::OpenOptions;
 use crate::framework::{filesystem, graphics};
 use crate::game::caret::{Caret, CaretType};
+use crate::game::hot_reload::HotReloadWatcher;
 use crate::game::npc::NPCTable;
 use crate::game::player::TargetPlayer;
 use crate::game::profile::GameProfile;
+#[cfg(feature = "scripting-lua")]
+use crate::game::scripting::lua::LuaScriptingState;
 use crate::game::scripting::tsc::credit_script::{CreditScript, CreditScriptVM};
 use crate::game::scripting::tsc::text_script::{
     ScriptMode, TextScript, TextScriptEncoding, TextScriptExecutionState, TextScriptVM,
 };
 use crate::game::settings::Settings;
-use crate::game::stage::StageData;
+use crate::game::stage::{StageData, StageTableFormat};
 use crate::graphics::bmfont::BMFont;
+use crate::graphics::post_process;
 use crate::graphics::texture_set::TextureSet;
 use crate::i18n::Locale;
 use crate::input::touch_controls::TouchControls;
@@ -39,7 +45,7 @@ use crate::util::rng::XorShift;
 
 use super::filesystem_container::FilesystemContainer;
 
-#[derive(PartialEq, Eq, Copy, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(PartialEq, Eq, Copy, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub enum TimingMode {
     _50Hz,
     _60Hz,
@@ -110,7 +116,7 @@ pub enum GameDifficulty {
     Hard = 4,
 }
 
-#[derive(PartialEq, Eq, Copy, Clone, num_derive::FromPrimitive)]
+#[derive(PartialEq, Eq, Copy, Clone, Debug, num_derive::FromPrimitive)]
 pub enum PlayerCount {
     One,
     Two,
@@ -231,12 +237,14 @@ pub enum MenuCharacter {
 pub enum ReplayKind {
     Best,
     Last,
+    /// Arbitrary play session recorded from the pause menu, stored in the replay directory.
+    Session,
 }
 
 impl ReplayKind {
     pub fn get_suffix(&self) -> String {
         match self {
-            ReplayKind::Best => ".rep".to_string(),
+            ReplayKind::Best | ReplayKind::Session => ".rep".to_string(),
             ReplayKind::Last => ".last.rep".to_string(),
         }
     }
@@ -289,9 +297,18 @@ impl Default for PlayerSkinLocation {
     }
 }
 
+/// Amount of flags available to scripts, vanilla only has 8000 of them.
+pub const GAME_FLAG_COUNT: usize = 10000;
+
+/// Amount of integer variables available to <VA=, <VV= and related opcodes.
+pub const VARIABLE_COUNT: usize = 1000;
+
 pub struct SharedGameState {
     pub control_flags: ControlFlags,
     pub game_flags: BitVec,
+    pub variables: Vec<i32>,
+    /// Key/value data stored in saves by mods, grouped by the mod name.
+    pub mod_data: BTreeMap<String, BTreeMap<String, String>>,
     pub skip_flags: BitVec,
     pub map_flags: BitVec,
     pub fade_state: FadeState,
@@ -308,6 +325,8 @@ pub struct SharedGameState {
     pub carets: Vec<Caret>,
     pub touch_controls: TouchControls,
     pub mod_path: Option<String>,
+    /// Mods a played back replay was recorded with, loaded in place of the enabled ones.
+    pub replay_mod_paths: Option<Vec<String>>,
     pub mod_list: ModList,
     pub npc_table: NPCTable,
     pub npc_super_pos: (i32, i32),
@@ -315,6 +334,10 @@ pub struct SharedGameState {
     pub npc_curly_counter: u16,
     pub water_level: i32,
     pub stages: Vec<StageData>,
+    /// Format the stage table was loaded from, None if it hasn't been loaded yet.
+    pub stage_table_format: Option<StageTableFormat>,
+    /// Watcher of modified assets, running while the game is in debug mode.
+    pub hot_reload: Option<HotReloadWatcher>,
     pub frame_time: f64,
     pub debugger: bool,
     pub command_line: bool,
@@ -338,6 +361,8 @@ pub struct SharedGameState {
     pub difficulty: GameDifficulty,
     pub player_count: PlayerCount,
     pub player_count_modified_in_game: bool,
+    /// Set by the pause menu to start or stop recording a session replay.
+    pub replay_recording_toggled: bool,
     pub player2_skin_location: PlayerSkinLocation,
     pub replay_state: ReplayState,
     pub mod_requirements: ModRequirements,
@@ -346,6 +371,8 @@ pub struct SharedGameState {
     pub more_rust: bool,
     #[cfg(feature = "discord-rpc")]
     pub discord_rpc: DiscordRPC,
+    #[cfg(feature = "scripting-lua")]
+    pub lua: LuaScriptingState,
     pub shutdown: bool,
 }
 
@@ -412,7 +439,7 @@ impl SharedGameState {
         }
 
         let season = Season::current();
-        constants.rebuild_path_list(None, season, &settings);
+        constants.rebuild_path_list(None, &[], season, &settings);
 
         constants.load_locales(ctx)?;
 
@@ -450,7 +477,9 @@ impl SharedGameState {
 
         Ok(SharedGameState {
             control_flags: ControlFlags(0),
-            game_flags: BitVec::with_size(8000),
+            game_flags: BitVec::with_size(GAME_FLAG_COUNT),
+            variables: vec![0; VARIABLE_COUNT],
+            mod_data: BTreeMap::new(),
             skip_flags: BitVec::with_size(64),
             map_flags: BitVec::with_size(128),
             fade_state: FadeState::Hidden,
@@ -465,6 +494,7 @@ impl SharedGameState {
             carets: Vec::with_capacity(32),
             touch_controls: TouchControls::new(),
             mod_path: None,
+            replay_mod_paths: None,
             mod_list,
             npc_table: NPCTable::new(),
             npc_super_pos: (0, 0),
@@ -472,6 +502,8 @@ impl SharedGameState {
             npc_curly_counter: 0,
             water_level: 0,
             stages: Vec::with_capacity(96),
+            stage_table_format: None,
+            hot_reload: None,
             frame_time: 0.0,
             debugger: false,
             command_line: false,
@@ -495,6 +527,7 @@ impl SharedGameState {
             difficulty: GameDifficulty::Normal,
             player_count: PlayerCount::One,
             player_count_modified_in_game: false,
+            replay_recording_toggled: false,
             player2_skin_location: PlayerSkinLocation::default(),
             replay_state: ReplayState::None,
             mod_requirements,
@@ -503,23 +536,28 @@ impl SharedGameState {
             more_rust,
             #[cfg(feature = "discord-rpc")]
             discord_rpc: DiscordRPC::new(discord_rpc_app_id),
+            #[cfg(feature = "scripting-lua")]
+            lua: LuaScriptingState::new(),
             shutdown: false,
         })
     }
 
     pub fn reload_stage_table(&mut self, ctx: &mut Context) -> GameResult {
-        let stages = StageData::load_stage_table(
+        let (stages, format) = StageData::load_stage_table(
             ctx,
             &self.constants.base_paths,
             self.constants.is_switch,
             self.constants.stage_encoding,
         )?;
         self.stages = stages;
+        self.stage_table_format = Some(format);
         Ok(())
     }
 
     pub fn reload_resources(&mut self, ctx: &mut Context) -> GameResult {
-        self.constants.rebuild_path_list(self.mod_path.clone(), self.season, &self.settings);
+        self.constants.revert_constant_json_files()?;
+        let mod_paths = self.mod_paths();
+        self.constants.rebuild_path_list(self.mod_path.as_ref(), &mod_paths, self.season, &self.settings);
         if !self.constants.is_demo {
             //TODO find a more elegant way to handle this
             self.constants.special_treatment_for_csplus_mods(self.mod_path.as_ref());
@@ -527,10 +565,12 @@ impl SharedGameState {
         self.constants.load_csplus_tables(ctx)?;
         self.constants.load_animated_faces(ctx)?;
         self.constants.load_texture_size_hints(ctx)?;
+        self.constants.apply_constant_json_files(ctx)?;
         self.reload_stage_table(ctx)?;
 
         let npc_tbl = filesystem::open_find(ctx, &self.constants.base_paths, "npc.tbl")?;
-        let npc_table = NPCTable::load_from(npc_tbl)?;
+        let mut npc_table = NPCTable::load_from(npc_tbl)?;
+        npc_table.load_custom_npcs(ctx, &self.constants.base_paths)?;
         self.npc_table = npc_table;
 
         let head_tsc = filesystem::open_find(ctx, &self.constants.base_paths, "Head.tsc")?;
@@ -558,11 +598,80 @@ impl SharedGameState {
 
         self.sound_manager.load_custom_sound_effects(ctx, &self.constants.base_paths)?;
 
+        #[cfg(feature = "scripting-lua")]
+        self.lua.reload_scripts(ctx, &self.constants.base_paths)?;
+
+        self.apply_post_process(ctx);
+
         Ok(())
     }
 
+    /// Rebuilds the post-processing chain of the renderer from the settings. Errors are only logged, as a broken
+    /// shader shouldn't keep the game from running.
+    pub fn apply_post_process(&self, ctx: &mut Context) {
+        if !graphics::supports_post_process(ctx).unwrap_or(false) {
+            return;
+        }
+
+        let passes = post_process::load_passes(ctx, &self.constants.base_paths, &self.settings.post_process);
+        if let Err(err) = graphics::set_post_process(ctx, &passes, self.scale) {
+            log::error!("Failed to set up post-processing: {}", err);
+        }
+    }
+
+    /// Returns the data directories of the current challenge, the enabled mods and their dependencies, from the
+    /// highest priority one. Enabled mods which are locked or can't be loaded together with the ones of higher
+    /// priority are left out. While a replay is played back, the mods it was recorded with are returned instead.
+    pub fn mod_paths(&self) -> Vec<String> {
+        if let Some(replay_mod_paths) = &self.replay_mod_paths {
+            return replay_mod_paths.clone();
+        }
+
+        let mut mod_paths: Vec<String> = self.mod_path.iter().cloned().collect();
+        let mut resolved = self.mod_list.resolve_paths(&mod_paths).unwrap_or_default();
+
+        let active_mods = self.settings.active_mods.iter().rev().filter(|path| {
+            self.mod_path.as_ref() != Some(*path)
+                && self.mod_list.mods.iter().any(|mod_info| {
+                    mod_info.valid && &mod_info.path == *path && mod_info.satisfies_requirement(&self.mod_requirements)
+                })
+        });
+
+        for path in active_mods {
+            mod_paths.push(path.clone());
+            match self.mod_list.resolve_paths(&mod_paths) {
+                Ok(paths) => resolved = paths,
+                Err(err) => {
+                    log::warn!("Not loading mod {}: {}", path, err);
+                    mod_paths.pop();
+                }
+            }
+        }
+
+        resolved
+    }
+
+    /// Selects the challenge and mods a replay was recorded with, resources have to be reloaded afterwards.
+    /// Replays made before the mod list was recorded only had the challenge and its dependencies loaded.
+    pub fn set_replay_mods(&mut self, header: &ReplayHeader) {
+        self.mod_path = header.mod_path.clone();
+        self.replay_mod_paths = Some(if header.version >= 4 {
+            header.mod_paths.clone()
+        } else {
+            let challenge: Vec<String> = header.mod_path.iter().cloned().collect();
+            self.mod_list.resolve_paths(&challenge).unwrap_or(challenge)
+        });
+    }
+
+    /// Goes back to the enabled mods after a replay has been played back.
+    pub fn clear_replay_mods(&mut self) {
+        self.mod_path = None;
+        self.replay_mod_paths = None;
+    }
+
     pub fn reload_graphics(&mut self) {
-        self.constants.rebuild_path_list(self.mod_path.clone(), self.season, &self.settings);
+        let mod_paths = self.mod_paths();
+        self.constants.rebuild_path_list(self.mod_path.as_ref(), &mod_paths, self.season, &self.settings);
         self.texture_set.unload_all();
     }
 
@@ -661,7 +770,8 @@ impl SharedGameState {
         target_player: Option<TargetPlayer>,
     ) -> GameResult {
         if let Some(save_path) = self.get_save_filename(self.save_slot) {
-            if let Ok(data) = filesystem::open_options(ctx, save_path, OpenOptions::new().write(true).create(true)) {
+            let options = OpenOptions::new().write(true).create(true).truncate(true);
+            if let Ok(data) = filesystem::open_options(ctx, save_path, options) {
                 let profile = GameProfile::dump(self, game_scene, target_player);
                 profile.write_save(data)?;
             } else {
@@ -706,7 +816,9 @@ impl SharedGameState {
 
     pub fn reset(&mut self) {
         self.control_flags.0 = 0;
-        self.game_flags = BitVec::with_size(8000);
+        self.game_flags = BitVec::with_size(GAME_FLAG_COUNT);
+        self.variables = vec![0; VARIABLE_COUNT];
+        self.mod_data.clear();
         self.fade_state = FadeState::Hidden;
         self.game_rng = XorShift::new(chrono::Local::now().timestamp() as i32);
         self.teleporter_slots.clear();
@@ -729,6 +841,7 @@ impl SharedGameState {
         // ensure no texture is bound before destroying them.
         set_render_target(ctx, None)?;
         self.lightmap_canvas = Some(create_texture_mutable(ctx, width, height)?);
+        self.apply_post_process(ctx);
 
         Ok(())
     }
@@ -784,6 +897,18 @@ impl SharedGameState {
         }
     }
 
+    pub fn set_variable(&mut self, id: usize, value: i32) {
+        if let Some(variable) = self.variables.get_mut(id) {
+            *variable = value;
+        } else {
+            log::warn!("Attempted to set an out-of-bounds variable: {} to {}.", id, value);
+        }
+    }
+
+    pub fn get_variable(&self, id: usize) -> i32 {
+        self.variables.get(id).copied().unwrap_or(0)
+    }
+
     pub fn reset_skip_flags(&mut self) {
         self.skip_flags = BitVec::with_size(64);
     }
@@ -921,3 +1046,14 @@ impl SharedGameState {
         return self.loc.tt(key, args);
     }
 }
+
+#[cfg(test)]
+impl SharedGameState {
+    /// Creates a game state with only the built-in data and no audio output, for tests which don't need the game data.
+    pub(crate) fn new_headless(ctx: &mut Context) -> SharedGameState {
+        filesystem::mount_vfs(ctx, Box::new(crate::data::builtin_fs::BuiltinFS::new()));
+        ctx.headless = true;
+
+        SharedGameState::new(ctx).unwrap()
+    }
+}
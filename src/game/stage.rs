@@ -1,8 +1,10 @@
+use std::collections::BTreeMap;
+use std::io;
 use std::io::{Cursor, Read};
 use std::str::from_utf8;
 
-use byteorder::ReadBytesExt;
 use byteorder::LE;
+use byteorder::{ReadBytesExt, WriteBytesExt};
 
 use crate::common::Color;
 use crate::components::tilemap::TileLayer;
@@ -11,8 +13,10 @@ use crate::framework::context::Context;
 use crate::framework::error::GameError::ResourceLoadError;
 use crate::framework::error::{GameError, GameResult};
 use crate::framework::filesystem;
+use crate::game::lighting::StageLighting;
 use crate::game::map::{Map, NPCData};
 use crate::game::scripting::tsc::text_script::{TextScript, TextScriptEncoding};
+use crate::game::shared_game_state::TileSize;
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct NpcType {
@@ -24,6 +28,10 @@ impl NpcType {
         Self { name: name.to_owned() }
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub fn filename(&self) -> String {
         ["Npc", &self.name].join("")
     }
@@ -112,7 +120,24 @@ impl From<u8> for BackgroundType {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+impl From<BackgroundType> for u8 {
+    fn from(val: BackgroundType) -> Self {
+        match val {
+            BackgroundType::TiledStatic => 0,
+            BackgroundType::TiledParallax => 1,
+            BackgroundType::Tiled => 2,
+            BackgroundType::Water => 3,
+            BackgroundType::Black => 4,
+            BackgroundType::Scrolling => 5,
+            BackgroundType::OutsideWind => 6,
+            BackgroundType::Outside => 7,
+            BackgroundType::OutsideUnknown => 8,
+            BackgroundType::Waterway => 9,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum PxPackScroll {
     Normal,
     ThreeQuarters,
@@ -161,7 +186,7 @@ impl PxPackScroll {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct PxPackStageData {
     pub tileset_fg: String,
     pub tileset_mg: String,
@@ -191,6 +216,95 @@ pub struct StageData {
     pub npc2: NpcType,
 }
 
+/// Format of a stage table, kept so the table can be written back the same way it was loaded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StageTableFormat {
+    /// `stage.json`, specific to this engine.
+    Json,
+    /// Cave Story+ `stage.tbl`.
+    CSPlus,
+    /// `stage.sect` dumped from the freeware executable.
+    Freeware,
+    /// Moustache Rider `mrmap.bin`.
+    MoustacheRider,
+    /// NXEngine `stage.dat`.
+    NXEngine,
+}
+
+impl StageTableFormat {
+    pub fn file_name(self) -> &'static str {
+        match self {
+            StageTableFormat::Json => "stage.json",
+            StageTableFormat::CSPlus => "stage.tbl",
+            StageTableFormat::Freeware => "stage.sect",
+            StageTableFormat::MoustacheRider => "mrmap.bin",
+            StageTableFormat::NXEngine => "stage.dat",
+        }
+    }
+}
+
+/// A stage entry in `stage.json`.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct StageTableEntry {
+    name: String,
+    #[serde(default)]
+    name_jp: String,
+    map: String,
+    tileset: String,
+    background: String,
+    background_type: u8,
+    #[serde(default)]
+    boss_no: u8,
+    npc1: String,
+    npc2: String,
+    #[serde(default = "default_background_color")]
+    background_color: [u8; 3],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pxpack_data: Option<PxPackStageData>,
+}
+
+fn default_background_color() -> [u8; 3] {
+    [0, 0, 32]
+}
+
+impl From<&StageData> for StageTableEntry {
+    fn from(stage: &StageData) -> Self {
+        StageTableEntry {
+            name: stage.name.clone(),
+            name_jp: stage.name_jp.clone(),
+            map: stage.map.clone(),
+            tileset: stage.tileset.name.clone(),
+            background: stage.background.name().to_owned(),
+            background_type: stage.background_type.into(),
+            boss_no: stage.boss_no,
+            npc1: stage.npc1.name().to_owned(),
+            npc2: stage.npc2.name().to_owned(),
+            background_color: [stage.background_color.r, stage.background_color.g, stage.background_color.b]
+                .map(|c| (c * 255.0).round() as u8),
+            pxpack_data: stage.pxpack_data.clone(),
+        }
+    }
+}
+
+impl From<StageTableEntry> for StageData {
+    fn from(entry: StageTableEntry) -> Self {
+        let [r, g, b] = entry.background_color;
+        StageData {
+            name_jp: if entry.name_jp.is_empty() { entry.name.clone() } else { entry.name_jp },
+            name: entry.name,
+            map: entry.map,
+            boss_no: entry.boss_no,
+            tileset: Tileset::new(&entry.tileset),
+            pxpack_data: entry.pxpack_data,
+            background: Background::new(&entry.background),
+            background_type: BackgroundType::from(entry.background_type),
+            background_color: Color::from_rgb(r, g, b),
+            npc1: NpcType::new(&entry.npc1),
+            npc2: NpcType::new(&entry.npc2),
+        }
+    }
+}
+
 const NXENGINE_BACKDROPS: [&str; 15] = [
     "bk0",
     "bkBlue",
@@ -250,19 +364,59 @@ fn from_csplus_stagetbl(s: &[u8], is_switch: bool, encoding: Option<TextScriptEn
     }
 }
 
+fn to_encoding(s: &str, encoding: Option<TextScriptEncoding>, default: &'static encoding_rs::Encoding) -> Vec<u8> {
+    let encoding: &encoding_rs::Encoding = encoding.map_or(default, |encoding| encoding.into());
+    encoding.encode(s).0.into_owned()
+}
+
+/// Writes a string into a fixed size, zero padded field.
+fn write_fixed<W: io::Write>(out: &mut W, s: &[u8], len: usize, field: &str) -> GameResult {
+    if s.len() > len {
+        return Err(GameError::InvalidValue(format!(
+            "{} \"{}\" is longer than {} bytes.",
+            field,
+            String::from_utf8_lossy(s),
+            len
+        )));
+    }
+
+    out.write_all(s)?;
+    out.write_all(&vec![0u8; len - s.len()])?;
+
+    Ok(())
+}
+
+fn nxengine_index(names: &[&str], name: &str, field: &str) -> GameResult<u8> {
+    let Some(idx) = names.iter().position(|&n| n == name) else {
+        let message = format!("{} \"{}\" isn't supported by NXEngine stage tables.", field, name);
+        return Err(GameError::InvalidValue(message));
+    };
+
+    Ok(idx as u8)
+}
+
 impl StageData {
+    /// Loads the stage table, also returning the format it was stored in.
     pub fn load_stage_table(
         ctx: &mut Context,
         roots: &Vec<String>,
         is_switch: bool,
         encoding: Option<TextScriptEncoding>,
-    ) -> GameResult<Vec<Self>> {
+    ) -> GameResult<(Vec<Self>, StageTableFormat)> {
+        let stage_json_path = "/stage.json";
         let stage_tbl_path = "/stage.tbl";
         let stage_sect_path = "/stage.sect";
         let mrmap_bin_path = "/mrmap.bin";
         let stage_dat_path = "/stage.dat";
 
-        if filesystem::exists_find(ctx, roots, stage_tbl_path) {
+        if let Ok(file) = filesystem::open_find(ctx, roots, stage_json_path) {
+            log::info!("Loading JSON stage table from {}", &stage_json_path);
+
+            let entries: Vec<StageTableEntry> = serde_json::from_reader(file)?;
+            let stages = entries.into_iter().map(StageData::from).collect();
+
+            return Ok((stages, StageTableFormat::Json));
+        } else if filesystem::exists_find(ctx, roots, stage_tbl_path) {
             // Cave Story+ stage table.
             // Mod stage.tbl expects to overwrite from base stage.tbl
             let mut stages = Vec::new();
@@ -330,7 +484,7 @@ impl StageData {
                 }
             }
 
-            return Ok(stages);
+            return Ok((stages, StageTableFormat::CSPlus));
         } else if let Ok(mut file) = filesystem::open_find(ctx, roots, stage_sect_path) {
             // Cave Story freeware executable dump.
             let mut stages = Vec::new();
@@ -387,7 +541,7 @@ impl StageData {
                 stages.push(stage);
             }
 
-            return Ok(stages);
+            return Ok((stages, StageTableFormat::Freeware));
         } else if let Ok(mut file) = filesystem::open_find(ctx, roots, mrmap_bin_path) {
             // Moustache Rider stage table
             let mut stages = Vec::new();
@@ -446,7 +600,7 @@ impl StageData {
                 stages.push(stage);
             }
 
-            return Ok(stages);
+            return Ok((stages, StageTableFormat::MoustacheRider));
         } else if let Ok(mut file) = filesystem::open_find(ctx, roots, stage_dat_path) {
             let mut stages = Vec::new();
 
@@ -503,17 +657,122 @@ impl StageData {
                 stages.push(stage);
             }
 
-            return Ok(stages);
+            return Ok((stages, StageTableFormat::NXEngine));
         }
 
         Err(ResourceLoadError("No stage table found.".to_string()))
     }
+
+    /// Writes the stage table in specified format, encoding the strings the same way `load_stage_table` decodes them.
+    pub fn write_stage_table<W: io::Write>(
+        stages: &[StageData],
+        format: StageTableFormat,
+        is_switch: bool,
+        encoding: Option<TextScriptEncoding>,
+        mut out: W,
+    ) -> GameResult {
+        match format {
+            StageTableFormat::Json => {
+                let entries: Vec<StageTableEntry> = stages.iter().map(StageTableEntry::from).collect();
+                serde_json::to_writer_pretty(out, &entries)?;
+            }
+            StageTableFormat::CSPlus => {
+                let default = if is_switch { encoding_rs::UTF_8 } else { encoding_rs::SHIFT_JIS };
+                let enc = |s: &str| to_encoding(s, encoding, default);
+
+                for stage in stages {
+                    write_fixed(&mut out, &enc(&stage.tileset.name), 0x20, "Tileset")?;
+                    write_fixed(&mut out, &enc(&stage.map), 0x20, "Map")?;
+                    out.write_u32::<LE>(u8::from(stage.background_type) as u32)?;
+                    write_fixed(&mut out, &enc(stage.background.name()), 0x20, "Background")?;
+                    write_fixed(&mut out, &enc(stage.npc1.name()), 0x20, "NPC sheet")?;
+                    write_fixed(&mut out, &enc(stage.npc2.name()), 0x20, "NPC sheet")?;
+                    out.write_u8(stage.boss_no)?;
+                    write_fixed(&mut out, &enc(&stage.name_jp), 0x20, "Japanese name")?;
+                    write_fixed(&mut out, &enc(&stage.name), 0x20, "Name")?;
+                }
+            }
+            StageTableFormat::Freeware => {
+                let enc = |s: &str| to_encoding(s, encoding, encoding_rs::SHIFT_JIS);
+
+                for stage in stages {
+                    write_fixed(&mut out, &enc(&stage.tileset.name), 0x20, "Tileset")?;
+                    write_fixed(&mut out, &enc(&stage.map), 0x20, "Map")?;
+                    out.write_u32::<LE>(u8::from(stage.background_type) as u32)?;
+                    write_fixed(&mut out, &enc(stage.background.name()), 0x20, "Background")?;
+                    write_fixed(&mut out, &enc(stage.npc1.name()), 0x20, "NPC sheet")?;
+                    write_fixed(&mut out, &enc(stage.npc2.name()), 0x20, "NPC sheet")?;
+                    out.write_u8(stage.boss_no)?;
+                    write_fixed(&mut out, &enc(&stage.name), 0x20, "Name")?;
+                    // alignment
+                    out.write_all(&[0u8; 3])?;
+                }
+            }
+            StageTableFormat::MoustacheRider => {
+                let enc = |s: &str| to_encoding(s, encoding, encoding_rs::SHIFT_JIS);
+
+                out.write_u32::<LE>(stages.len() as u32)?;
+                for stage in stages {
+                    write_fixed(&mut out, &enc(&stage.tileset.name), 0x10, "Tileset")?;
+                    write_fixed(&mut out, &enc(&stage.map), 0x10, "Map")?;
+                    out.write_u8(stage.background_type.into())?;
+                    write_fixed(&mut out, &enc(stage.background.name()), 0x10, "Background")?;
+                    write_fixed(&mut out, &enc(stage.npc1.name()), 0x10, "NPC sheet")?;
+                    write_fixed(&mut out, &enc(stage.npc2.name()), 0x10, "NPC sheet")?;
+                    out.write_u8(stage.boss_no)?;
+                    write_fixed(&mut out, &enc(&stage.name), 0x22, "Name")?;
+                }
+            }
+            StageTableFormat::NXEngine => {
+                let count = u8::try_from(stages.len()).map_err(|_| {
+                    GameError::InvalidValue("NXEngine stage tables can't have more than 255 stages.".to_owned())
+                })?;
+
+                out.write_u8(count)?;
+                for stage in stages {
+                    write_fixed(&mut out, stage.map.as_bytes(), 0x20, "Map")?;
+                    write_fixed(&mut out, stage.name.as_bytes(), 0x23, "Name")?;
+                    out.write_u8(nxengine_index(&NXENGINE_TILESETS, &stage.tileset.name, "Tileset")?)?;
+                    out.write_u8(nxengine_index(&NXENGINE_BACKDROPS, stage.background.name(), "Background")?)?;
+                    out.write_u8(stage.background_type.into())?;
+                    out.write_u8(stage.boss_no)?;
+                    out.write_u8(nxengine_index(&NXENGINE_NPCS, stage.npc1.name(), "NPC sheet")?)?;
+                    out.write_u8(nxengine_index(&NXENGINE_NPCS, stage.npc2.name(), "NPC sheet")?)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Optional settings of a stage which don't fit in the stage table, loaded from `Stage/<map>.json`.
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+pub struct StageMetadata {
+    #[serde(default)]
+    pub lighting: StageLighting,
+}
+
+impl StageMetadata {
+    pub fn load(roots: &Vec<String>, map: &str, ctx: &Context) -> StageMetadata {
+        if let Ok(file) = filesystem::open_find(ctx, roots, ["Stage/", map, ".json"].join("")) {
+            match serde_json::from_reader::<_, StageMetadata>(file) {
+                Ok(metadata) => return metadata,
+                Err(err) => log::warn!("Failed to deserialize metadata of stage {}: {}", map, err),
+            }
+        }
+
+        StageMetadata::default()
+    }
 }
 
 #[derive(Clone)]
 pub struct Stage {
     pub map: Map,
     pub data: StageData,
+    pub metadata: StageMetadata,
+    /// Tiles changed since the stage has been loaded, by their index in `map.tiles`.
+    pub tile_edits: BTreeMap<usize, u16>,
 }
 
 impl Stage {
@@ -522,15 +781,17 @@ impl Stage {
 
         if let Ok(pxpack_file) = filesystem::open_find(ctx, roots, ["Stage/", &data.map, ".pxpack"].join("")) {
             let map = Map::load_pxpack(pxpack_file, roots, &mut data, ctx)?;
-            let stage = Self { map, data };
+            let metadata = StageMetadata::load(roots, &data.map, ctx);
+            let stage = Self { map, data, metadata, tile_edits: BTreeMap::new() };
 
             return Ok(stage);
         } else if let Ok(map_file) = filesystem::open_find(ctx, roots, ["Stage/", &data.map, ".pxm"].join("")) {
             let attrib_file = filesystem::open_find(ctx, roots, ["Stage/", &data.tileset.name, ".pxa"].join(""))?;
 
             let map = Map::load_pxm(map_file, attrib_file)?;
+            let metadata = StageMetadata::load(roots, &data.map, ctx);
 
-            let stage = Self { map, data };
+            let stage = Self { map, data, metadata, tile_edits: BTreeMap::new() };
 
             return Ok(stage);
         }
@@ -538,6 +799,31 @@ impl Stage {
         Err(GameError::ResourceLoadError(format!("Stage {} not found", data.map)))
     }
 
+    /// Returns true if the map file of the stage exists.
+    pub fn exists(roots: &Vec<String>, data: &StageData, ctx: &Context) -> bool {
+        filesystem::exists_find(ctx, roots, ["Stage/", &data.map, ".pxpack"].join(""))
+            || filesystem::exists_find(ctx, roots, ["Stage/", &data.map, ".pxm"].join(""))
+    }
+
+    /// Creates a stage with an empty map, for stages which don't have a map file yet.
+    pub fn new_empty(roots: &Vec<String>, data: &StageData, width: u16, height: u16, ctx: &mut Context) -> Self {
+        let mut attrib = Vec::new();
+        let attrib_path = ["Stage/", &data.tileset.name, ".pxa"].join("");
+        if let Ok(mut attrib_file) = filesystem::open_find(ctx, roots, attrib_path) {
+            if attrib_file.read_to_end(&mut attrib).is_err() {
+                log::warn!("Failed to read the attributes of tileset {}.", data.tileset.name);
+            }
+        }
+        attrib.resize(attrib.len().max(0x100), 0);
+
+        let tiles = vec![0; width as usize * height as usize];
+        let map = Map { width, height, tiles, attrib, tile_size: TileSize::Tile16x16 };
+
+        let metadata = StageMetadata::load(roots, &data.map, ctx);
+
+        Self { map, data: data.clone(), metadata, tile_edits: BTreeMap::new() }
+    }
+
     pub fn load_text_script(
         &self,
         roots: &Vec<String>,
@@ -571,8 +857,8 @@ impl Stage {
         self.change_tile_layer(x, y, tile_type, TileLayer::Foreground)
     }
 
-    /// Changes map tile on any layer. Returns true if smoke should be emitted
-    pub fn change_tile_layer(&mut self, x: usize, y: usize, tile_type: u16, layer: TileLayer) -> bool {
+    /// Returns the index of a tile in `map.tiles`, or None if it's out of bounds.
+    pub fn tile_index(&self, x: usize, y: usize, layer: TileLayer) -> Option<usize> {
         // y * width + x + (layer * width * height)
         // Order in memory
         let layer_offset = self.map.width as usize * self.map.height as usize * match layer{
@@ -582,9 +868,18 @@ impl Stage {
             TileLayer::FarForeground =>{3},
             TileLayer::Snack => {0},
         };
-        if let Some(ptr) = self.map.tiles.get_mut(y.wrapping_mul(self.map.width as usize).wrapping_add(x).wrapping_add(layer_offset)) {
+        let index = y.wrapping_mul(self.map.width as usize).wrapping_add(x).wrapping_add(layer_offset);
+
+        (index < self.map.tiles.len()).then_some(index)
+    }
+
+    /// Changes map tile on any layer. Returns true if smoke should be emitted
+    pub fn change_tile_layer(&mut self, x: usize, y: usize, tile_type: u16, layer: TileLayer) -> bool {
+        if let Some(index) = self.tile_index(x, y, layer) {
+            let ptr = &mut self.map.tiles[index];
             if *ptr != tile_type {
                 *ptr = tile_type;
+                self.tile_edits.insert(index, tile_type);
                 return true;
             }
         }
@@ -650,3 +945,135 @@ impl StageTexturePaths {
         self.npc2 = ["Npc/", &stage.data.npc2.filename()].join("");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framework::vfs::PhysicalFS;
+
+    fn test_stage() -> StageData {
+        StageData::from(StageTableEntry {
+            name: "Mimiga Village".to_owned(),
+            name_jp: String::new(),
+            map: "Mimi".to_owned(),
+            tileset: "Mimi".to_owned(),
+            background: "bkBlue".to_owned(),
+            background_type: 1,
+            boss_no: 0,
+            npc1: "Guest".to_owned(),
+            npc2: "Cemet".to_owned(),
+            background_color: default_background_color(),
+            pxpack_data: None,
+        })
+    }
+
+    #[test]
+    fn test_write_stage_table() {
+        let stages = vec![test_stage(), test_stage()];
+        let sizes = [
+            (StageTableFormat::CSPlus, 2 * 0xe5),
+            (StageTableFormat::Freeware, 2 * 0xc8),
+            (StageTableFormat::MoustacheRider, 4 + 2 * 0x74),
+            (StageTableFormat::NXEngine, 1 + 2 * 0x49),
+        ];
+
+        for (format, size) in sizes {
+            let mut data = Vec::new();
+            StageData::write_stage_table(&stages, format, false, None, &mut data).unwrap();
+            assert_eq!(data.len(), size, "{:?}", format);
+        }
+
+        let mut data = Vec::new();
+        StageData::write_stage_table(&stages, StageTableFormat::Json, false, None, &mut data).unwrap();
+        let entries: Vec<StageTableEntry> = serde_json::from_slice(&data).unwrap();
+        let loaded: Vec<StageData> = entries.into_iter().map(StageData::from).collect();
+        assert_eq!(loaded[1].name_jp, "Mimiga Village");
+        assert_eq!(loaded[1].background_type, BackgroundType::TiledParallax);
+        assert_eq!(loaded[1].npc2.name(), "Cemet");
+
+        let mut stage = test_stage();
+        stage.background_color = Color::from_rgb(16, 32, 48);
+        stage.pxpack_data = Some(PxPackStageData {
+            tileset_fg: "MpFg".to_owned(),
+            tileset_mg: "MpMg".to_owned(),
+            tileset_bg: "MpBg".to_owned(),
+            scroll_fg: PxPackScroll::Normal,
+            scroll_mg: PxPackScroll::Half,
+            scroll_bg: PxPackScroll::Zero,
+            size_fg: (40, 30),
+            size_mg: (20, 15),
+            size_bg: (10, 8),
+            offset_mg: 1200,
+            offset_bg: 1500,
+        });
+
+        let mut data = Vec::new();
+        StageData::write_stage_table(&[stage], StageTableFormat::Json, false, None, &mut data).unwrap();
+        let entries: Vec<StageTableEntry> = serde_json::from_slice(&data).unwrap();
+        let loaded = StageData::from(entries.into_iter().next().unwrap());
+        assert_eq!(StageTableEntry::from(&loaded).background_color, [16, 32, 48]);
+        let pxpack_data = loaded.pxpack_data.unwrap();
+        assert_eq!(pxpack_data.scroll_mg, PxPackScroll::Half);
+        assert_eq!((pxpack_data.size_bg, pxpack_data.offset_bg), ((10, 8), 1500));
+
+        let mut stage = test_stage();
+        stage.tileset = Tileset::new("Custom");
+        assert!(StageData::write_stage_table(&[stage], StageTableFormat::NXEngine, false, None, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_stage_table_roundtrip() {
+        let boss_stage = StageData::from(StageTableEntry {
+            name: "Egg Observation Room".to_owned(),
+            name_jp: String::new(),
+            map: "Egg6".to_owned(),
+            tileset: "EggIn".to_owned(),
+            background: "bk0".to_owned(),
+            background_type: 5,
+            boss_no: 4,
+            npc1: "Eggs1".to_owned(),
+            npc2: "Dark".to_owned(),
+            background_color: default_background_color(),
+            pxpack_data: None,
+        });
+        let stages = vec![test_stage(), boss_stage];
+
+        let formats = [
+            StageTableFormat::Json,
+            StageTableFormat::CSPlus,
+            StageTableFormat::Freeware,
+            StageTableFormat::MoustacheRider,
+            StageTableFormat::NXEngine,
+        ];
+
+        for format in formats {
+            let dir = std::env::temp_dir().join(format!("doukutsu-rs-stage-table-{}-{:?}", std::process::id(), format));
+            std::fs::create_dir_all(&dir).unwrap();
+
+            let mut data = Vec::new();
+            StageData::write_stage_table(&stages, format, false, None, &mut data).unwrap();
+            std::fs::write(dir.join(format.file_name()), data).unwrap();
+
+            let mut ctx = Box::new(Context::new());
+            filesystem::mount_vfs(&mut ctx, Box::new(PhysicalFS::new(&dir, true)));
+            let result = StageData::load_stage_table(&mut ctx, &vec![String::new()], false, None);
+            std::fs::remove_dir_all(&dir).unwrap();
+
+            let (loaded, loaded_format) = result.unwrap();
+            assert_eq!(loaded_format, format);
+            assert_eq!(loaded.len(), stages.len(), "{:?}", format);
+
+            for (loaded, stage) in loaded.iter().zip(stages.iter()) {
+                assert_eq!(loaded.name, stage.name, "{:?}", format);
+                assert_eq!(loaded.name_jp, stage.name_jp, "{:?}", format);
+                assert_eq!(loaded.map, stage.map, "{:?}", format);
+                assert_eq!(loaded.boss_no, stage.boss_no, "{:?}", format);
+                assert_eq!(loaded.tileset.name, stage.tileset.name, "{:?}", format);
+                assert_eq!(loaded.background.name(), stage.background.name(), "{:?}", format);
+                assert_eq!(loaded.background_type, stage.background_type, "{:?}", format);
+                assert_eq!(loaded.npc1.name(), stage.npc1.name(), "{:?}", format);
+                assert_eq!(loaded.npc2.name(), stage.npc2.name(), "{:?}", format);
+            }
+        }
+    }
+}
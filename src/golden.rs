@@ -0,0 +1,325 @@
+//! Golden image tests, which render scenes with the software renderer and compare them against reference PNGs.
+//!
+//! The game data isn't redistributable, so the tests use a fixture data directory pointed to by the
+//! `DOUKUTSU_GOLDEN_DATA` environment variable. It defaults to `res/golden/data`, synthetic data generated by
+//! `res/golden/generate_data.py` together with the built-in data. If it's missing the tests are skipped,
+//! unless `DOUKUTSU_GOLDEN_REQUIRED` is set, in which case missing fixture data or stages make them fail.
+//! Reference images are stored in `res/golden/`, run the tests with `DOUKUTSU_GOLDEN_BLESS=1` to (re)generate them.
+//! On mismatch, the rendered frame and a diff image are written to `target/golden/`.
+//!
+//! Each test renders a single [`GoldenCase`], new ones only need a stage and a camera position.
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use image::{Rgba, RgbaImage};
+
+use crate::common::FadeState;
+use crate::data::builtin_fs::BuiltinFS;
+use crate::framework::context::Context;
+use crate::framework::error::{GameError, GameResult};
+use crate::framework::filesystem;
+use crate::framework::graphics;
+use crate::framework::render_software::SoftwareRenderer;
+use crate::framework::vfs::PhysicalFS;
+use crate::game::shared_game_state::{Season, SharedGameState};
+use crate::game::stage::{BackgroundType, Stage, StageData};
+use crate::graphics::texture_set::{G_MAG, I_MAG};
+use crate::scene::game_scene::GameScene;
+use crate::scene::title_scene::TitleScene;
+use crate::scene::Scene;
+use crate::util::rng::XorShift;
+
+const SCREEN_WIDTH: u16 = 640;
+const SCREEN_HEIGHT: u16 = 480;
+
+/// Maximum difference of a single color channel for the pixels to be considered equal.
+const CHANNEL_TOLERANCE: u8 = 2;
+/// Fraction of pixels which can differ before the comparison fails.
+const MAX_DIFF_RATIO: f64 = 0.001;
+
+struct Harness {
+    ctx: Box<Context>,
+    state: SharedGameState,
+}
+
+impl Harness {
+    /// Boots the game state against the data in `data_dir`, with a software renderer as the backend.
+    fn new(data_dir: &Path) -> GameResult<Harness> {
+        let mut ctx = Box::new(Context::new());
+        filesystem::mount_vfs(&mut ctx, Box::new(PhysicalFS::new(data_dir, true)));
+        filesystem::mount_vfs(&mut ctx, Box::new(BuiltinFS::new()));
+
+        // Keeps the sound manager from opening an audio device.
+        ctx.headless = true;
+        let mut state = SharedGameState::new(&mut ctx)?;
+        ctx.headless = false;
+
+        ctx.screen_size = (SCREEN_WIDTH as f32, SCREEN_HEIGHT as f32);
+        ctx.real_screen_size = (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+        ctx.renderer = Some(Box::new(SoftwareRenderer::new(SCREEN_WIDTH, SCREEN_HEIGHT)));
+
+        // Anything depending on the current date or time would make the output differ between runs.
+        state.season = Season::None;
+        state.settings.seasonal_textures = false;
+        state.handle_resize(&mut ctx)?;
+        state.reload_resources(&mut ctx)?;
+
+        Ok(Harness { ctx, state })
+    }
+
+    /// Initializes the scene, runs it for `ticks` ticks and renders a single frame.
+    fn render(&mut self, scene: &mut dyn Scene, ticks: usize) -> GameResult<RgbaImage> {
+        let (state, ctx) = (&mut self.state, &mut *self.ctx);
+        state.game_rng = XorShift::new(0);
+
+        scene.init(state, ctx)?;
+        for _ in 0..ticks {
+            scene.tick(state, ctx)?;
+        }
+        scene.draw_tick(state)?;
+
+        state.frame_time = 1.0;
+        unsafe {
+            G_MAG = if state.settings.subpixel_coords { state.scale } else { 1.0 };
+            I_MAG = state.scale;
+        }
+
+        graphics::prepare_draw(ctx)?;
+        graphics::clear(ctx, [0.0, 0.0, 0.0, 1.0].into());
+        scene.draw(state, ctx)?;
+        graphics::present(ctx)?;
+
+        let renderer = ctx.renderer.as_ref().ok_or_else(|| GameError::RenderError("No renderer.".to_owned()))?;
+        let renderer = renderer
+            .as_any()
+            .downcast_ref::<SoftwareRenderer>()
+            .ok_or_else(|| GameError::RenderError("Not a software renderer.".to_owned()))?;
+
+        let screen = renderer.screen();
+        let screen = screen.borrow();
+        RgbaImage::from_raw(screen.width() as u32, screen.height() as u32, screen.pixels().to_vec())
+            .ok_or_else(|| GameError::RenderError("Invalid framebuffer size.".to_owned()))
+    }
+
+    /// Creates a game scene for the stage with the camera centered at the given tile, or at the center of the map.
+    fn stage_scene(&mut self, id: usize, pos: Option<(i32, i32)>) -> GameResult<GameScene> {
+        let mut scene = GameScene::new(&mut self.state, &mut self.ctx, id)?;
+        let tile_size = scene.stage.map.tile_size.as_int();
+        let (x, y) = pos.unwrap_or((scene.stage.map.width as i32 / 2, scene.stage.map.height as i32 / 2));
+
+        scene.player1.cond.set_alive(true);
+        scene.player1.x = x * tile_size * 0x200;
+        scene.player1.y = y * tile_size * 0x200;
+
+        self.state.control_flags.set_control_enabled(true);
+        self.state.control_flags.set_tick_world(true);
+        self.state.fade_state = FadeState::Hidden;
+
+        Ok(scene)
+    }
+
+    /// Returns the id of the first stage with an existing map which matches the predicate.
+    fn find_stage(&self, predicate: fn(&Harness, &StageData) -> bool) -> Option<usize> {
+        let roots = &self.state.constants.base_paths;
+        self.state.stages.iter().position(|data| Stage::exists(roots, data, &self.ctx) && predicate(self, data))
+    }
+}
+
+/// The tests share global renderer state, so only one of them can run at a time.
+static HARNESS_LOCK: Mutex<()> = Mutex::new(());
+
+fn is_required() -> bool {
+    std::env::var_os("DOUKUTSU_GOLDEN_REQUIRED").is_some()
+}
+
+/// Skips the test, or fails it if the golden image tests are required to run.
+fn skip(reason: &str) {
+    assert!(!is_required(), "{} (DOUKUTSU_GOLDEN_REQUIRED is set)", reason);
+    eprintln!("Skipping golden image test, {}.", reason);
+}
+
+fn data_dir() -> PathBuf {
+    std::env::var_os("DOUKUTSU_GOLDEN_DATA").map(PathBuf::from).unwrap_or_else(|| golden_dir().join("data"))
+}
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("res/golden")
+}
+
+/// Compares the image against the reference, returning a diff image if they don't match.
+fn compare(actual: &RgbaImage, expected: &RgbaImage) -> Result<(), (String, RgbaImage)> {
+    if actual.dimensions() != expected.dimensions() {
+        let message = format!("size mismatch: {:?} != {:?}", actual.dimensions(), expected.dimensions());
+        return Err((message, actual.clone()));
+    }
+
+    let mut diff = RgbaImage::new(actual.width(), actual.height());
+    let mut diff_pixels = 0usize;
+
+    for ((a, e), d) in actual.pixels().zip(expected.pixels()).zip(diff.pixels_mut()) {
+        let differs = a.0.iter().zip(e.0.iter()).any(|(a, e)| a.abs_diff(*e) > CHANNEL_TOLERANCE);
+
+        *d = if differs {
+            diff_pixels += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            Rgba([e[0] / 4, e[1] / 4, e[2] / 4, 255])
+        };
+    }
+
+    let ratio = diff_pixels as f64 / (actual.width() * actual.height()).max(1) as f64;
+    if ratio > MAX_DIFF_RATIO {
+        return Err((format!("{} pixels differ ({:.3}%)", diff_pixels, ratio * 100.0), diff));
+    }
+
+    Ok(())
+}
+
+/// Checks the image against `res/golden/<name>.png`, or overwrites the reference in bless mode.
+fn check(name: &str, actual: &RgbaImage) -> Result<(), String> {
+    let reference = golden_dir().join(format!("{}.png", name));
+
+    if std::env::var_os("DOUKUTSU_GOLDEN_BLESS").is_some() {
+        let _ = std::fs::create_dir_all(golden_dir());
+        actual.save(&reference).map_err(|err| format!("{}: failed to write {:?}: {}", name, reference, err))?;
+        eprintln!("{}: updated {:?}", name, reference);
+        return Ok(());
+    }
+
+    let expected = match image::open(&reference) {
+        Ok(image) => image.to_rgba8(),
+        Err(err) => return Err(format!("{}: failed to open {:?}: {}", name, reference, err)),
+    };
+
+    compare(actual, &expected).map_err(|(message, diff)| {
+        let out_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/golden");
+        let _ = std::fs::create_dir_all(&out_dir);
+        let _ = actual.save(out_dir.join(format!("{}.actual.png", name)));
+        let _ = diff.save(out_dir.join(format!("{}.diff.png", name)));
+
+        format!("{}: {}, see {:?}", name, message, out_dir)
+    })
+}
+
+/// Stage rendered by a golden image test case.
+enum GoldenStage {
+    /// The title screen instead of a stage.
+    Title,
+    /// The stage a new game starts in.
+    NewGame,
+    Id(usize),
+    /// The first stage in the stage table which matches the predicate.
+    FirstMatching(fn(&Harness, &StageData) -> bool),
+}
+
+struct GoldenCase {
+    /// Name of the reference image in `res/golden/`.
+    name: String,
+    stage: GoldenStage,
+    /// Tile the camera is centered at. Defaults to the player spawn point for [`GoldenStage::NewGame`]
+    /// and to the center of the map otherwise.
+    camera: Option<(i32, i32)>,
+    /// Number of ticks the scene runs for before it's drawn.
+    ticks: usize,
+}
+
+impl GoldenCase {
+    fn run(self) {
+        let _lock = HARNESS_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+
+        let data_dir = data_dir();
+        if !data_dir.is_dir() {
+            return skip(&format!("fixture data not found at {:?}", data_dir));
+        }
+
+        let mut harness = Harness::new(&data_dir).unwrap();
+        let image = match self.stage {
+            GoldenStage::Title => harness.render(&mut TitleScene::new(), self.ticks).unwrap(),
+            stage => {
+                let (id, camera) = match stage {
+                    GoldenStage::NewGame => {
+                        let (x, y) = harness.state.constants.game.new_game_player_pos;
+                        let camera = self.camera.or(Some((x as i32, y as i32)));
+                        (Some(harness.state.constants.game.new_game_stage as usize), camera)
+                    }
+                    GoldenStage::Id(id) => {
+                        let roots = &harness.state.constants.base_paths;
+                        let data = harness.state.stages.get(id);
+                        let exists = data.is_some_and(|data| Stage::exists(roots, data, &harness.ctx));
+                        (Some(id).filter(|_| exists), self.camera)
+                    }
+                    GoldenStage::FirstMatching(predicate) => (harness.find_stage(predicate), self.camera),
+                    GoldenStage::Title => unreachable!(),
+                };
+
+                let Some(id) = id else {
+                    return skip(&format!("no stage matching {} in the fixture data", self.name));
+                };
+
+                let mut scene = harness.stage_scene(id, camera).unwrap();
+                harness.render(&mut scene, self.ticks).unwrap()
+            }
+        };
+
+        if let Err(message) = check(&self.name, &image) {
+            panic!("Golden image mismatch: {}", message);
+        }
+    }
+}
+
+#[test]
+fn golden_title() {
+    GoldenCase { name: "title".to_owned(), stage: GoldenStage::Title, camera: None, ticks: 1 }.run();
+}
+
+/// Tile layers and the HUD, at the spawn point of a new game.
+#[test]
+fn golden_new_game() {
+    GoldenCase { name: "new_game".to_owned(), stage: GoldenStage::NewGame, camera: None, ticks: 0 }.run();
+}
+
+#[test]
+fn golden_water() {
+    let stage = GoldenStage::FirstMatching(|_, data| data.background_type == BackgroundType::Water);
+    GoldenCase { name: "water".to_owned(), stage, camera: None, ticks: 0 }.run();
+}
+
+#[test]
+fn golden_pxpack() {
+    let stage = GoldenStage::FirstMatching(|harness, data| {
+        let path = ["Stage/", &data.map, ".pxpack"].join("");
+        filesystem::exists_find(&harness.ctx, &harness.state.constants.base_paths, path)
+    });
+    GoldenCase { name: "pxpack".to_owned(), stage, camera: None, ticks: 0 }.run();
+}
+
+/// Renders the stage given by `DOUKUTSU_GOLDEN_STAGE`, as `<id>` or `<id>:<x>,<y>` with the camera position in tiles,
+/// against `res/golden/stage_<id>[_<x>_<y>].png`. Does nothing if the variable isn't set.
+#[test]
+fn golden_stage_from_env() {
+    let Ok(spec) = std::env::var("DOUKUTSU_GOLDEN_STAGE") else {
+        return;
+    };
+
+    let parse = || -> Option<(usize, Option<(i32, i32)>)> {
+        let (id, camera) = match spec.split_once(':') {
+            Some((id, camera)) => (id, Some(camera.split_once(',')?)),
+            None => (spec.as_str(), None),
+        };
+        let camera = match camera {
+            Some((x, y)) => Some((x.trim().parse().ok()?, y.trim().parse().ok()?)),
+            None => None,
+        };
+
+        Some((id.trim().parse().ok()?, camera))
+    };
+
+    let (id, camera) = parse().unwrap_or_else(|| panic!("Invalid DOUKUTSU_GOLDEN_STAGE value: {:?}", spec));
+    let name = match camera {
+        Some((x, y)) => format!("stage_{}_{}_{}", id, x, y),
+        None => format!("stage_{}", id),
+    };
+
+    GoldenCase { name, stage: GoldenStage::Id(id), camera, ticks: 0 }.run();
+}
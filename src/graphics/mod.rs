@@ -1,3 +1,4 @@
 pub mod bmfont;
 pub mod font;
+pub mod post_process;
 pub mod texture_set;
@@ -0,0 +1,54 @@
+//! Post-processing shaders applied to the game canvas, loaded from `shaders/<name>.glsl` in the data directories.
+//!
+//! Shaders are fragment shader bodies, see [`PostProcessPass`] for the inputs provided by the renderer.
+//! A shader containing a `#pragma upscale` line runs at the screen resolution, otherwise it runs at the native
+//! resolution of the canvas.
+
+use std::io::Read;
+
+use crate::framework::backend::PostProcessPass;
+use crate::framework::context::Context;
+use crate::framework::error::GameResult;
+use crate::framework::filesystem;
+
+pub const SHADER_DIR: &str = "shaders/";
+const UPSCALE_PRAGMA: &str = "#pragma upscale";
+
+/// Returns the names of the shaders available in the data directories, sorted alphabetically.
+pub fn list_shaders(ctx: &Context, roots: &Vec<String>) -> Vec<String> {
+    let mut names: Vec<String> = match filesystem::read_dir_find(ctx, roots, SHADER_DIR) {
+        Ok(files) => files
+            .filter(|path| path.extension().map_or(false, |ext| ext == "glsl"))
+            .filter_map(|path| path.file_stem().map(|stem| stem.to_string_lossy().to_string()))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+
+    names.sort();
+    names.dedup();
+    names
+}
+
+pub fn load_pass(ctx: &Context, roots: &Vec<String>, name: &str) -> GameResult<PostProcessPass> {
+    let mut file = filesystem::open_find(ctx, roots, [SHADER_DIR, name, ".glsl"].join(""))?;
+    let mut source = String::new();
+    file.read_to_string(&mut source)?;
+
+    let upscale = source.lines().any(|line| line.trim() == UPSCALE_PRAGMA);
+
+    Ok(PostProcessPass { name: name.to_owned(), source, upscale })
+}
+
+/// Loads the shaders with given names in order, skipping the ones which can't be found.
+pub fn load_passes(ctx: &Context, roots: &Vec<String>, names: &[String]) -> Vec<PostProcessPass> {
+    names
+        .iter()
+        .filter_map(|name| match load_pass(ctx, roots, name) {
+            Ok(pass) => Some(pass),
+            Err(err) => {
+                log::warn!("Failed to load post-processing shader {}: {}", name, err);
+                None
+            }
+        })
+        .collect()
+}
@@ -17,6 +17,8 @@ mod engine_constants;
 mod entity;
 mod framework;
 pub mod game;
+#[cfg(test)]
+mod golden;
 mod graphics;
 mod i18n;
 mod input;
@@ -24,6 +26,7 @@ mod live_debugger;
 mod macros;
 mod menu;
 mod mod_list;
+mod mod_manifest;
 mod mod_requirements;
 mod scene;
 mod sound;
@@ -3,24 +3,58 @@
 use std::process::exit;
 
 fn main() {
-    let args = std::env::args();
-    let mut options = doukutsu_rs::game::LaunchOptions { server_mode: false, editor: false };
+    let mut args = std::env::args().skip(1);
+    let mut tsc_options = doukutsu_rs::game::scripting::tsc::cli::TSCToolOptions::default();
+    let mut options =
+        doukutsu_rs::game::LaunchOptions { server_mode: false, editor: false, server_options: Default::default() };
 
-    for arg in args {
+    let mut unknown_args = Vec::new();
+
+    while let Some(arg) = args.next() {
         if arg == "--server-mode" {
             options.server_mode = true;
+            continue;
         }
 
         if arg == "--editor" {
             options.editor = true;
+            continue;
+        }
+
+        match tsc_options.parse_arg(&arg, &mut args) {
+            Ok(true) => continue,
+            Ok(false) => {}
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        }
+
+        match options.server_options.parse_arg(&arg, &mut args) {
+            Ok(true) => {}
+            Ok(false) => unknown_args.push(arg),
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1);
+            }
         }
     }
 
+    if tsc_options.is_set() {
+        exit(if tsc_options.run() { 1 } else { 0 });
+    }
+
     if options.server_mode && options.editor {
         eprintln!("Cannot run in server mode and editor mode at the same time.");
         exit(1);
     }
 
+    // the game itself ignores unknown arguments, but a typo in a runner option shouldn't go unnoticed
+    if options.server_mode && !unknown_args.is_empty() {
+        eprintln!("Unknown arguments: {}", unknown_args.join(" "));
+        exit(1);
+    }
+
     let result = doukutsu_rs::game::init(options);
 
     #[cfg(target_os = "windows")]
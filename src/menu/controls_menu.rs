@@ -11,7 +11,7 @@ use crate::input::combined_menu_controller::CombinedMenuController;
 
 use super::{ControlMenuData, Menu, MenuEntry, MenuSelectionResult};
 
-const FORBIDDEN_SCANCODES: [ScanCode; 12] = [
+const FORBIDDEN_SCANCODES: [ScanCode; 14] = [
     ScanCode::F1,
     ScanCode::F2,
     ScanCode::F3,
@@ -24,6 +24,8 @@ const FORBIDDEN_SCANCODES: [ScanCode; 12] = [
     ScanCode::F10,
     ScanCode::F11,
     ScanCode::F12,
+    ScanCode::Snapshot,
+    ScanCode::Sysrq,
 ];
 
 #[derive(PartialEq, Eq, Clone, Debug)]
@@ -2,7 +2,7 @@ use crate::framework::context::Context;
 use crate::framework::error::GameResult;
 use crate::framework::graphics;
 use crate::framework::keyboard::ScanCode;
-use crate::game::shared_game_state::{MenuCharacter, PlayerCount, SharedGameState};
+use crate::game::shared_game_state::{MenuCharacter, PlayerCount, ReplayKind, ReplayState, SharedGameState};
 use crate::input::combined_menu_controller::CombinedMenuController;
 use crate::menu::MenuEntry;
 use crate::menu::{Menu, MenuSelectionResult};
@@ -27,6 +27,7 @@ enum PauseMenuEntry {
     Retry,
     AddPlayer2,
     DropPlayer2,
+    Replay,
     Settings,
     Title,
     Quit,
@@ -61,6 +62,8 @@ pub struct PauseMenu {
     confirm_menu: Menu<ConfirmMenuEntry>,
     tick: u32,
     should_update_coop_menu: bool,
+    /// Kind of the replay recorded by the game scene, only session recordings can be stopped from the menu.
+    pub replay_kind: ReplayKind,
 }
 
 impl PauseMenu {
@@ -77,6 +80,7 @@ impl PauseMenu {
             confirm_menu: Menu::new(0, 0, 75, 0),
             tick: 0,
             should_update_coop_menu: false,
+            replay_kind: ReplayKind::Session,
         }
     }
 
@@ -90,6 +94,7 @@ impl PauseMenu {
             .push_entry(PauseMenuEntry::Retry, MenuEntry::Active(state.loc.t("menus.pause_menu.retry").to_owned()));
         self.pause_menu.push_entry(PauseMenuEntry::AddPlayer2, MenuEntry::Hidden);
         self.pause_menu.push_entry(PauseMenuEntry::DropPlayer2, MenuEntry::Hidden);
+        self.pause_menu.push_entry(PauseMenuEntry::Replay, MenuEntry::Hidden);
         self.pause_menu.push_entry(
             PauseMenuEntry::Settings,
             MenuEntry::Active(state.loc.t("menus.pause_menu.options").to_owned()),
@@ -116,6 +121,7 @@ impl PauseMenu {
         state.menu_character = MenuCharacter::Quote;
 
         self.update_coop_menu_items(state);
+        self.update_replay_menu_item(state);
 
         Ok(())
     }
@@ -137,6 +143,13 @@ impl PauseMenu {
             return;
         }
 
+        // changing the player count would break the recorded key streams
+        if state.replay_state != ReplayState::None {
+            self.pause_menu.set_entry(PauseMenuEntry::AddPlayer2, MenuEntry::Hidden);
+            self.pause_menu.set_entry(PauseMenuEntry::DropPlayer2, MenuEntry::Hidden);
+            return;
+        }
+
         match state.player_count {
             PlayerCount::One => {
                 self.pause_menu.set_entry(
@@ -163,6 +176,18 @@ impl PauseMenu {
         }
     }
 
+    fn update_replay_menu_item(&mut self, state: &SharedGameState) {
+        let entry = match state.replay_state {
+            ReplayState::None => MenuEntry::Active(state.loc.t("menus.pause_menu.record_replay").to_owned()),
+            ReplayState::Recording if self.replay_kind == ReplayKind::Session => {
+                MenuEntry::Active(state.loc.t("menus.pause_menu.stop_recording").to_owned())
+            }
+            ReplayState::Recording | ReplayState::Playback(_) => MenuEntry::Hidden,
+        };
+
+        self.pause_menu.set_entry(PauseMenuEntry::Replay, entry);
+    }
+
     pub fn pause(&mut self, state: &mut SharedGameState) {
         self.is_paused = true;
         state.sound_manager.play_sfx(5);
@@ -187,6 +212,7 @@ impl PauseMenu {
 
         if self.should_update_coop_menu {
             self.update_coop_menu_items(state);
+            self.update_replay_menu_item(state);
             self.should_update_coop_menu = false;
         }
 
@@ -218,6 +244,12 @@ impl PauseMenu {
                     state.player_count_modified_in_game = true;
                     self.should_update_coop_menu = true;
                 }
+                MenuSelectionResult::Selected(PauseMenuEntry::Replay, _) => {
+                    state.replay_recording_toggled = true;
+                    self.tick = 0;
+                    self.is_paused = false;
+                    self.should_update_coop_menu = true;
+                }
                 MenuSelectionResult::Selected(PauseMenuEntry::Settings, _) => {
                     self.current_menu = CurrentMenu::SettingsMenu;
                 }
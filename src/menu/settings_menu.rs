@@ -6,6 +6,7 @@ use crate::framework::graphics::VSyncMode;
 use crate::framework::{filesystem, graphics};
 use crate::game::shared_game_state::{CutsceneSkipMode, ScreenShakeIntensity, SharedGameState, TimingMode, WindowMode};
 use crate::graphics::font::Font;
+use crate::graphics::post_process;
 use crate::input::combined_menu_controller::CombinedMenuController;
 use crate::menu::MenuEntry;
 use crate::menu::{Menu, MenuSelectionResult};
@@ -27,6 +28,8 @@ enum CurrentMenu {
     LanguageMenu,
     BehaviorMenu,
     LinksMenu,
+    ModsMenu,
+    PostProcessMenu,
     AdvancedMenu,
     PortableMenu,
 }
@@ -39,6 +42,7 @@ enum MainMenuEntry {
     Language,
     Behavior,
     Links,
+    Mods,
     Advanced,
     Back,
 }
@@ -60,6 +64,7 @@ enum GraphicsMenuEntry {
     SubpixelScrolling,
     OriginalTextures,
     SeasonalTextures,
+    PostProcess,
     Renderer,
     Back,
 }
@@ -140,6 +145,35 @@ impl Default for LinksMenuEntry {
     }
 }
 
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum ModsMenuEntry {
+    Title,
+    Mod(usize),
+    Error(usize),
+    /// Why the last selected mod couldn't be enabled.
+    Status,
+    Back,
+}
+
+impl Default for ModsMenuEntry {
+    fn default() -> Self {
+        ModsMenuEntry::Back
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum PostProcessMenuEntry {
+    Title,
+    Shader(usize),
+    Back,
+}
+
+impl Default for PostProcessMenuEntry {
+    fn default() -> Self {
+        PostProcessMenuEntry::Back
+    }
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 enum AdvancedMenuEntry {
     Title,
@@ -181,9 +215,14 @@ pub struct SettingsMenu {
     language: Menu<LanguageMenuEntry>,
     behavior: Menu<BehaviorMenuEntry>,
     links: Menu<LinksMenuEntry>,
+    mods: Menu<ModsMenuEntry>,
+    post_process: Menu<PostProcessMenuEntry>,
+    /// Names of the shaders listed in the post-processing menu.
+    shaders: Vec<String>,
     advanced: Menu<AdvancedMenuEntry>,
     portable: Menu<PortableMenuEntry>,
     controls_menu: ControlsMenu,
+    mods_changed: bool,
     pub on_title: bool,
 }
 
@@ -204,6 +243,8 @@ impl SettingsMenu {
         let language = Menu::new(0, 0, 120, 0);
         let behavior = Menu::new(0, 0, 220, 0);
         let links = Menu::new(0, 0, 220, 0);
+        let mods = Menu::new(0, 0, 220, 0);
+        let post_process = Menu::new(0, 0, 180, 0);
         let advanced = Menu::new(0, 0, 220, 0);
         let portable = Menu::new(0, 0, 220, 0);
 
@@ -218,9 +259,13 @@ impl SettingsMenu {
             language,
             behavior,
             links,
+            mods,
+            post_process,
+            shaders: Vec::new(),
             advanced,
             controls_menu,
             portable,
+            mods_changed: false,
             on_title: false,
         }
     }
@@ -328,6 +373,18 @@ impl SettingsMenu {
             );
         }
 
+        if graphics::supports_post_process(ctx)? {
+            self.graphics.push_entry(
+                GraphicsMenuEntry::PostProcess,
+                MenuEntry::Active(state.loc.t("menus.options_menu.graphics_menu.post_process").to_owned()),
+            );
+        } else {
+            self.graphics.push_entry(
+                GraphicsMenuEntry::PostProcess,
+                MenuEntry::Disabled(state.loc.t("menus.options_menu.graphics_menu.post_process").to_owned()),
+            );
+        }
+
         self.graphics.push_entry(
             GraphicsMenuEntry::Renderer,
             MenuEntry::Disabled(format!(
@@ -392,6 +449,50 @@ impl SettingsMenu {
         );
         self.links.push_entry(LinksMenuEntry::Link(GETPLUS_LINK), MenuEntry::Active("Get Cave Story+".to_owned()));
 
+        // Switching the mods reloads all resources, which can only be done safely on the title screen.
+        if self.on_title && !state.mod_list.mods.is_empty() {
+            self.main
+                .push_entry(MainMenuEntry::Mods, MenuEntry::Active(state.loc.t("menus.options_menu.mods").to_owned()));
+        }
+
+        self.mods
+            .push_entry(ModsMenuEntry::Title, MenuEntry::Disabled(state.loc.t("menus.options_menu.mods").to_owned()));
+
+        for (idx, mod_info) in state.mod_list.mods.iter().enumerate() {
+            if mod_info.valid && !mod_info.satisfies_requirement(&state.mod_requirements) {
+                self.mods.push_entry(ModsMenuEntry::Mod(idx), MenuEntry::Disabled("???".to_owned()));
+            } else if mod_info.valid {
+                let enabled = state.settings.active_mods.contains(&mod_info.path);
+                self.mods.push_entry(ModsMenuEntry::Mod(idx), MenuEntry::Toggle(mod_info.name.clone(), enabled));
+            } else {
+                self.mods.push_entry(ModsMenuEntry::Mod(idx), MenuEntry::Disabled(mod_info.path.clone()));
+                if let Some(error) = &mod_info.error {
+                    self.mods.push_entry(ModsMenuEntry::Error(idx), MenuEntry::LongText(error.clone(), false, false));
+                }
+            }
+        }
+
+        self.mods.push_entry(ModsMenuEntry::Status, MenuEntry::Hidden);
+        self.mods.push_entry(ModsMenuEntry::Back, MenuEntry::Active(state.loc.t("common.back").to_owned()));
+
+        self.post_process.push_entry(
+            PostProcessMenuEntry::Title,
+            MenuEntry::Disabled(state.loc.t("menus.options_menu.graphics_menu.post_process").to_owned()),
+        );
+
+        // Shaders shipped by mods don't have a translated name, so the file name is shown instead.
+        self.shaders = post_process::list_shaders(ctx, &state.constants.base_paths);
+        for (idx, name) in self.shaders.iter().enumerate() {
+            let key = format!("menus.options_menu.graphics_menu.post_process_shaders.{}", name);
+            let label = if state.loc.t(&key) == key { name.clone() } else { state.loc.t(&key).to_owned() };
+            let enabled = state.settings.post_process.contains(name);
+
+            self.post_process.push_entry(PostProcessMenuEntry::Shader(idx), MenuEntry::Toggle(label, enabled));
+        }
+
+        self.post_process
+            .push_entry(PostProcessMenuEntry::Back, MenuEntry::Active(state.loc.t("common.back").to_owned()));
+
         #[cfg(not(any(target_os = "horizon")))]
         self.main.push_entry(
             MainMenuEntry::Advanced,
@@ -640,6 +741,16 @@ impl SettingsMenu {
         self.links.x = ((state.canvas_size.0 - self.links.width as f32) / 2.0).floor() as isize;
         self.links.y = 30 + ((state.canvas_size.1 - self.links.height as f32) / 2.0).floor() as isize;
 
+        self.mods.update_width(state);
+        self.mods.update_height(state);
+        self.mods.x = ((state.canvas_size.0 - self.mods.width as f32) / 2.0).floor() as isize;
+        self.mods.y = 30 + ((state.canvas_size.1 - self.mods.height as f32) / 2.0).floor() as isize;
+
+        self.post_process.update_width(state);
+        self.post_process.update_height(state);
+        self.post_process.x = ((state.canvas_size.0 - self.post_process.width as f32) / 2.0).floor() as isize;
+        self.post_process.y = 30 + ((state.canvas_size.1 - self.post_process.height as f32) / 2.0).floor() as isize;
+
         self.advanced.update_width(state);
         self.advanced.update_height(state);
         self.advanced.x = ((state.canvas_size.0 - self.advanced.width as f32) / 2.0).floor() as isize;
@@ -680,6 +791,9 @@ impl SettingsMenu {
                 MenuSelectionResult::Selected(MainMenuEntry::Links, _) => {
                     self.current = CurrentMenu::LinksMenu;
                 }
+                MenuSelectionResult::Selected(MainMenuEntry::Mods, _) => {
+                    self.current = CurrentMenu::ModsMenu;
+                }
                 MenuSelectionResult::Selected(MainMenuEntry::Advanced, _) => {
                     self.current = CurrentMenu::AdvancedMenu;
                 }
@@ -821,6 +935,9 @@ impl SettingsMenu {
                         *value = state.settings.seasonal_textures;
                     }
                 }
+                MenuSelectionResult::Selected(GraphicsMenuEntry::PostProcess, _) => {
+                    self.current = CurrentMenu::PostProcessMenu;
+                }
                 MenuSelectionResult::Selected(GraphicsMenuEntry::Back, _) | MenuSelectionResult::Canceled => {
                     self.current = CurrentMenu::MainMenu
                 }
@@ -1069,6 +1186,71 @@ impl SettingsMenu {
                 }
                 _ => (),
             },
+            CurrentMenu::ModsMenu => match self.mods.tick(controller, state) {
+                MenuSelectionResult::Selected(ModsMenuEntry::Mod(idx), toggle) => {
+                    if let (MenuEntry::Toggle(_, value), Some(mod_info)) = (toggle, state.mod_list.mods.get(idx)) {
+                        // Refuses to enable mods which conflict with the enabled ones or their dependencies.
+                        if !state.settings.active_mods.contains(&mod_info.path) {
+                            let mut mod_paths = vec![mod_info.path.clone()];
+                            mod_paths.extend(state.mod_paths());
+
+                            if let Err(err) = state.mod_list.resolve_paths(&mod_paths) {
+                                self.mods.set_entry(ModsMenuEntry::Status, MenuEntry::LongText(err, false, false));
+                                return Ok(());
+                            }
+                        }
+
+                        // Mods enabled later are placed on top of the already enabled ones.
+                        let active_mods = &mut state.settings.active_mods;
+                        if let Some(pos) = active_mods.iter().position(|path| path == &mod_info.path) {
+                            active_mods.remove(pos);
+                        } else {
+                            active_mods.push(mod_info.path.clone());
+                        }
+
+                        *value = active_mods.contains(&mod_info.path);
+                        self.mods.set_entry(ModsMenuEntry::Status, MenuEntry::Hidden);
+                        self.mods_changed = true;
+                        let _ = state.settings.save(ctx);
+                    }
+                }
+                MenuSelectionResult::Selected(ModsMenuEntry::Back, _) | MenuSelectionResult::Canceled => {
+                    self.mods.set_entry(ModsMenuEntry::Status, MenuEntry::Hidden);
+
+                    if self.mods_changed {
+                        self.mods_changed = false;
+                        state.reload_resources(ctx)?;
+
+                        let mut new_menu = TitleScene::new();
+                        new_menu.open_settings_menu()?;
+                        state.next_scene = Some(Box::new(new_menu));
+                    }
+
+                    self.current = CurrentMenu::MainMenu;
+                }
+                _ => (),
+            },
+            CurrentMenu::PostProcessMenu => match self.post_process.tick(controller, state) {
+                MenuSelectionResult::Selected(PostProcessMenuEntry::Shader(idx), toggle) => {
+                    if let (MenuEntry::Toggle(_, value), Some(name)) = (toggle, self.shaders.get(idx)) {
+                        // Shaders are applied in the order they were enabled in.
+                        let post_process = &mut state.settings.post_process;
+                        if let Some(pos) = post_process.iter().position(|shader| shader == name) {
+                            post_process.remove(pos);
+                        } else {
+                            post_process.push(name.clone());
+                        }
+
+                        *value = post_process.contains(name);
+                        state.apply_post_process(ctx);
+                        let _ = state.settings.save(ctx);
+                    }
+                }
+                MenuSelectionResult::Selected(PostProcessMenuEntry::Back, _) | MenuSelectionResult::Canceled => {
+                    self.current = CurrentMenu::GraphicsMenu;
+                }
+                _ => (),
+            },
             CurrentMenu::AdvancedMenu => match self.advanced.tick(controller, state) {
                 MenuSelectionResult::Selected(AdvancedMenuEntry::OpenUserData, _) => {
                     if let Some(fs_container) = &state.fs_container {
@@ -1116,6 +1298,8 @@ impl SettingsMenu {
             CurrentMenu::LanguageMenu => self.language.draw(state, ctx)?,
             CurrentMenu::BehaviorMenu => self.behavior.draw(state, ctx)?,
             CurrentMenu::LinksMenu => self.links.draw(state, ctx)?,
+            CurrentMenu::ModsMenu => self.mods.draw(state, ctx)?,
+            CurrentMenu::PostProcessMenu => self.post_process.draw(state, ctx)?,
             CurrentMenu::AdvancedMenu => self.advanced.draw(state, ctx)?,
             CurrentMenu::PortableMenu => self.portable.draw(state, ctx)?,
         }
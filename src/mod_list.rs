@@ -6,6 +6,7 @@ use std::str::Chars;
 use crate::framework::context::Context;
 use crate::framework::error::GameResult;
 use crate::framework::filesystem;
+use crate::mod_manifest::{ModManifest, Version, ENGINE_VERSION};
 use crate::mod_requirements::ModRequirements;
 
 #[derive(Debug)]
@@ -18,9 +19,18 @@ pub struct ModInfo {
     pub name: String,
     pub description: String,
     pub valid: bool,
+    /// Contents of mod.json, if the mod has one.
+    pub manifest: Option<ModManifest>,
+    /// Why the mod can't be loaded, if it's not valid.
+    pub error: Option<String>,
 }
 
 impl ModInfo {
+    /// Name used to refer to the mod in error messages.
+    fn label(&self) -> &str {
+        self.manifest.as_ref().map_or(&self.name, |manifest| &manifest.id)
+    }
+
     pub fn satisfies_requirement(&self, mod_requirements: &ModRequirements) -> bool {
         match self.requirement {
             Requirement::Unlocked => true,
@@ -149,6 +159,19 @@ impl ModList {
                     }
                 }
 
+                // Mods can also be shipped as a single zip archive, eg. `/mods/example.zip`.
+                let archive = path.trim_end_matches('/');
+                if archive.to_lowercase().ends_with(".zip") {
+                    let archive = archive.to_owned();
+                    path = [&archive, "/"].join("");
+
+                    if !filesystem::is_dir(ctx, &path) {
+                        if let Err(err) = filesystem::mount_zip(ctx, &archive, &path) {
+                            log::warn!("Failed to mount mod archive {}: {}", archive, err);
+                        }
+                    }
+                }
+
                 let mut valid = false;
                 let mut name = String::new();
                 let mut description = String::new();
@@ -173,13 +196,200 @@ impl ModList {
                     description = "mod.txt not found".to_string();
                 }
 
-                mods.push(ModInfo { id, requirement, priority, save_slot, path, name, description, valid })
+                let mut manifest = None;
+                let mut error = None;
+
+                if let Ok(file) = filesystem::open(ctx, [&path, "/mod.json"].join("")) {
+                    match serde_json::from_reader::<_, ModManifest>(file) {
+                        Ok(mod_manifest) => {
+                            valid = true;
+                            name = mod_manifest.name.clone().unwrap_or_else(|| mod_manifest.id.clone());
+                            description = mod_manifest.description.clone();
+                            save_slot = mod_manifest.save_slot.unwrap_or(save_slot);
+                            manifest = Some(mod_manifest);
+                        }
+                        Err(err) => {
+                            valid = false;
+                            error = Some(format!("Invalid mod.json: {}", err));
+                        }
+                    }
+                }
+
+                mods.push(ModInfo {
+                    id,
+                    requirement,
+                    priority,
+                    save_slot,
+                    path,
+                    name,
+                    description,
+                    valid,
+                    manifest,
+                    error,
+                })
             }
         }
 
         mods.sort_by(|a, b| a.priority.cmp(&b.priority));
 
-        Ok(ModList { mods })
+        let mut mod_list = ModList { mods };
+        mod_list.invalidate_unresolvable();
+
+        for mod_info in mod_list.mods.iter().filter(|mod_info| mod_info.error.is_some()) {
+            log::warn!("Mod {} can't be loaded: {}", mod_info.path, mod_info.error.as_deref().unwrap_or(""));
+        }
+
+        Ok(mod_list)
+    }
+
+    /// Marks mods whose dependencies can't be resolved as invalid. Repeated until nothing changes, since a mod
+    /// becoming invalid also breaks the mods depending on it.
+    fn invalidate_unresolvable(&mut self) {
+        loop {
+            let mut changed = false;
+
+            for idx in 0..self.mods.len() {
+                if !self.mods[idx].valid {
+                    continue;
+                }
+
+                if let Err(err) = self.resolve(&[idx]) {
+                    let mod_info = &mut self.mods[idx];
+                    mod_info.valid = false;
+                    mod_info.error = Some(err);
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    /// Returns the indices of the mods and all of their dependencies in load order, from the highest priority one,
+    /// or an error if the set of mods can't be loaded together. `indices` are also ordered from the highest priority.
+    pub fn resolve(&self, indices: &[usize]) -> Result<Vec<usize>, String> {
+        let mut order = Vec::new();
+        for &idx in indices.iter().rev() {
+            self.visit(idx, &mut order, &mut Vec::new())?;
+        }
+
+        for &a in &order {
+            let Some(manifest) = &self.mods[a].manifest else {
+                continue;
+            };
+
+            for &b in &order {
+                if self.mods[b].manifest.as_ref().map_or(false, |other| manifest.conflicts.contains(&other.id)) {
+                    return Err(format!("{} conflicts with {}.", self.mods[a].label(), self.mods[b].label()));
+                }
+            }
+        }
+
+        order.reverse();
+        Ok(order)
+    }
+
+    /// Adds the dependencies of a mod to `order` depth-first, followed by the mod itself.
+    fn visit(&self, idx: usize, order: &mut Vec<usize>, stack: &mut Vec<usize>) -> Result<(), String> {
+        if order.contains(&idx) {
+            return Ok(());
+        }
+
+        let mod_info = &self.mods[idx];
+        if stack.contains(&idx) {
+            let cycle: Vec<&str> = stack.iter().chain([&idx]).map(|&i| self.mods[i].label()).collect();
+            return Err(format!("Circular dependency: {}.", cycle.join(" -> ")));
+        }
+
+        let Some(manifest) = &mod_info.manifest else {
+            order.push(idx);
+            return Ok(());
+        };
+
+        if let Some(min_version) = manifest.min_engine_version {
+            let engine_version: Version = ENGINE_VERSION.parse()?;
+            if engine_version < min_version {
+                return Err(format!(
+                    "{} requires engine version {} or newer, this is {}.",
+                    mod_info.label(),
+                    min_version,
+                    engine_version
+                ));
+            }
+        }
+
+        stack.push(idx);
+
+        for dependency in &manifest.dependencies {
+            let candidates: Vec<(usize, Version)> = self
+                .mods
+                .iter()
+                .enumerate()
+                .filter_map(|(i, m)| m.manifest.as_ref().filter(|m| m.id == dependency.id).map(|m| (i, m.version)))
+                .collect();
+
+            if candidates.is_empty() {
+                return Err(format!(
+                    "{} requires {} {}, which is not installed.",
+                    mod_info.label(),
+                    dependency.id,
+                    dependency.version
+                ));
+            }
+
+            let candidates: Vec<(usize, Version)> =
+                candidates.into_iter().filter(|&(i, _)| self.mods[i].valid).collect();
+
+            if candidates.is_empty() {
+                return Err(format!(
+                    "{} requires {} {}, which can't be loaded.",
+                    mod_info.label(),
+                    dependency.id,
+                    dependency.version
+                ));
+            }
+
+            let Some(&(dep_idx, _)) = candidates
+                .iter()
+                .filter(|(_, version)| dependency.version.matches(version))
+                .max_by_key(|(_, version)| *version)
+            else {
+                let installed: Vec<String> = candidates.iter().map(|(_, version)| version.to_string()).collect();
+                return Err(format!(
+                    "{} requires {} {}, but the installed version is {}.",
+                    mod_info.label(),
+                    dependency.id,
+                    dependency.version,
+                    installed.join(", ")
+                ));
+            };
+
+            self.visit(dep_idx, order, stack)?;
+        }
+
+        stack.pop();
+        order.push(idx);
+
+        Ok(())
+    }
+
+    /// Returns the data directories of the mods at `mod_paths` and their dependencies, from the highest priority one.
+    /// Paths which aren't in the mod list are kept as they are, with the highest priority.
+    pub fn resolve_paths(&self, mod_paths: &[String]) -> Result<Vec<String>, String> {
+        let mut indices = Vec::new();
+        let mut paths = Vec::new();
+
+        for mod_path in mod_paths {
+            match self.mods.iter().position(|mod_info| &mod_info.path == mod_path) {
+                Some(idx) => indices.push(idx),
+                None => paths.push(mod_path.clone()),
+            }
+        }
+
+        paths.extend(self.resolve(&indices)?.into_iter().map(|idx| self.mods[idx].path.clone()));
+        Ok(paths)
     }
 
     pub fn get_save_from_path(&self, mod_path: String) -> i32 {
@@ -198,3 +408,61 @@ impl ModList {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mod_info(path: &str, manifest: &str) -> ModInfo {
+        ModInfo {
+            id: path.to_owned(),
+            requirement: Requirement::Unlocked,
+            priority: 0,
+            save_slot: -1,
+            path: path.to_owned(),
+            name: path.to_owned(),
+            description: String::new(),
+            valid: true,
+            manifest: Some(serde_json::from_str(manifest).unwrap()),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_invalid_dependency_chain() {
+        let mut mod_list = ModList {
+            mods: vec![
+                mod_info("/a/", r#"{"id": "a", "version": "1.0.0", "dependencies": [{"id": "b", "version": "^1.0"}]}"#),
+                mod_info("/b/", r#"{"id": "b", "version": "1.0.0", "dependencies": [{"id": "c", "version": "^1.0"}]}"#),
+                mod_info("/c/", r#"{"id": "c", "version": "1.0.0", "min_engine_version": "999.0.0"}"#),
+                mod_info("/d/", r#"{"id": "d", "version": "1.0.0"}"#),
+            ],
+        };
+
+        mod_list.invalidate_unresolvable();
+
+        assert!(!mod_list.mods[0].valid);
+        assert!(!mod_list.mods[1].valid);
+        assert!(!mod_list.mods[2].valid);
+        assert!(mod_list.mods[3].valid);
+        assert!(mod_list.resolve(&[0]).is_err());
+        assert_eq!(mod_list.resolve(&[3]), Ok(vec![3]));
+    }
+
+    #[test]
+    fn test_skip_invalid_candidate() {
+        let mut mod_list = ModList {
+            mods: vec![
+                mod_info("/a/", r#"{"id": "a", "version": "1.0.0", "dependencies": [{"id": "b", "version": "^1.0"}]}"#),
+                mod_info("/b1/", r#"{"id": "b", "version": "1.2.0", "min_engine_version": "999.0.0"}"#),
+                mod_info("/b2/", r#"{"id": "b", "version": "1.1.0"}"#),
+            ],
+        };
+
+        mod_list.invalidate_unresolvable();
+
+        assert!(mod_list.mods[0].valid);
+        assert!(!mod_list.mods[1].valid);
+        assert_eq!(mod_list.resolve(&[0]), Ok(vec![0, 2]));
+    }
+}
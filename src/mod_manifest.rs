@@ -0,0 +1,249 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+/// Version of the engine, checked against `ModManifest::min_engine_version`.
+pub const ENGINE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// A `major.minor.patch` version number, missing components are treated as zero.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl Version {
+    pub fn new(major: u32, minor: u32, patch: u32) -> Version {
+        Version { major, minor, patch }
+    }
+
+    /// Parses a version which may have its minor and patch numbers omitted, returning how many components it had.
+    fn parse_partial(s: &str) -> Result<(Version, usize), String> {
+        // Pre-release and build metadata are ignored.
+        let s = s.trim().split(['-', '+']).next().unwrap_or("");
+        let mut parts = [0u32; 3];
+        let mut count = 0;
+
+        for part in s.split('.') {
+            if count == parts.len() {
+                return Err(format!("Invalid version \"{}\": too many components.", s));
+            }
+
+            parts[count] = part.parse().map_err(|_| format!("Invalid version \"{}\".", s))?;
+            count += 1;
+        }
+
+        Ok((Version::new(parts[0], parts[1], parts[2]), count))
+    }
+}
+
+impl FromStr for Version {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Version::parse_partial(s).map(|(version, _)| version)
+    }
+}
+
+impl TryFrom<String> for Version {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Op {
+    Exact,
+    Greater,
+    GreaterEq,
+    Less,
+    LessEq,
+    /// `^1.2`, compatible updates which don't change the leftmost non-zero component.
+    Caret,
+    /// `~1.2`, patch updates only, or minor updates if only the major version is given.
+    Tilde,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Comparator {
+    op: Op,
+    version: Version,
+    /// Number of components given in the requirement.
+    components: usize,
+}
+
+impl Comparator {
+    fn matches(&self, version: &Version) -> bool {
+        let v = &self.version;
+        let ord = version.cmp(v);
+
+        match self.op {
+            Op::Exact => match self.components {
+                1 => version.major == v.major,
+                2 => (version.major, version.minor) == (v.major, v.minor),
+                _ => ord == Ordering::Equal,
+            },
+            Op::Greater => ord == Ordering::Greater,
+            Op::GreaterEq => ord != Ordering::Less,
+            Op::Less => ord == Ordering::Less,
+            Op::LessEq => ord != Ordering::Greater,
+            Op::Caret => {
+                ord != Ordering::Less
+                    && if v.major > 0 || self.components == 1 {
+                        version.major == v.major
+                    } else if v.minor > 0 || self.components == 2 {
+                        (version.major, version.minor) == (v.major, v.minor)
+                    } else {
+                        version == v
+                    }
+            }
+            Op::Tilde => {
+                ord != Ordering::Less
+                    && version.major == v.major
+                    && (self.components == 1 || version.minor == v.minor)
+            }
+        }
+    }
+}
+
+/// A set of version constraints separated by commas, eg. `>=1.2, <2`. A bare version is treated like `^version`
+/// and `*` matches any version.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct VersionReq {
+    text: String,
+    comparators: Vec<Comparator>,
+}
+
+impl VersionReq {
+    pub fn any() -> VersionReq {
+        VersionReq { text: "*".to_owned(), comparators: Vec::new() }
+    }
+
+    pub fn matches(&self, version: &Version) -> bool {
+        self.comparators.iter().all(|comparator| comparator.matches(version))
+    }
+}
+
+impl FromStr for VersionReq {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut comparators = Vec::new();
+
+        for part in s.split(',').map(str::trim) {
+            if part == "*" || part.is_empty() {
+                continue;
+            }
+
+            let (op, version) = [
+                (">=", Op::GreaterEq),
+                ("<=", Op::LessEq),
+                (">", Op::Greater),
+                ("<", Op::Less),
+                ("=", Op::Exact),
+                ("^", Op::Caret),
+                ("~", Op::Tilde),
+            ]
+            .iter()
+            .find_map(|(prefix, op)| part.strip_prefix(prefix).map(|version| (*op, version)))
+            .unwrap_or((Op::Caret, part));
+
+            let (version, components) = Version::parse_partial(version)?;
+            comparators.push(Comparator { op, version, components });
+        }
+
+        Ok(VersionReq { text: s.trim().to_owned(), comparators })
+    }
+}
+
+impl TryFrom<String> for VersionReq {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Default for VersionReq {
+    fn default() -> Self {
+        VersionReq::any()
+    }
+}
+
+impl fmt::Display for VersionReq {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModDependency {
+    pub id: String,
+    #[serde(default)]
+    pub version: VersionReq,
+}
+
+/// Contents of the optional `mod.json` file in a mod directory.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModManifest {
+    pub id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    pub version: Version,
+    #[serde(default)]
+    pub authors: Vec<String>,
+    #[serde(default)]
+    pub description: String,
+    /// Path of the icon image, relative to the mod directory.
+    #[serde(default)]
+    pub icon: Option<String>,
+    /// Save slot used by the mod, overrides the one from mod.txt.
+    #[serde(default)]
+    pub save_slot: Option<i32>,
+    /// Mods which are loaded along with this one, with lower priority.
+    #[serde(default)]
+    pub dependencies: Vec<ModDependency>,
+    /// Ids of mods which can't be loaded along with this one.
+    #[serde(default)]
+    pub conflicts: Vec<String>,
+    #[serde(default)]
+    pub min_engine_version: Option<Version>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_req() {
+        let req: VersionReq = ">=1.2, <2".parse().unwrap();
+        assert!(req.matches(&Version::new(1, 2, 0)));
+        assert!(req.matches(&Version::new(1, 9, 3)));
+        assert!(!req.matches(&Version::new(1, 1, 9)));
+        assert!(!req.matches(&Version::new(2, 0, 0)));
+
+        let req: VersionReq = "0.3".parse().unwrap();
+        assert!(req.matches(&Version::new(0, 3, 5)));
+        assert!(!req.matches(&Version::new(0, 4, 0)));
+
+        let req: VersionReq = "~1.4.2".parse().unwrap();
+        assert!(req.matches(&Version::new(1, 4, 7)));
+        assert!(!req.matches(&Version::new(1, 5, 0)));
+
+        assert!(VersionReq::any().matches(&Version::new(0, 0, 1)));
+        assert!("1.x".parse::<VersionReq>().is_err());
+    }
+}
@@ -2,8 +2,9 @@ use std::cell::RefCell;
 use std::rc::Rc;
 
 use downcast::Downcast;
-use imgui::{Condition, MenuItem, TabItem, TabItemFlags, Window};
+use imgui::{Condition, Key, MenuItem, TabItem, TabItemFlags, Window};
 
+use crate::editor::stage_table::{new_stage_data, save_stage_table, StageEntryWindow};
 use crate::editor::{CurrentTool, EditorInstance};
 use crate::framework::context::Context;
 use crate::framework::error::GameResult;
@@ -11,7 +12,7 @@ use crate::framework::keyboard;
 use crate::framework::keyboard::ScanCode;
 use crate::framework::ui::Components;
 use crate::game::shared_game_state::SharedGameState;
-use crate::game::stage::Stage;
+use crate::game::stage::{Stage, StageTableFormat};
 use crate::graphics::font::Font;
 use crate::scene::Scene;
 use crate::scene::game_scene::GameScene;
@@ -26,6 +27,22 @@ impl ErrorList {
         Self { errors: Vec::new() }
     }
 
+    fn show(&mut self, ui: &imgui::Ui) {
+        if self.errors.is_empty() {
+            return;
+        }
+
+        ui.window("Errors").size([400.0, 0.0], Condition::FirstUseEver).build(|| {
+            for error in &self.errors {
+                ui.text_wrapped(error);
+            }
+
+            if ui.button("Dismiss") {
+                self.errors.clear();
+            }
+        });
+    }
+
     fn try_or_push_error(&mut self, func: impl FnOnce() -> GameResult<()>) {
         if let Err(err) = func() {
             self.errors.push(err.to_string());
@@ -39,6 +56,7 @@ fn catch(error_list: Rc<RefCell<ErrorList>>, func: impl FnOnce() -> GameResult<(
 
 pub struct EditorScene {
     stage_list: StageListWindow,
+    stage_entry: StageEntryWindow,
     error_list: Rc<RefCell<ErrorList>>,
     instances: Vec<EditorInstance>,
     subscene: Option<Box<GameScene>>,
@@ -51,6 +69,7 @@ impl EditorScene {
     pub fn new() -> Self {
         EditorScene {
             stage_list: StageListWindow::new(),
+            stage_entry: StageEntryWindow::new(),
             error_list: Rc::new(RefCell::new(ErrorList::new())),
             instances: Vec::new(),
             subscene: None,
@@ -75,9 +94,16 @@ impl EditorScene {
             }
 
             if let Some(stage) = state.stages.get(stage_id) {
-                let stage = Stage::load(&state.constants.base_paths, stage, ctx)?;
+                let roots = &state.constants.base_paths;
+                let stage = if Stage::exists(roots, stage, ctx) {
+                    Stage::load(roots, stage, ctx)?
+                } else {
+                    // Stages created in the editor don't have a map until they're saved.
+                    Stage::new_empty(roots, stage, 20, 15, ctx)
+                };
+                let npc_data = stage.load_npcs(&state.constants.base_paths, ctx).unwrap_or_default();
 
-                let new_instance = EditorInstance::new(stage_id, stage);
+                let new_instance = EditorInstance::new(stage_id, stage, npc_data);
                 self.instances.push(new_instance);
                 self.selected_instance = self.instances.len() - 1;
                 self.switch_tab = true;
@@ -87,6 +113,57 @@ impl EditorScene {
         });
     }
 
+    fn save_stage_table(&mut self, state: &mut SharedGameState, ctx: &mut Context, format: Option<StageTableFormat>) {
+        catch(self.error_list.clone(), || save_stage_table(state, ctx, format));
+    }
+
+    fn new_stage(&mut self, state: &mut SharedGameState, source: Option<usize>) {
+        let stage_id = state.stages.len();
+        let stage = match source.and_then(|idx| state.stages.get(idx)) {
+            // Duplicates share the map until it's renamed in the table entry and saved.
+            Some(source) => {
+                let mut stage = source.clone();
+                stage.name = format!("{} (copy)", stage.name);
+                stage.name_jp = format!("{} (copy)", stage.name_jp);
+                stage
+            }
+            None => new_stage_data(&format!("Stage{}", stage_id)),
+        };
+
+        state.stages.push(stage);
+        self.stage_list.selected_stage = stage_id as i32;
+        self.stage_entry.open(stage_id);
+    }
+
+    /// Removes a stage from the table, the stages after it are renumbered.
+    fn delete_stage(&mut self, state: &mut SharedGameState, stage_id: usize) {
+        if stage_id >= state.stages.len() {
+            return;
+        }
+
+        state.stages.remove(stage_id);
+        self.instances.retain(|instance| instance.stage_id != stage_id);
+        for instance in self.instances.iter_mut() {
+            if instance.stage_id > stage_id {
+                instance.stage_id -= 1;
+            }
+        }
+
+        self.selected_instance = self.selected_instance.min(self.instances.len().saturating_sub(1));
+        self.stage_list.selected_stage = self.stage_list.selected_stage.min(state.stages.len() as i32 - 1);
+        self.stage_entry.stage_id = None;
+    }
+
+    fn save_stage(&mut self, state: &mut SharedGameState, ctx: &mut Context) {
+        catch(self.error_list.clone(), || {
+            if let Some(instance) = self.instances.get(self.selected_instance) {
+                instance.save(state, ctx)?;
+            }
+
+            Ok(())
+        });
+    }
+
     fn test_stage(&mut self, state: &mut SharedGameState, ctx: &mut Context) {
         catch(self.error_list.clone(), || {
             if let Some(instance) = self.instances.get(self.selected_instance) {
@@ -112,6 +189,10 @@ impl EditorScene {
         for action in actions.iter() {
             match action {
                 StageListAction::OpenStage(idx) => self.open_stage(state, ctx, *idx),
+                StageListAction::EditStage(idx) => self.stage_entry.open(*idx),
+                StageListAction::NewStage => self.new_stage(state, None),
+                StageListAction::DuplicateStage(idx) => self.new_stage(state, Some(*idx)),
+                StageListAction::DeleteStage(idx) => self.delete_stage(state, *idx),
             }
         }
     }
@@ -226,6 +307,19 @@ impl Scene for EditorScene {
                     self.stage_list.show();
                 }
 
+                if MenuItem::new("Save").shortcut("Ctrl+S").enabled(!self.instances.is_empty()).build(ui) {
+                    self.save_stage(state, ctx);
+                }
+
+                let table_name = state.stage_table_format.map_or("stage table", |f| f.file_name());
+                if MenuItem::new(format!("Save {}", table_name)).build(ui) {
+                    self.save_stage_table(state, ctx, None);
+                }
+
+                if MenuItem::new("Save stage table as JSON").build(ui) {
+                    self.save_stage_table(state, ctx, Some(StageTableFormat::Json));
+                }
+
                 ui.separator();
 
                 if MenuItem::new("Exit editor").build(ui) {
@@ -234,6 +328,37 @@ impl Scene for EditorScene {
 
                 menu.end();
             }
+
+            if let Some(menu) = ui.begin_menu("Edit") {
+                let instance = self.instances.get_mut(self.selected_instance);
+                let (can_undo, can_redo) =
+                    instance.as_ref().map_or((false, false), |i| (i.history.can_undo(), i.history.can_redo()));
+                let can_resize = instance.as_ref().map_or(false, |i| i.stage.data.pxpack_data.is_none());
+                let undo = MenuItem::new("Undo").shortcut("Ctrl+Z").enabled(can_undo).build(ui);
+                let redo = MenuItem::new("Redo").shortcut("Ctrl+Y").enabled(can_redo).build(ui);
+                ui.separator();
+                let resize = MenuItem::new("Resize stage...").enabled(can_resize).build(ui);
+
+                if let Some(instance) = instance {
+                    if undo {
+                        instance.undo();
+                    } else if redo {
+                        instance.redo();
+                    } else if resize {
+                        instance.show_resize_dialog();
+                    }
+                }
+
+                menu.end();
+            }
+
+            if let Some(menu) = ui.begin_menu("View") {
+                if let Some(instance) = self.instances.get_mut(self.selected_instance) {
+                    MenuItem::new("Tile attributes").build_with_ref(ui, &mut instance.show_attributes);
+                }
+
+                menu.end();
+            }
             menu_bar.end();
         }
 
@@ -258,6 +383,10 @@ impl Scene for EditorScene {
                 if ui.tool_button("Rectangle", self.current_tool == CurrentTool::Rectangle) {
                     self.current_tool = CurrentTool::Rectangle;
                 }
+                ui.same_line();
+                if ui.tool_button("Entities", self.current_tool == CurrentTool::Entity) {
+                    self.current_tool = CurrentTool::Entity;
+                }
 
                 ui.same_line();
                 ui.text("|");
@@ -288,8 +417,23 @@ impl Scene for EditorScene {
             });
 
         self.stage_list.action(state, ctx, ui);
+        self.error_list.borrow_mut().show(ui);
+
+        if let Some(stage_id) = self.stage_entry.action(state, ui) {
+            if let Some(instance) = self.instances.iter_mut().find(|instance| instance.stage_id == stage_id) {
+                instance.set_stage_data(&state.stages[stage_id], state, ctx);
+            }
+        }
 
         if let Some(instance) = self.instances.get_mut(self.selected_instance) {
+            if ui.io().key_ctrl && !ui.io().want_capture_keyboard {
+                if ui.is_key_pressed(Key::Z) {
+                    instance.undo();
+                } else if ui.is_key_pressed(Key::Y) {
+                    instance.redo();
+                }
+            }
+
             instance.process(state, ctx, ui, self.current_tool);
         }
 
@@ -305,6 +449,10 @@ struct StageListWindow {
 
 enum StageListAction {
     OpenStage(usize),
+    EditStage(usize),
+    NewStage,
+    DuplicateStage(usize),
+    DeleteStage(usize),
 }
 
 impl StageListWindow {
@@ -325,7 +473,7 @@ impl StageListWindow {
             .resizable(false)
             .collapsible(false)
             .position_pivot([0.5, 0.5])
-            .size([300.0, 352.0], Condition::FirstUseEver)
+            .size([300.0, 376.0], Condition::FirstUseEver)
             .build(|| {
                 let mut stages = Vec::with_capacity(state.stages.len());
                 for stage in state.stages.iter() {
@@ -341,13 +489,35 @@ impl StageListWindow {
                     }
 
                     ui.same_line();
-                    if ui.button("Edit table entry") {}
+                    if ui.button("Edit table entry") {
+                        self.actions.push(StageListAction::EditStage(self.selected_stage as usize));
+                    }
                 });
 
                 ui.same_line();
                 if ui.button("Cancel") {
                     self.visible = false;
                 }
+
+                if ui.button("New") {
+                    self.actions.push(StageListAction::NewStage);
+                }
+
+                ui.disabled(self.selected_stage < 0, || {
+                    ui.same_line();
+                    if ui.button("Duplicate") {
+                        self.actions.push(StageListAction::DuplicateStage(self.selected_stage as usize));
+                    }
+
+                    ui.same_line();
+                    if ui.button("Delete") {
+                        self.actions.push(StageListAction::DeleteStage(self.selected_stage as usize));
+                    }
+
+                    if ui.is_item_hovered() {
+                        ui.tooltip_text("Stages after the deleted one are renumbered, which breaks <TRA to them.");
+                    }
+                });
             });
     }
 }
@@ -16,7 +16,7 @@ use crate::components::hud::HUD;
 use crate::components::inventory::InventoryUI;
 use crate::components::map_system::MapSystem;
 use crate::components::nikumaru::NikumaruCounter;
-use crate::components::replay::Replay;
+use crate::components::replay::{Replay, ReplayChecksum, ReplayKeyframe};
 use crate::components::stage_select::StageSelect;
 use crate::components::text_boxes::TextBoxes;
 use crate::components::tilemap::{TileLayer, Tilemap};
@@ -30,19 +30,26 @@ use crate::framework::graphics::{draw_rect, BlendMode, FilterMode};
 use crate::framework::keyboard::ScanCode;
 use crate::framework::ui::Components;
 use crate::framework::{filesystem, gamepad, graphics};
-use crate::game::caret::CaretType;
+use crate::game::caret::{Caret, CaretType};
 use crate::game::frame::{Frame, UpdateTarget};
+use crate::game::hot_reload::{ChangedAsset, HotReloadWatcher};
 use crate::game::inventory::{Inventory, TakeExperienceResult};
+use crate::game::lighting::{Light, LightSystem, LightTarget};
 use crate::game::map::WaterParams;
 use crate::game::npc::boss::BossNPC;
 use crate::game::npc::list::NPCList;
 use crate::game::npc::{NPCLayer, NPC};
 use crate::game::physics::{PhysicalEntity, OFFSETS};
 use crate::game::player::{ControlMode, Player, TargetPlayer};
+use crate::game::profile::GameProfile;
+#[cfg(feature = "scripting-lua")]
+use crate::game::scripting::lua::{LuaHook, LuaScriptingState};
 use crate::game::scripting::tsc::credit_script::CreditScriptVM;
-use crate::game::scripting::tsc::text_script::{ScriptMode, TextScriptExecutionState, TextScriptVM};
+use crate::game::scripting::tsc::text_script::{ScriptMode, TextScript, TextScriptExecutionState, TextScriptVM};
 use crate::game::settings::ControllerType;
-use crate::game::shared_game_state::{CutsceneSkipMode, PlayerCount, ReplayState, SharedGameState, TileSize};
+use crate::game::shared_game_state::{
+    CutsceneSkipMode, PlayerCount, ReplayKind, ReplayState, SharedGameState, TileSize,
+};
 use crate::game::stage::{BackgroundType, Stage, StageTexturePaths};
 use crate::game::weapon::bullet::BulletManager;
 use crate::game::weapon::{Weapon, WeaponType};
@@ -84,6 +91,7 @@ pub struct GameScene {
     pub boss: BossNPC,
     pub bullet_manager: BulletManager,
     pub lighting_mode: LightingMode,
+    pub lights: LightSystem,
     pub intro_mode: bool,
     pub pause_menu: PauseMenu,
     pub stage_textures: Rc<RefCell<StageTexturePaths>>,
@@ -146,6 +154,8 @@ impl GameScene {
             player2.load_skin(skinsheet_name.to_owned(), state, ctx);
         }
 
+        let lights = LightSystem::new(&stage.metadata.lighting);
+
         Ok(Self {
             tick: 0,
             stage,
@@ -176,6 +186,7 @@ impl GameScene {
             boss: BossNPC::new(),
             bullet_manager: BulletManager::new(),
             lighting_mode: LightingMode::None,
+            lights,
             intro_mode: false,
             pause_menu: PauseMenu::new(),
             stage_textures,
@@ -209,6 +220,45 @@ impl GameScene {
         self.player2.cond.set_alive(false);
     }
 
+    /// Starts recording a session replay from the current room, or stops and saves the one being recorded.
+    pub fn toggle_replay_recording(&mut self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
+        match state.replay_state {
+            // Challenge runs are recorded for the best and last replays, those can't be stopped.
+            ReplayState::Recording if self.replay.kind != ReplayKind::Session => {}
+            ReplayState::Recording => {
+                self.replay.stop_session_recording(state, ctx)?;
+            }
+            ReplayState::None => {
+                // Re-enter the room from a profile snapshot, so playback can start from a restorable state.
+                let profile = GameProfile::dump(state, self, None);
+                state.reset();
+
+                let mut next_scene = GameScene::new(state, ctx, profile.current_map as usize)?;
+                profile.apply(state, &mut next_scene, ctx);
+                next_scene.replay.kind = ReplayKind::Session;
+
+                state.replay_state = ReplayState::Recording;
+                state.next_scene = Some(Box::new(next_scene));
+            }
+            ReplayState::Playback(_) => {}
+        }
+
+        Ok(())
+    }
+
+    /// Saves the session being recorded if the game is about to be left, either to the title screen or by quitting.
+    fn save_session_on_exit(&mut self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
+        if state.replay_state != ReplayState::Recording || self.replay.kind != ReplayKind::Session {
+            return Ok(());
+        }
+
+        if state.shutdown || state.next_scene.as_ref().is_some_and(|scene| scene.is::<TitleScene>()) {
+            self.replay.stop_session_recording(state, ctx)?;
+        }
+
+        Ok(())
+    }
+
     fn draw_npc_layer(&self, state: &mut SharedGameState, ctx: &mut Context, layer: NPCLayer) -> GameResult {
         for npc in self.npc_list.iter_alive() {
             if npc.layer != layer
@@ -498,6 +548,99 @@ impl GameScene {
         }
     }
 
+    /// Draws the lights defined by the stage metadata and scripts.
+    fn draw_stage_lights(
+        &self,
+        carets: &[Caret],
+        canvas_size: (f32, f32),
+        frame_time: f64,
+        batch: &mut Box<dyn SpriteBatch>,
+    ) {
+        if self.lights.lights.is_empty() {
+            return;
+        }
+
+        let mut draw = |light: &Light, x: f32, y: f32, seed: u32| {
+            let color = LightSystem::light_color(light, seed, self.tick, frame_time);
+            self.draw_light(x, y, light.radius / 32.0, color, batch);
+        };
+
+        for npc in self.npc_list.iter_alive() {
+            if npc.cond.hidden() {
+                continue;
+            }
+
+            let x = interpolate_fix9_scale(npc.prev_x - self.frame.prev_x, npc.x - self.frame.x, frame_time);
+            let y = interpolate_fix9_scale(npc.prev_y - self.frame.prev_y, npc.y - self.frame.y, frame_time);
+
+            let lights = self
+                .lights
+                .lights_for(LightTarget::NpcType { id: npc.npc_type })
+                .chain(self.lights.lights_for(LightTarget::NpcEvent { id: npc.event_num }));
+            for light in lights {
+                draw(light, x, y, npc.id as u32);
+            }
+        }
+
+        for (idx, bullet) in self.bullet_manager.bullets.iter().enumerate() {
+            for light in self.lights.lights_for(LightTarget::Bullet { id: bullet.btype }) {
+                let x = interpolate_fix9_scale(bullet.prev_x - self.frame.prev_x, bullet.x - self.frame.x, frame_time);
+                let y = interpolate_fix9_scale(bullet.prev_y - self.frame.prev_y, bullet.y - self.frame.y, frame_time);
+                draw(light, x, y, 0x10000 + idx as u32);
+            }
+        }
+
+        for (idx, caret) in carets.iter().enumerate() {
+            for light in self.lights.lights_for(LightTarget::Caret { id: caret.ctype as u8 }) {
+                let x = interpolate_fix9_scale(caret.prev_x - self.frame.prev_x, caret.x - self.frame.x, frame_time);
+                let y = interpolate_fix9_scale(caret.prev_y - self.frame.prev_y, caret.y - self.frame.y, frame_time);
+                draw(light, x, y, 0x20000 + idx as u32);
+            }
+        }
+
+        let (frame_x, frame_y) = self.frame.xy_interpolated(frame_time);
+        let tile_size = self.stage.map.tile_size.as_float();
+
+        for def in self.lights.lights.iter() {
+            match def.target {
+                LightTarget::Point { x, y } => {
+                    let seed = 0x30000 + y as u32 * self.stage.map.width as u32 + x as u32;
+                    draw(
+                        &def.light,
+                        (x as f32 + 0.5) * tile_size - frame_x,
+                        (y as f32 + 0.5) * tile_size - frame_y,
+                        seed,
+                    );
+                }
+                LightTarget::Tile { id } => {
+                    // Only the tiles within the radius of the light from the visible area can light it up.
+                    let margin = def.light.radius;
+                    let left = ((frame_x - margin) / tile_size).floor().max(0.0) as usize;
+                    let top = ((frame_y - margin) / tile_size).floor().max(0.0) as usize;
+                    let right = ((frame_x + canvas_size.0 + margin) / tile_size).ceil() as usize;
+                    let bottom = ((frame_y + canvas_size.1 + margin) / tile_size).ceil() as usize;
+
+                    for y in top..bottom.min(self.stage.map.height as usize) {
+                        for x in left..right.min(self.stage.map.width as usize) {
+                            if self.stage.tile_at(x, y) != id {
+                                continue;
+                            }
+
+                            let seed = 0x30000 + (y * self.stage.map.width as usize + x) as u32;
+                            draw(
+                                &def.light,
+                                (x as f32 + 0.5) * tile_size - frame_x,
+                                (y as f32 + 0.5) * tile_size - frame_y,
+                                seed,
+                            );
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
     fn draw_light_map(&self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
         {
             let maybe_canvas = state.lightmap_canvas.as_ref();
@@ -511,7 +654,7 @@ impl GameScene {
 
         graphics::set_blend_mode(ctx, BlendMode::Add)?;
 
-        graphics::clear(ctx, Color::from_rgb(100, 100, 110));
+        graphics::clear(ctx, self.lights.ambient());
 
         for npc in self.npc_list.iter_alive() {
             if npc.x < (self.frame.x - 128 * 0x200 - npc.display_bounds.width() as i32 * 0x200)
@@ -1070,6 +1213,8 @@ impl GameScene {
                 }
             }
 
+            self.draw_stage_lights(&state.carets, state.canvas_size, state.frame_time, batch);
+
             batch.draw_filtered(FilterMode::Linear, ctx)?;
         }
 
@@ -1638,12 +1783,100 @@ impl GameScene {
 
         Ok(())
     }
+
+    /// Reloads the assets modified on disk in place, so the player keeps their position and state.
+    /// Only active in debug builds or with debug mode enabled.
+    fn tick_hot_reload(&mut self, state: &mut SharedGameState, ctx: &mut Context) {
+        if !cfg!(debug_assertions) && !state.settings.debug_mode {
+            state.hot_reload = None;
+            return;
+        }
+
+        let watcher = state.hot_reload.get_or_insert_with(|| HotReloadWatcher::new(filesystem::physical_roots(ctx)));
+        let mut changes = watcher.poll(&state.constants.base_paths);
+
+        // Swapping the scripts while an event runs would make it continue in the middle of different bytecode.
+        if state.textscript_vm.state != TextScriptExecutionState::Ended {
+            let (scripts, others): (Vec<_>, Vec<_>) =
+                changes.into_iter().partition(|asset| matches!(asset, ChangedAsset::TextScript(_)));
+            watcher.defer(scripts);
+            changes = others;
+        }
+
+        for asset in changes {
+            if let Err(err) = self.reload_asset(state, ctx, &asset) {
+                log::warn!("Failed to reload {:?}: {}", asset, err);
+            }
+        }
+    }
+
+    fn reload_asset(&mut self, state: &mut SharedGameState, ctx: &mut Context, asset: &ChangedAsset) -> GameResult {
+        match asset {
+            ChangedAsset::TextScript(path) => {
+                let stage_script = ["Stage/", &self.stage.data.map, ".tsc"].join("");
+                let mut scripts = state.textscript_vm.scripts.borrow_mut();
+                let script = if path.eq_ignore_ascii_case("Head.tsc") {
+                    &mut scripts.global_script
+                } else if path.eq_ignore_ascii_case("ArmsItem.tsc") {
+                    &mut scripts.inventory_script
+                } else if path.eq_ignore_ascii_case("StageSelect.tsc") {
+                    &mut scripts.stage_select_script
+                } else if path.eq_ignore_ascii_case(&stage_script) {
+                    &mut scripts.scene_script
+                } else {
+                    return Ok(());
+                };
+
+                let file = filesystem::open_find(ctx, &state.constants.base_paths, path)?;
+                *script = TextScript::load_from(file, &state.constants)?;
+                info!("Reloaded script {}", path);
+            }
+            ChangedAsset::Map(name) => {
+                if !name.eq_ignore_ascii_case(&self.stage.data.map)
+                    && !name.eq_ignore_ascii_case(&self.stage.data.tileset.name)
+                {
+                    return Ok(());
+                }
+
+                let stage = Stage::load(&state.constants.base_paths, &self.stage.data, ctx)?;
+                let mut map = stage.map;
+
+                // Keep the tiles changed by scripts, as long as they still refer to the same place.
+                if (map.width, map.height) == (self.stage.map.width, self.stage.map.height) {
+                    for (&index, &tile) in &self.stage.tile_edits {
+                        if let Some(map_tile) = map.tiles.get_mut(index) {
+                            *map_tile = tile;
+                        }
+                    }
+                } else {
+                    self.stage.tile_edits.clear();
+                }
+
+                self.stage.map = map;
+                info!("Reloaded map {}", name);
+            }
+            ChangedAsset::Texture(name) => {
+                state.texture_set.tex_map.retain(|key, _| !key.eq_ignore_ascii_case(name));
+                info!("Reloaded texture {}", name);
+            }
+            ChangedAsset::LuaScript => {
+                #[cfg(feature = "scripting-lua")]
+                state.lua.reload_scripts(ctx, &state.constants.base_paths)?;
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl Scene for GameScene {
     fn init(&mut self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
-        if state.mod_path.is_some() && state.replay_state == ReplayState::Recording {
-            self.replay.initialize_recording(state);
+        if state.replay_state == ReplayState::Recording {
+            self.replay.initialize_recording(state, self.stage_id);
+
+            let tick = self.replay.recorded_ticks();
+            let keyframe = ReplayKeyframe::capture(state, self, tick)?;
+            self.replay.keyframes.push(keyframe);
         }
         if state.player_count == PlayerCount::Two {
             self.add_player2(state, ctx);
@@ -1651,10 +1884,8 @@ impl Scene for GameScene {
             self.drop_player2();
         }
 
-        if state.mod_path.is_some() {
-            if let ReplayState::Playback(replay_kind) = state.replay_state {
-                self.replay.initialize_playback(state, ctx, replay_kind)?;
-            }
+        if let ReplayState::Playback(replay_kind) = state.replay_state {
+            self.replay.initialize_playback(state, ctx, replay_kind)?;
         }
 
         self.npc_list.set_rng_seed(state.game_rng.next());
@@ -1735,6 +1966,11 @@ impl Scene for GameScene {
             _ => LightingMode::None,
         };
 
+        if !self.intro_mode {
+            self.lighting_mode = self.lights.lighting_mode(self.lighting_mode);
+        }
+
+        self.pause_menu.replay_kind = self.replay.kind;
         self.pause_menu.init(state, ctx)?;
         self.whimsical_star.init(&self.player1);
 
@@ -1749,13 +1985,16 @@ impl Scene for GameScene {
             }
         }
 
+        #[cfg(feature = "scripting-lua")]
+        LuaScriptingState::run_hook(state, self, LuaHook::StageLoad(self.stage_id))?;
+
         Ok(())
     }
 
     fn tick(&mut self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
         if !self.pause_menu.is_paused() {
             if let ReplayState::Playback(_) = state.replay_state {
-                self.replay.tick(state, (ctx, &mut self.player1))?;
+                self.replay.tick(state, (ctx, &mut self.player1, &mut self.player2))?;
             }
         }
 
@@ -1769,6 +2008,13 @@ impl Scene for GameScene {
             state.player_count_modified_in_game = false;
         }
 
+        if state.replay_recording_toggled {
+            state.replay_recording_toggled = false;
+            self.toggle_replay_recording(state, ctx)?;
+        }
+
+        self.tick_hot_reload(state, ctx);
+
         self.player1.controller.update(state, ctx)?;
         self.player1.controller.update_trigger();
         self.player2.controller.update(state, ctx)?;
@@ -1801,12 +2047,13 @@ impl Scene for GameScene {
         }
 
         if self.pause_menu.is_paused() {
+            self.pause_menu.replay_kind = self.replay.kind;
             self.pause_menu.tick(state, ctx)?;
-            return Ok(());
+            return self.save_session_on_exit(state, ctx);
         }
 
         if state.replay_state == ReplayState::Recording {
-            self.replay.tick(state, (ctx, &mut self.player1))?;
+            self.replay.tick(state, (ctx, &mut self.player1, &mut self.player2))?;
         }
 
         match state.textscript_vm.state {
@@ -1863,6 +2110,9 @@ impl Scene for GameScene {
                     _ => {
                         if state.control_flags.tick_world() {
                             self.tick_world(state)?;
+
+                            #[cfg(feature = "scripting-lua")]
+                            LuaScriptingState::run_tick(state, self)?;
                         }
                     }
                 }
@@ -1889,6 +2139,16 @@ impl Scene for GameScene {
         self.flash.tick(state, ())?;
         self.text_boxes.tick(state, ())?;
 
+        if state.replay_state != ReplayState::None {
+            let checksum = ReplayChecksum::capture(state, self, self.replay.current_tick(state));
+            self.replay.process_checksum(state, checksum);
+        }
+
+        if self.replay.keyframe_due(state) {
+            let keyframe = ReplayKeyframe::capture(state, self, self.replay.recorded_ticks())?;
+            self.replay.keyframes.push(keyframe);
+        }
+
         if state.control_flags.tick_world() {
             self.tick = self.tick.wrapping_add(1);
         }
@@ -1910,7 +2170,7 @@ impl Scene for GameScene {
             state.super_quake_rumble_counter = 0;
         }
 
-        Ok(())
+        self.save_session_on_exit(state, ctx)
     }
 
     fn draw_tick(&mut self, state: &mut SharedGameState) -> GameResult {
@@ -2357,3 +2617,29 @@ impl Scene for GameScene {
         Ok(())
     }
 }
+
+#[cfg(test)]
+impl GameScene {
+    /// Creates a scene with an empty stage, for tests which don't need the game data.
+    pub(crate) fn new_empty(state: &mut SharedGameState, ctx: &mut Context) -> GameScene {
+        use crate::game::stage::{Background, NpcType, StageData, Tileset};
+
+        let data = StageData {
+            name: "Test".to_owned(),
+            name_jp: "Test".to_owned(),
+            map: "0".to_owned(),
+            boss_no: 0,
+            tileset: Tileset::new("0"),
+            pxpack_data: None,
+            background: Background::new("bk0"),
+            background_type: BackgroundType::TiledStatic,
+            background_color: Color::from_rgb(0, 0, 32),
+            npc1: NpcType::new("0"),
+            npc2: NpcType::new("0"),
+        };
+        state.stages = vec![data.clone()];
+
+        let stage = Stage::new_empty(&state.constants.base_paths, &data, 16, 16, ctx);
+        GameScene::from_stage(state, ctx, stage, 0).unwrap()
+    }
+}
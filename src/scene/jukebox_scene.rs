@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use itertools::Itertools;
 
 use crate::common::Color;
@@ -11,7 +13,7 @@ use crate::game::frame::Frame;
 use crate::game::map::Map;
 use crate::game::settings::ControllerType;
 use crate::game::shared_game_state::{SharedGameState, TileSize};
-use crate::game::stage::{BackgroundType, NpcType, Stage, StageData, StageTexturePaths, Tileset};
+use crate::game::stage::{BackgroundType, NpcType, Stage, StageData, StageMetadata, StageTexturePaths, Tileset};
 use crate::graphics::font::Font;
 use crate::input::combined_menu_controller::CombinedMenuController;
 use crate::scene::title_scene::TitleScene;
@@ -80,6 +82,8 @@ impl JukeboxScene {
                 npc1: NpcType::new("0"),
                 npc2: NpcType::new("0"),
             },
+            metadata: StageMetadata::default(),
+            tile_edits: BTreeMap::new(),
         };
 
         let mut textures = StageTexturePaths::new();
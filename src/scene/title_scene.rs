@@ -1,16 +1,21 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
 use crate::common::{Color, VERSION_BANNER};
 use crate::components::background::Background;
 use crate::components::compact_jukebox::CompactJukebox;
 use crate::components::nikumaru::NikumaruCounter;
+use crate::components::replay::{Replay, SESSION_REPLAY_DIR};
 use crate::entity::GameEntity;
 use crate::framework::context::Context;
-use crate::framework::error::GameResult;
+use crate::framework::error::{GameError, GameResult};
+use crate::framework::filesystem;
 use crate::game::frame::Frame;
 use crate::game::map::Map;
 use crate::game::shared_game_state::{
     GameDifficulty, MenuCharacter, ReplayKind, ReplayState, Season, SharedGameState, TileSize,
 };
-use crate::game::stage::{BackgroundType, NpcType, Stage, StageData, StageTexturePaths, Tileset};
+use crate::game::stage::{BackgroundType, NpcType, Stage, StageData, StageMetadata, StageTexturePaths, Tileset};
 use crate::graphics::font::Font;
 use crate::input::combined_menu_controller::CombinedMenuController;
 use crate::input::touch_controls::TouchControlType;
@@ -31,12 +36,14 @@ enum CurrentMenu {
     ChallengesMenu,
     ChallengeConfirmMenu,
     PlayerCountMenu,
+    ReplayMenu,
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum MainMenuEntry {
     Start,
     Challenges,
+    Replays,
     Options,
     Editor,
     Jukebox,
@@ -53,6 +60,7 @@ impl Default for MainMenuEntry {
 pub enum ChallengesMenuEntry {
     Back,
     Challenge(usize),
+    Error(usize),
 }
 
 impl Default for ChallengesMenuEntry {
@@ -61,6 +69,18 @@ impl Default for ChallengesMenuEntry {
     }
 }
 
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ReplayMenuEntry {
+    Back,
+    Replay(usize),
+}
+
+impl Default for ReplayMenuEntry {
+    fn default() -> Self {
+        ReplayMenuEntry::Back
+    }
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum ConfirmMenuEntry {
     Title,
@@ -84,6 +104,8 @@ pub struct TitleScene {
     save_select_menu: SaveSelectMenu,
     challenges_menu: Menu<ChallengesMenuEntry>,
     confirm_menu: Menu<ConfirmMenuEntry>,
+    replay_menu: Menu<ReplayMenuEntry>,
+    replay_files: Vec<PathBuf>,
     coop_menu: PlayerCountMenu,
     settings_menu: SettingsMenu,
     background: Background,
@@ -111,6 +133,8 @@ impl TitleScene {
                 npc1: NpcType::new("0"),
                 npc2: NpcType::new("0"),
             },
+            metadata: StageMetadata::default(),
+            tile_edits: BTreeMap::new(),
         };
         let mut textures = StageTexturePaths::new();
         textures.update(&fake_stage);
@@ -126,6 +150,8 @@ impl TitleScene {
             save_select_menu: SaveSelectMenu::new(),
             challenges_menu: Menu::new(0, 0, 150, 0),
             confirm_menu: Menu::new(0, 0, 150, 0),
+            replay_menu: Menu::new(0, 0, 150, 0),
+            replay_files: Vec::new(),
             coop_menu: PlayerCountMenu::new(),
             settings_menu,
             background: Background::new(),
@@ -193,6 +219,54 @@ impl TitleScene {
         self.current_menu = CurrentMenu::OptionMenu;
         Ok(())
     }
+
+    fn update_replay_menu(&mut self, state: &mut SharedGameState, ctx: &mut Context) {
+        self.replay_files = filesystem::user_read_dir(ctx, SESSION_REPLAY_DIR)
+            .map(|files| files.filter(|path| path.extension().map_or(false, |ext| ext == "rep")).collect())
+            .unwrap_or_default();
+        // file names are timestamps, newest first
+        self.replay_files.sort_unstable_by(|a, b| b.cmp(a));
+
+        self.replay_menu = Menu::new(0, 0, 150, 0);
+        for (idx, path) in self.replay_files.iter().enumerate() {
+            let name = path.file_stem().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+            self.replay_menu.push_entry(ReplayMenuEntry::Replay(idx), MenuEntry::Active(name));
+        }
+
+        if self.replay_files.is_empty() {
+            self.replay_menu.push_entry(
+                ReplayMenuEntry::Replay(0),
+                MenuEntry::Disabled(state.loc.t("menus.replay_menu.no_replays").to_owned()),
+            );
+        }
+
+        self.replay_menu
+            .push_entry(ReplayMenuEntry::Back, MenuEntry::Active(state.loc.t("common.back").to_owned()));
+        self.replay_menu.selected =
+            if self.replay_files.is_empty() { ReplayMenuEntry::Back } else { ReplayMenuEntry::Replay(0) };
+    }
+
+    fn play_session_replay(&self, state: &mut SharedGameState, ctx: &mut Context, idx: usize) -> GameResult {
+        let path = self.replay_files.get(idx).ok_or_else(|| GameError::InvalidValue("No such replay.".to_owned()))?;
+
+        let mut replay = Replay::new();
+        replay.load_from(filesystem::user_open(ctx, path)?)?;
+        replay.kind = ReplayKind::Session;
+
+        if replay.keyframes.is_empty() {
+            return Err(GameError::InvalidValue("Replay has no keyframe to start the playback from.".to_owned()));
+        }
+
+        state.set_replay_mods(&replay.header);
+        state.reload_resources(ctx)?;
+
+        replay.header.check_compatibility(state)?;
+
+        let scene = replay.seek(state, ctx, 0)?;
+        state.next_scene = Some(Box::new(scene));
+
+        Ok(())
+    }
 }
 
 static COPYRIGHT_PIXEL: &str = "2004.12  Studio Pixel";
@@ -201,8 +275,8 @@ static COPYRIGHT_NICALIS: &str = "@2022 NICALIS INC."; // Nicalis font uses @ fo
 
 impl Scene for TitleScene {
     fn init(&mut self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
-        if !state.mod_path.is_none() {
-            state.mod_path = None;
+        if state.mod_path.is_some() || state.replay_mod_paths.is_some() {
+            state.clear_replay_mods();
             state.reload_resources(ctx)?;
         }
 
@@ -219,6 +293,13 @@ impl Scene for TitleScene {
             );
         }
 
+        if filesystem::user_is_dir(ctx, SESSION_REPLAY_DIR) {
+            self.main_menu.push_entry(
+                MainMenuEntry::Replays,
+                MenuEntry::Active(state.loc.t("menus.main_menu.replays").to_owned()),
+            );
+        }
+
         self.main_menu
             .push_entry(MainMenuEntry::Options, MenuEntry::Active(state.loc.t("menus.main_menu.options").to_owned()));
 
@@ -251,6 +332,10 @@ impl Scene for TitleScene {
             if !mod_info.valid {
                 self.challenges_menu
                     .push_entry(ChallengesMenuEntry::Challenge(idx), MenuEntry::Disabled(mod_info.path.clone()));
+                if let Some(error) = &mod_info.error {
+                    self.challenges_menu
+                        .push_entry(ChallengesMenuEntry::Error(idx), MenuEntry::LongText(error.clone(), false, false));
+                }
                 continue;
             }
             if mod_info.satisfies_requirement(&state.mod_requirements) {
@@ -314,6 +399,11 @@ impl Scene for TitleScene {
         self.challenges_menu.y =
             ((state.canvas_size.1 + 30.0 - self.challenges_menu.height as f32) / 2.0).floor() as isize;
 
+        self.replay_menu.update_width(state);
+        self.replay_menu.update_height(state);
+        self.replay_menu.x = ((state.canvas_size.0 - self.replay_menu.width as f32) / 2.0).floor() as isize;
+        self.replay_menu.y = ((state.canvas_size.1 + 30.0 - self.replay_menu.height as f32) / 2.0).floor() as isize;
+
         if self.controller.trigger_left()
             && self.compact_jukebox.is_shown()
             && self.current_menu == CurrentMenu::MainMenu
@@ -339,6 +429,10 @@ impl Scene for TitleScene {
                 MenuSelectionResult::Selected(MainMenuEntry::Challenges, _) => {
                     self.current_menu = CurrentMenu::ChallengesMenu;
                 }
+                MenuSelectionResult::Selected(MainMenuEntry::Replays, _) => {
+                    self.update_replay_menu(state, ctx);
+                    self.current_menu = CurrentMenu::ReplayMenu;
+                }
                 MenuSelectionResult::Selected(MainMenuEntry::Options, _) => {
                     self.current_menu = CurrentMenu::OptionMenu;
                 }
@@ -445,10 +539,25 @@ impl Scene for TitleScene {
                     self.current_menu = CurrentMenu::PlayerCountMenu;
                 }
                 MenuSelectionResult::Selected(ConfirmMenuEntry::Replay(kind), _) => {
-                    state.difficulty = GameDifficulty::Normal;
-                    state.replay_state = ReplayState::Playback(kind);
-                    state.reload_resources(ctx)?;
-                    state.start_new_game(ctx)?;
+                    let result = Replay::read_header(state, ctx, kind).and_then(|header| {
+                        state.set_replay_mods(&header);
+                        state.reload_resources(ctx)?;
+                        header.check_compatibility(state)
+                    });
+
+                    match result {
+                        Ok(()) => {
+                            state.difficulty = GameDifficulty::Normal;
+                            state.replay_state = ReplayState::Playback(kind);
+                            state.start_new_game(ctx)?;
+                        }
+                        Err(e) => {
+                            log::warn!("Cannot play back the replay: {}", e);
+
+                            state.replay_mod_paths = None;
+                            state.reload_resources(ctx)?;
+                        }
+                    }
                 }
                 MenuSelectionResult::Selected(ConfirmMenuEntry::DeleteReplay, _) => {
                     state.delete_replay_data(ctx, ReplayKind::Best)?;
@@ -459,6 +568,22 @@ impl Scene for TitleScene {
                 }
                 _ => (),
             },
+            CurrentMenu::ReplayMenu => match self.replay_menu.tick(&mut self.controller, state) {
+                MenuSelectionResult::Selected(ReplayMenuEntry::Replay(idx), _) => {
+                    if let Err(e) = self.play_session_replay(state, ctx, idx) {
+                        log::warn!("Cannot play back the replay: {}", e);
+
+                        if state.mod_path.is_some() || state.replay_mod_paths.is_some() {
+                            state.clear_replay_mods();
+                            state.reload_resources(ctx)?;
+                        }
+                    }
+                }
+                MenuSelectionResult::Selected(ReplayMenuEntry::Back, _) | MenuSelectionResult::Canceled => {
+                    self.current_menu = CurrentMenu::MainMenu;
+                }
+                _ => (),
+            },
             CurrentMenu::PlayerCountMenu => {
                 let cm = &mut self.current_menu;
                 let rm = CurrentMenu::ChallengeConfirmMenu;
@@ -509,6 +634,7 @@ impl Scene for TitleScene {
                 CurrentMenu::OptionMenu => state.loc.t("menus.main_menu.options"),
                 CurrentMenu::MainMenu => unreachable!(),
                 CurrentMenu::PlayerCountMenu => state.loc.t("menus.main_menu.start"),
+                CurrentMenu::ReplayMenu => state.loc.t("menus.main_menu.replays"),
             };
             state
                 .font
@@ -540,6 +666,7 @@ impl Scene for TitleScene {
             CurrentMenu::OptionMenu => self.settings_menu.draw(state, ctx)?,
             CurrentMenu::SaveSelectMenu => self.save_select_menu.draw(state, ctx)?,
             CurrentMenu::PlayerCountMenu => self.coop_menu.draw(state, ctx)?,
+            CurrentMenu::ReplayMenu => self.replay_menu.draw(state, ctx)?,
         }
 
         Ok(())
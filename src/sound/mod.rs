@@ -185,6 +185,11 @@ impl SoundManager {
         }
     }
 
+    /// Enables or disables all playback, returns the previous setting so it can be restored.
+    pub fn set_muted(&mut self, muted: bool) -> bool {
+        std::mem::replace(&mut self.no_audio, muted)
+    }
+
     pub fn play_sfx(&mut self, id: u8) {
         if self.no_audio {
             return;